@@ -0,0 +1,807 @@
+//! SNMP agent (command responder) serving registered MIB handlers.
+
+use std::cmp;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+use std::net::UdpSocket;
+use std::time::Instant;
+
+use asn1_exp::to_asn1;
+
+use client::MAX_DATAGRAM_SIZE;
+use message::Message;
+use oids;
+use security::{LocalEngine, PrivError, UserTable};
+use security::usm::UsmProcessor;
+use types::*;
+
+mod tree;
+
+pub use self::tree::MibTree;
+
+/// Shortest possible encoding of a binding, used to bound GetBulk responses.
+const MIN_BIND_LEN: usize = 7;
+
+/// Instrumentation of a MIB subtree.
+pub trait MibHandler {
+    /// Value of instance `oid`, or `NoSuchObject`/`NoSuchInstance` if it does not exist.
+    fn get(&mut self, oid: &ObjectIdentifier) -> Variable;
+
+    /// First instance of the subtree lexicographically greater than `oid`.
+    fn get_next(&mut self, oid: &ObjectIdentifier) -> Option<VarBind>;
+
+    /// First phase of Set: validates `value` for instance `oid` and reserves whatever the commit
+    /// needs. Called for every binding of the request before any of them is committed.
+    fn test(&mut self, _oid: &ObjectIdentifier, _value: &Variable) -> Result<(), ErrorStatus> {
+        Err(ErrorStatus::NotWritable)
+    }
+
+    /// Second phase of Set: applies a tested value. A failure undoes the already committed bindings.
+    fn commit(&mut self, _oid: &ObjectIdentifier, _value: &Variable) -> Result<(), ()> {
+        Err(())
+    }
+
+    /// Reverts a committed value after a later binding of the same request failed to commit.
+    fn undo(&mut self, _oid: &ObjectIdentifier, _value: &Variable) -> Result<(), ()> {
+        Err(())
+    }
+
+    /// Releases resources reserved by a successful `test`, whatever the outcome of the request.
+    fn cleanup(&mut self, _oid: &ObjectIdentifier, _value: &Variable) {}
+}
+
+/// Access granted to a community or USM user.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// Handler registration conflicts with the already registered subtree.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct OverlappingSubtree(pub ObjectIdentifier);
+
+impl Display for OverlappingSubtree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "subtree overlaps with registered {}", self.0)
+    }
+}
+
+impl Error for OverlappingSubtree {
+    fn description(&self) -> &str {
+        "overlapping subtree"
+    }
+}
+
+/// Agent dispatching requests of all versions to handlers registered for non-overlapping subtrees.
+///
+/// Communities are checked for v1/v2c, USM users of the local engine for v3.
+/// v3 requests are only served in the default context.
+pub struct Agent {
+    handlers: Vec<(Vec<u64>, Box<MibHandler>)>,
+    communities: Vec<(Community, Access)>,
+    user_access: Vec<(UserName, Access)>,
+    usm: UsmProcessor,
+    max_size: u32,
+}
+
+impl fmt::Debug for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let subtrees: Vec<&[u64]> = self.handlers.iter().map(|&(ref subtree, _)| &subtree[..]).collect();
+        f.debug_struct("Agent")
+            .field("subtrees", &subtrees)
+            .field("communities", &self.communities)
+            .field("user_access", &self.user_access)
+            .field("usm", &self.usm)
+            .field("max_size", &self.max_size)
+            .finish()
+    }
+}
+
+impl Agent {
+    pub fn new(engine: LocalEngine) -> Result<Self, PrivError> {
+        Ok(Agent {
+            handlers: Vec::new(),
+            communities: Vec::new(),
+            user_access: Vec::new(),
+            usm: UsmProcessor::new(engine, UserTable::new(), MAX_DATAGRAM_SIZE as u32)?,
+            max_size: MAX_DATAGRAM_SIZE as u32,
+        })
+    }
+
+    pub fn engine(&self) -> &LocalEngine {
+        self.usm.engine()
+    }
+
+    pub fn users(&self) -> &UserTable {
+        self.usm.users()
+    }
+
+    pub fn users_mut(&mut self) -> &mut UserTable {
+        self.usm.users_mut()
+    }
+
+    pub fn add_community(&mut self, community: Community, access: Access) {
+        self.communities.retain(|&(ref c, _)| *c != community);
+        self.communities.push((community, access));
+    }
+
+    /// Access of authenticated requests of v3 user `user`, read-write unless set otherwise.
+    /// noAuthNoPriv requests are always read-only.
+    pub fn set_user_access(&mut self, user: UserName, access: Access) {
+        self.user_access.retain(|&(ref u, _)| *u != user);
+        self.user_access.push((user, access));
+    }
+
+    /// Largest response message the agent sends; v3 requests may lower it with msgMaxSize.
+    pub fn max_size(&self) -> u32 {
+        self.max_size
+    }
+
+    pub fn set_max_size(&mut self, value: u32) {
+        self.max_size = value
+    }
+
+    pub fn register<H: MibHandler + 'static>(
+        &mut self,
+        subtree: &ObjectIdentifier,
+        handler: H,
+    ) -> Result<(), OverlappingSubtree> {
+        let subtree = oids::components(subtree).to_vec();
+        if let Some(&(ref registered, _)) = self.handlers
+            .iter()
+            .find(|&&(ref registered, _)| registered.starts_with(&subtree) || subtree.starts_with(registered))
+        {
+            return Err(OverlappingSubtree(oids::oid(registered)));
+        }
+
+        let position = self.handlers
+            .iter()
+            .position(|&(ref registered, _)| *registered > subtree)
+            .unwrap_or(self.handlers.len());
+        self.handlers.insert(position, (subtree, Box::new(handler)));
+        Ok(())
+    }
+
+    pub fn unregister(&mut self, subtree: &ObjectIdentifier) -> Option<Box<MibHandler>> {
+        let position = self.handlers
+            .iter()
+            .position(|&(ref registered, _)| &registered[..] == oids::components(subtree))?;
+        Some(self.handlers.remove(position).1)
+    }
+
+    /// Serves requests received on `socket` until an I/O error occurs.
+    pub fn serve(&mut self, socket: &UdpSocket) -> io::Result<()> {
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let (len, from) = socket.recv_from(&mut buf)?;
+            if let Some(response) = self.process(&buf[..len], Instant::now()) {
+                socket.send_to(&response, from)?;
+            }
+        }
+    }
+
+    /// Processes a received message, returning the response or Report to send back.
+    /// Malformed, unauthorized and non-request messages are silently dropped.
+    pub fn process(&mut self, message: &[u8], now: Instant) -> Option<Vec<u8>> {
+        let packet = match Message::decode(message).ok()? {
+            Message::Version1(packet) | Message::Version2(packet) => return self.process_community(packet),
+            Message::Version3(packet) => packet,
+        };
+        let accepted = match self.usm.accept(message, packet, false, now) {
+            Ok(accepted) => accepted,
+            Err(report) => return report,
+        };
+
+        if !accepted.scoped.context_name().as_bytes().is_empty() {
+            return None;
+        }
+
+        let access = if accepted.flags.is_authenticated() {
+            self.user_access
+                .iter()
+                .find(|&&(ref user, _)| user == accepted.params.user_name())
+                .map(|&(_, access)| access)
+                .unwrap_or(Access::ReadWrite)
+        } else {
+            Access::ReadOnly
+        };
+        let max_size = cmp::min(self.max_size, accepted.max_size) as usize;
+        let request = accepted.scoped.pdu().clone();
+        let response = self.dispatch(Version::Version3, access, &request, max_size)?;
+        let usm = &mut self.usm;
+        fit(Version::Version3, &request, response, max_size, |pdu| {
+            usm.respond(&accepted, pdu, now)
+        })
+    }
+
+    fn process_community(&mut self, packet: PacketV2) -> Option<Vec<u8>> {
+        let version = packet.version();
+        let access = self.communities
+            .iter()
+            .find(|&&(ref community, _)| community == packet.community())
+            .map(|&(_, access)| access)?;
+
+        let community = packet.community().clone();
+        let request = packet.into_pdu();
+        let max_size = self.max_size as usize;
+        let response = self.dispatch(version, access, &request, max_size)?;
+        fit(version, &request, response, max_size, |pdu| {
+            to_asn1(&PacketV2::new(version, community.clone(), pdu)).ok()
+        })
+    }
+
+    fn dispatch(&mut self, version: Version, access: Access, request: &Pdu, max_size: usize) -> Option<InnerPdu> {
+        match *request {
+            Pdu::GetRequest(ref pdu) => Some(self.get(version, pdu)),
+            Pdu::GetNextRequest(ref pdu) => Some(self.get_next(version, pdu)),
+            Pdu::GetBulkRequest(ref pdu) if version != Version::Version1 => Some(self.get_bulk(pdu, max_size)),
+            Pdu::SetRequest(ref pdu) => Some(self.set(version, access, pdu)),
+            _ => None,
+        }
+    }
+
+    fn get(&mut self, version: Version, request: &InnerPdu) -> InnerPdu {
+        let mut binds = Vec::with_capacity(request.binds().len());
+        for (i, bind) in request.binds().iter().enumerate() {
+            let value = match self.handler_index(bind.oid()) {
+                Some(index) => self.handlers[index].1.get(bind.oid()),
+                None => Variable::NoSuchObject,
+            };
+            if version == Version::Version1 && !is_v1_value(&value) {
+                return error_response(request, ErrorStatus::NoSuchName, i + 1);
+            }
+            binds.push(VarBind::new(bind.oid().clone(), value));
+        }
+        InnerPdu::new(request.request_id(), ErrorStatus::NoError, 0, binds)
+    }
+
+    fn get_next(&mut self, version: Version, request: &InnerPdu) -> InnerPdu {
+        let mut binds = Vec::with_capacity(request.binds().len());
+        for (i, bind) in request.binds().iter().enumerate() {
+            match self.next(version, bind.oid()) {
+                Some(next) => binds.push(next),
+                None if version == Version::Version1 => return error_response(request, ErrorStatus::NoSuchName, i + 1),
+                None => binds.push(VarBind::new(bind.oid().clone(), Variable::EndOfMibView)),
+            }
+        }
+        InnerPdu::new(request.request_id(), ErrorStatus::NoError, 0, binds)
+    }
+
+    /// RFC 3416 section 4.2.3; repetitions stop early once all columns reached the end of the MIB
+    /// view or the response could not fit into `max_size` anyway.
+    fn get_bulk(&mut self, request: &BulkPdu, max_size: usize) -> InnerPdu {
+        let requested = request.binds();
+        let non_repeaters = cmp::min(request.non_repeaters() as usize, requested.len());
+        let limit = max_size / MIN_BIND_LEN;
+
+        let mut binds = Vec::new();
+        for bind in &requested[..non_repeaters] {
+            binds.push(self.next_or_end(bind.oid()));
+        }
+
+        let mut last: Vec<ObjectIdentifier> = requested[non_repeaters..].iter().map(|b| b.oid().clone()).collect();
+        if !last.is_empty() {
+            for _ in 0..request.max_repetitions() {
+                let mut finished = true;
+                for oid in &mut last {
+                    let next = self.next_or_end(oid);
+                    if *next.value() != Variable::EndOfMibView {
+                        finished = false;
+                    }
+                    *oid = next.oid().clone();
+                    binds.push(next);
+                }
+                if finished || binds.len() >= limit {
+                    break;
+                }
+            }
+        }
+
+        InnerPdu::new(request.request_id(), ErrorStatus::NoError, 0, binds)
+    }
+
+    /// Two-phase Set (RFC 3416 section 4.2.5): all bindings are tested, then committed in order.
+    /// A failed commit undoes the committed bindings in reverse order and reports commitFailed,
+    /// or undoFailed with error index zero if any of them could not be undone.
+    fn set(&mut self, version: Version, access: Access, request: &InnerPdu) -> InnerPdu {
+        if access == Access::ReadOnly {
+            return set_error_response(version, request, ErrorStatus::NoAccess, 1);
+        }
+
+        let binds = request.binds();
+        let mut tested = Vec::with_capacity(binds.len());
+        let mut result = Ok(());
+        for (i, bind) in binds.iter().enumerate() {
+            let index = match self.handler_index(bind.oid()) {
+                Some(index) => index,
+                None => {
+                    result = Err((ErrorStatus::NoCreation, i + 1));
+                    break;
+                }
+            };
+            if let Err(status) = self.handlers[index].1.test(bind.oid(), bind.value()) {
+                result = Err((status, i + 1));
+                break;
+            }
+            tested.push(index);
+        }
+
+        if result.is_ok() {
+            result = self.commit(binds, &tested);
+        }
+        for (bind, &index) in binds.iter().zip(&tested) {
+            self.handlers[index].1.cleanup(bind.oid(), bind.value());
+        }
+
+        match result {
+            Ok(()) => InnerPdu::new(request.request_id(), ErrorStatus::NoError, 0, binds.clone()),
+            Err((status, index)) => set_error_response(version, request, status, index),
+        }
+    }
+
+    fn commit(&mut self, binds: &[VarBind], handlers: &[usize]) -> Result<(), (ErrorStatus, usize)> {
+        for (i, (bind, &index)) in binds.iter().zip(handlers).enumerate() {
+            if self.handlers[index].1.commit(bind.oid(), bind.value()).is_ok() {
+                continue;
+            }
+
+            let mut undone = true;
+            for (bind, &index) in binds[..i].iter().zip(&handlers[..i]).rev() {
+                undone &= self.handlers[index].1.undo(bind.oid(), bind.value()).is_ok();
+            }
+            return Err(if undone { (ErrorStatus::CommitFailed, i + 1) } else { (ErrorStatus::UndoFailed, 0) });
+        }
+        Ok(())
+    }
+
+    fn handler_index(&self, oid: &ObjectIdentifier) -> Option<usize> {
+        let oid = oids::components(oid);
+        self.handlers.iter().position(|&(ref subtree, _)| oid.starts_with(subtree))
+    }
+
+    fn next_or_end(&mut self, oid: &ObjectIdentifier) -> VarBind {
+        self.next(Version::Version2, oid)
+            .unwrap_or_else(|| VarBind::new(oid.clone(), Variable::EndOfMibView))
+    }
+
+    /// Next instance after `oid` over all handlers; v1 skips values it cannot represent.
+    fn next(&mut self, version: Version, oid: &ObjectIdentifier) -> Option<VarBind> {
+        let start = oids::components(oid).to_vec();
+        for &mut (ref subtree, ref mut handler) in &mut self.handlers {
+            if *subtree < start && !start.starts_with(subtree) {
+                continue;
+            }
+
+            let mut previous = oid.clone();
+            while let Some(next) = handler.get_next(&previous) {
+                let components = oids::components(next.oid());
+                // Guard against handlers going backwards or leaving their subtree.
+                if components <= oids::components(&previous) || !components.starts_with(subtree) {
+                    break;
+                }
+                if version != Version::Version1 || is_v1_value(next.value()) {
+                    return Some(next);
+                }
+                previous = next.oid().clone();
+            }
+        }
+        None
+    }
+}
+
+/// Encodes the response, applying RFC 3416 message size rules: GetBulk responses are truncated,
+/// others are replaced by tooBig. Nothing is sent if even that does not fit.
+fn fit<F>(version: Version, request: &Pdu, mut response: InnerPdu, max_size: usize, mut encode: F) -> Option<Vec<u8>>
+where
+    F: FnMut(Pdu) -> Option<Vec<u8>>,
+{
+    let message = encode(Pdu::GetResponse(response.clone()))?;
+    if message.len() <= max_size {
+        return Some(message);
+    }
+
+    if let Pdu::GetBulkRequest(_) = *request {
+        let mut excess = message.len() - max_size;
+        while excess > 0 {
+            let bind = response.binds_mut().pop()?;
+            excess = excess.saturating_sub(to_asn1(&bind).map(|b| b.len()).unwrap_or(1));
+        }
+        loop {
+            let message = encode(Pdu::GetResponse(response.clone()))?;
+            if message.len() <= max_size {
+                return Some(message);
+            }
+            response.binds_mut().pop()?;
+        }
+    }
+
+    // v1 echoes the request bindings, v2 sends none.
+    let binds = match *request {
+        Pdu::GetRequest(ref pdu) | Pdu::GetNextRequest(ref pdu) | Pdu::SetRequest(ref pdu)
+            if version == Version::Version1 => pdu.binds().clone(),
+        _ => Vec::new(),
+    };
+    let message = encode(Pdu::GetResponse(InnerPdu::new(response.request_id(), ErrorStatus::TooBig, 0, binds)))?;
+    if message.len() <= max_size {
+        Some(message)
+    } else {
+        None
+    }
+}
+
+fn error_response(request: &InnerPdu, status: ErrorStatus, index: usize) -> InnerPdu {
+    InnerPdu::new(request.request_id(), status, index as u32, request.binds().clone())
+}
+
+fn set_error_response(version: Version, request: &InnerPdu, status: ErrorStatus, index: usize) -> InnerPdu {
+    let status = if version == Version::Version1 {
+        status.to_v1()
+    } else {
+        status
+    };
+    error_response(request, status, index)
+}
+
+/// Values SNMPv1 can carry (RFC 3584 section 4.2.2.1).
+fn is_v1_value(value: &Variable) -> bool {
+    match *value {
+        Variable::NoSuchObject | Variable::NoSuchInstance | Variable::EndOfMibView | Variable::Counter64(_) => false,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use asn1_exp::from_asn1;
+
+    use super::*;
+    use client::SyncSession;
+    use security::{testing, AuthProtocol, PrivProtocol, UsmUser};
+
+    /// Scalar and table objects kept in a map; `writable` objects accept Integer values.
+    ///
+    /// Set of -1 fails to commit, undo of -2 fails and -3 is an inconsistent value.
+    struct Objects {
+        values: BTreeMap<Vec<u64>, Variable>,
+        writable: bool,
+        undo: Vec<(Vec<u64>, Option<Variable>)>,
+        cleanups: Rc<Cell<usize>>,
+    }
+
+    impl Objects {
+        fn new(values: BTreeMap<Vec<u64>, Variable>, writable: bool) -> Self {
+            Objects {
+                values: values,
+                writable: writable,
+                undo: Vec::new(),
+                cleanups: Rc::new(Cell::new(0)),
+            }
+        }
+    }
+
+    impl MibHandler for Objects {
+        fn get(&mut self, oid: &ObjectIdentifier) -> Variable {
+            let oid = oids::components(oid);
+            match self.values.get(oid) {
+                Some(value) => value.clone(),
+                None if self.values.keys().any(|k| oid.starts_with(&k[..k.len() - 1])) => Variable::NoSuchInstance,
+                None => Variable::NoSuchObject,
+            }
+        }
+
+        fn get_next(&mut self, oid: &ObjectIdentifier) -> Option<VarBind> {
+            let oid = oids::components(oid);
+            self.values
+                .iter()
+                .find(|&(k, _)| &k[..] > oid)
+                .map(|(k, v)| VarBind::new(oids::oid(k), v.clone()))
+        }
+
+        fn test(&mut self, _oid: &ObjectIdentifier, value: &Variable) -> Result<(), ErrorStatus> {
+            if !self.writable {
+                return Err(ErrorStatus::NotWritable);
+            }
+            match *value {
+                Variable::Integer(-3) => Err(ErrorStatus::InconsistentValue),
+                Variable::Integer(_) => Ok(()),
+                _ => Err(ErrorStatus::WrongType),
+            }
+        }
+
+        fn commit(&mut self, oid: &ObjectIdentifier, value: &Variable) -> Result<(), ()> {
+            if *value == Variable::Integer(-1) {
+                return Err(());
+            }
+            let oid = oids::components(oid).to_vec();
+            let previous = self.values.insert(oid.clone(), value.clone());
+            self.undo.push((oid, previous));
+            Ok(())
+        }
+
+        fn undo(&mut self, _oid: &ObjectIdentifier, value: &Variable) -> Result<(), ()> {
+            let (oid, previous) = self.undo.pop().ok_or(())?;
+            if *value == Variable::Integer(-2) {
+                return Err(());
+            }
+            match previous {
+                Some(previous) => self.values.insert(oid, previous),
+                None => self.values.remove(&oid),
+            };
+            Ok(())
+        }
+
+        fn cleanup(&mut self, _oid: &ObjectIdentifier, _value: &Variable) {
+            self.undo.clear();
+            self.cleanups.set(self.cleanups.get() + 1);
+        }
+    }
+
+    const SYSTEM: &[u64] = &[1, 3, 6, 1, 2, 1, 1];
+    const IF_TABLE: &[u64] = &[1, 3, 6, 1, 2, 1, 2];
+
+    fn oid(components: &[u64], suffix: &[u64]) -> ObjectIdentifier {
+        let mut oid = components.to_vec();
+        oid.extend_from_slice(suffix);
+        oids::oid(&oid)
+    }
+
+    fn agent() -> Agent {
+        let mut agent = Agent::new(LocalEngine::new(EngineID::new(b"agent".to_vec()), 1)).unwrap();
+        agent.add_community(Community::new("public"), Access::ReadOnly);
+        agent.add_community(Community::new("private"), Access::ReadWrite);
+
+        let mut system = BTreeMap::new();
+        system.insert(oids::components(&oid(SYSTEM, &[3, 0])).to_vec(), Variable::TimeTicks(42));
+        system.insert(oids::components(&oid(SYSTEM, &[5, 0])).to_vec(), Variable::Integer(1));
+        agent.register(&oids::oid(SYSTEM), Objects::new(system, true)).unwrap();
+
+        let mut interfaces = BTreeMap::new();
+        interfaces.insert(oids::components(&oid(IF_TABLE, &[2, 1, 10, 1])).to_vec(), Variable::Counter64(1 << 40));
+        interfaces.insert(oids::components(&oid(IF_TABLE, &[2, 1, 16, 1])).to_vec(), Variable::Counter(7));
+        agent.register(&oids::oid(IF_TABLE), Objects::new(interfaces, false)).unwrap();
+        agent
+    }
+
+    fn request(agent: &mut Agent, version: Version, community: &str, pdu: Pdu) -> Option<InnerPdu> {
+        let message = to_asn1(&PacketV2::new(version, Community::new(community), pdu)).unwrap();
+        let response: PacketV2 = from_asn1(&agent.process(&message, Instant::now())?).unwrap();
+        match response.into_pdu() {
+            Pdu::GetResponse(pdu) => Some(pdu),
+            pdu => panic!("unexpected PDU: {:?}", pdu),
+        }
+    }
+
+    /// v3 request of `user` at its security level in context `context`.
+    fn v3_request(agent: &mut Agent, user: &UsmUser, context: &str, pdu: Pdu) -> Option<InnerPdu> {
+        let flags = MessageFlags::new(true, user.security_level());
+        let mut packet = testing::packet(1, flags, b"agent", 1, 0, "", Pdu::new_empty_request(RequestType::Get));
+        packet.security_parameters_mut().set_user_name(user.name().clone());
+        let scoped = ScopedPdu::new(ContextEngineID::new(b"agent".to_vec()), ContextName::new(context), pdu);
+        packet.set_pdu(PduV3::Scoped(scoped));
+        let message = match user.security_level() {
+            SecurityLevel::NoAuthNoPriv => to_asn1(&packet).unwrap(),
+            _ => testing::protect(packet, user),
+        };
+
+        let response: PacketV3 = from_asn1(&agent.process(&message, Instant::now())?).unwrap();
+        match response.into_pdu() {
+            PduV3::Scoped(scoped) => match scoped.into_pdu() {
+                Pdu::GetResponse(pdu) => Some(pdu),
+                pdu => panic!("unexpected PDU: {:?}", pdu),
+            },
+            PduV3::Crypted(_) => panic!("response must not be encrypted"),
+        }
+    }
+
+    fn nulls(oids: Vec<ObjectIdentifier>) -> VarBindList {
+        oids.into_iter().map(VarBind::new_null).collect()
+    }
+
+    #[test]
+    fn register_overlapping() {
+        let mut agent = agent();
+        let handler = Objects::new(BTreeMap::new(), false);
+        assert_eq!(
+            agent.register(&oid(SYSTEM, &[1]), handler),
+            Err(OverlappingSubtree(oids::oid(SYSTEM)))
+        );
+    }
+
+    #[test]
+    fn get_exceptions() {
+        let mut agent = agent();
+        let binds = nulls(vec![oid(SYSTEM, &[3, 0]), oid(SYSTEM, &[3, 1]), oid(&[1, 3, 6, 1, 4], &[])]);
+        let response = request(&mut agent, Version::Version2, "public", Pdu::GetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds.clone()))).unwrap();
+        let values: Vec<&Variable> = response.binds().iter().map(|b| b.value()).collect();
+        assert_eq!(values, vec![&Variable::TimeTicks(42), &Variable::NoSuchInstance, &Variable::NoSuchObject]);
+
+        let response = request(&mut agent, Version::Version1, "public", Pdu::GetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds.clone()))).unwrap();
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::NoSuchName, 2));
+        assert_eq!(response.binds(), &binds);
+
+        assert!(request(&mut agent, Version::Version2, "wrong", Pdu::GetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds))).is_none());
+    }
+
+    #[test]
+    fn get_next() {
+        let mut agent = agent();
+        let binds = nulls(vec![oid(SYSTEM, &[5, 0]), oid(IF_TABLE, &[2, 1, 16, 1])]);
+
+        let response = request(&mut agent, Version::Version2, "public", Pdu::GetNextRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds.clone()))).unwrap();
+        assert_eq!(
+            response.binds(),
+            &vec![
+                VarBind::new(oid(IF_TABLE, &[2, 1, 10, 1]), Variable::Counter64(1 << 40)),
+                VarBind::new(oid(IF_TABLE, &[2, 1, 16, 1]), Variable::EndOfMibView),
+            ]
+        );
+
+        // Counter64 is skipped for v1, end of the MIB view is noSuchName.
+        let response = request(&mut agent, Version::Version1, "public", Pdu::GetNextRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds[..1].to_vec()))).unwrap();
+        assert_eq!(response.binds(), &vec![VarBind::new(oid(IF_TABLE, &[2, 1, 16, 1]), Variable::Counter(7))]);
+        let response = request(&mut agent, Version::Version1, "public", Pdu::GetNextRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds))).unwrap();
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::NoSuchName, 2));
+    }
+
+    #[test]
+    fn get_bulk() {
+        let mut agent = agent();
+        let pdu = Pdu::new_bulk_request(1, 1, 10, nulls(vec![oid(SYSTEM, &[]), oid(SYSTEM, &[3, 0])]));
+        let response = request(&mut agent, Version::Version2, "public", pdu.clone()).unwrap();
+        assert_eq!(
+            response.binds(),
+            &vec![
+                VarBind::new(oid(SYSTEM, &[3, 0]), Variable::TimeTicks(42)),
+                VarBind::new(oid(SYSTEM, &[5, 0]), Variable::Integer(1)),
+                VarBind::new(oid(IF_TABLE, &[2, 1, 10, 1]), Variable::Counter64(1 << 40)),
+                VarBind::new(oid(IF_TABLE, &[2, 1, 16, 1]), Variable::Counter(7)),
+                VarBind::new(oid(IF_TABLE, &[2, 1, 16, 1]), Variable::EndOfMibView),
+            ]
+        );
+
+        // Truncated to fit the maximum message size.
+        let full = to_asn1(&PacketV2::new(Version::Version2, Community::new("public"), Pdu::GetResponse(response))).unwrap();
+        agent.set_max_size(full.len() as u32 - 1);
+        let response = request(&mut agent, Version::Version2, "public", pdu).unwrap();
+        assert_eq!(response.binds().len(), 4);
+        assert_eq!(response.error_status(), ErrorStatus::NoError);
+    }
+
+    #[test]
+    fn too_big() {
+        let mut agent = agent();
+        agent.set_max_size(40);
+        let binds = nulls(vec![oid(SYSTEM, &[3, 0]), oid(SYSTEM, &[5, 0])]);
+        let response = request(&mut agent, Version::Version2, "public", Pdu::GetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds))).unwrap();
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::TooBig, 0));
+        assert!(response.binds().is_empty());
+    }
+
+    #[test]
+    fn set() {
+        let mut agent = agent();
+        let binds = vec![VarBind::new(oid(SYSTEM, &[5, 0]), Variable::Integer(2))];
+        let pdu = Pdu::SetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds.clone()));
+
+        let response = request(&mut agent, Version::Version2, "public", pdu.clone()).unwrap();
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::NoAccess, 1));
+
+        let response = request(&mut agent, Version::Version2, "private", pdu).unwrap();
+        assert_eq!(response.error_status(), ErrorStatus::NoError);
+        let response = request(&mut agent, Version::Version2, "public", Pdu::GetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds.clone()))).unwrap();
+        assert_eq!(response.binds(), &binds);
+
+        let binds = vec![VarBind::new(oid(IF_TABLE, &[2, 1, 16, 1]), Variable::Integer(2))];
+        let pdu = Pdu::SetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds));
+        let response = request(&mut agent, Version::Version2, "private", pdu.clone()).unwrap();
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::NotWritable, 1));
+        let response = request(&mut agent, Version::Version1, "private", pdu).unwrap();
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::NoSuchName, 1));
+    }
+
+    #[test]
+    fn v3_access() {
+        let guest = UsmUser::new("guest");
+        let operator = UsmUser::new("operator").with_auth(AuthProtocol::Sha1, b"authpassword");
+        let mut agent = agent();
+        agent.users_mut().insert(guest.clone());
+        agent.users_mut().insert(operator.clone());
+
+        let binds = vec![VarBind::new(oid(SYSTEM, &[5, 0]), Variable::Integer(2))];
+        let pdu = Pdu::SetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds));
+
+        let response = v3_request(&mut agent, &guest, "", pdu.clone()).unwrap();
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::NoAccess, 1));
+        let get = Pdu::GetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, nulls(vec![oid(SYSTEM, &[5, 0])])));
+        let response = v3_request(&mut agent, &guest, "", get).unwrap();
+        assert_eq!(*response.binds()[0].value(), Variable::Integer(1));
+
+        let response = v3_request(&mut agent, &operator, "", pdu.clone()).unwrap();
+        assert_eq!(response.error_status(), ErrorStatus::NoError);
+        assert!(v3_request(&mut agent, &operator, "other", pdu.clone()).is_none());
+
+        agent.set_user_access(UserName::new("operator"), Access::ReadOnly);
+        let response = v3_request(&mut agent, &operator, "", pdu).unwrap();
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::NoAccess, 1));
+    }
+
+    fn set_request(agent: &mut Agent, version: Version, binds: &[(u64, i32)]) -> InnerPdu {
+        let binds = binds
+            .iter()
+            .map(|&(column, value)| VarBind::new(oid(SYSTEM, &[column, 0]), Variable::Integer(value)))
+            .collect();
+        request(agent, version, "private", Pdu::SetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds))).unwrap()
+    }
+
+    fn system_value(agent: &mut Agent, column: u64) -> Variable {
+        let binds = nulls(vec![oid(SYSTEM, &[column, 0])]);
+        let response = request(agent, Version::Version2, "public", Pdu::GetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds))).unwrap();
+        response.binds()[0].value().clone()
+    }
+
+    #[test]
+    fn set_two_phase() {
+        let mut agent = agent();
+        let mut values = BTreeMap::new();
+        values.insert(oids::components(&oid(SYSTEM, &[5, 0])).to_vec(), Variable::Integer(1));
+        let handler = Objects::new(values, true);
+        let cleanups = handler.cleanups.clone();
+        agent.unregister(&oids::oid(SYSTEM)).unwrap();
+        agent.register(&oids::oid(SYSTEM), handler).unwrap();
+
+        // Inconsistent third binding: nothing is committed, the tested ones are cleaned up.
+        let response = set_request(&mut agent, Version::Version2, &[(5, 2), (6, 3), (7, -3)]);
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::InconsistentValue, 3));
+        assert_eq!(system_value(&mut agent, 5), Variable::Integer(1));
+        assert_eq!(cleanups.get(), 2);
+
+        // Failed commit of the second binding undoes the first one.
+        let response = set_request(&mut agent, Version::Version2, &[(5, 2), (6, -1)]);
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::CommitFailed, 2));
+        assert_eq!(system_value(&mut agent, 5), Variable::Integer(1));
+        assert_eq!(system_value(&mut agent, 6), Variable::NoSuchObject);
+
+        let response = set_request(&mut agent, Version::Version2, &[(5, -2), (6, -1)]);
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::UndoFailed, 0));
+        let response = set_request(&mut agent, Version::Version1, &[(5, 2), (6, -1)]);
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::GenErr, 2));
+
+        let response = set_request(&mut agent, Version::Version2, &[(5, 2), (6, 3)]);
+        assert_eq!(response.error_status(), ErrorStatus::NoError);
+        assert_eq!(system_value(&mut agent, 6), Variable::Integer(3));
+
+        let binds = vec![VarBind::new(oids::oid(&[1, 3, 6, 1, 4, 1, 1, 0]), Variable::Integer(1))];
+        let response = request(&mut agent, Version::Version2, "private", Pdu::SetRequest(InnerPdu::new(1, ErrorStatus::NoError, 0, binds))).unwrap();
+        assert_eq!((response.error_status(), response.error_index()), (ErrorStatus::NoCreation, 1));
+    }
+
+    #[test]
+    fn v3_session() {
+        let user = UsmUser::new("operator")
+            .with_auth(AuthProtocol::Sha1, b"authpassword")
+            .with_privacy(PrivProtocol::Des, b"privpassword");
+
+        let (tx, rx) = mpsc::channel();
+        let agent_user = user.clone();
+        thread::spawn(move || {
+            let mut agent = agent();
+            agent.users_mut().insert(agent_user);
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            tx.send(socket.local_addr().unwrap()).unwrap();
+            agent.serve(&socket).unwrap();
+        });
+
+        let mut session = SyncSession::new_v3(rx.recv().unwrap(), user).unwrap();
+        let binds = session.get(&[oid(SYSTEM, &[3, 0])]).unwrap();
+        assert_eq!(binds, vec![VarBind::new(oid(SYSTEM, &[3, 0]), Variable::TimeTicks(42))]);
+        assert_eq!(session.engine().unwrap().engine_id(), Some(&EngineID::new(b"agent".to_vec())));
+    }
+}
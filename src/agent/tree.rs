@@ -0,0 +1,191 @@
+//! In-memory OID-keyed ordered map.
+
+use std::collections::BTreeMap;
+use std::collections::Bound::{Excluded, Included, Unbounded};
+
+use agent::MibHandler;
+use oids;
+use types::*;
+
+/// Values keyed by OID in SNMP (component-wise lexicographic) order,
+/// so that `1.3.6.1.2` < `1.3.6.1.2.1` < `1.3.6.1.10`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MibTree<V> {
+    entries: BTreeMap<Vec<u64>, V>,
+}
+
+impl<V> Default for MibTree<V> {
+    fn default() -> Self {
+        MibTree {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<V> MibTree<V> {
+    pub fn new() -> Self {
+        MibTree::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn insert(&mut self, oid: &ObjectIdentifier, value: V) -> Option<V> {
+        self.entries.insert(oids::components(oid).to_vec(), value)
+    }
+
+    pub fn remove(&mut self, oid: &ObjectIdentifier) -> Option<V> {
+        self.entries.remove(oids::components(oid))
+    }
+
+    pub fn contains(&self, oid: &ObjectIdentifier) -> bool {
+        self.entries.contains_key(oids::components(oid))
+    }
+
+    pub fn get(&self, oid: &ObjectIdentifier) -> Option<&V> {
+        self.entries.get(oids::components(oid))
+    }
+
+    pub fn get_mut(&mut self, oid: &ObjectIdentifier) -> Option<&mut V> {
+        self.entries.get_mut(oids::components(oid))
+    }
+
+    /// First entry lexicographically greater than `oid`, which itself need not be in the tree.
+    pub fn get_next(&self, oid: &ObjectIdentifier) -> Option<(ObjectIdentifier, &V)> {
+        self.after(oid).next()
+    }
+
+    /// Up to `max` entries following `oid`, as a GetBulk repetition over a single column would return.
+    pub fn bulk(&self, oid: &ObjectIdentifier, max: usize) -> Vec<(ObjectIdentifier, &V)> {
+        self.after(oid).take(max).collect()
+    }
+
+    /// Entries of the subtree rooted at `root`, including `root` itself, in order.
+    pub fn subtree<'a>(&'a self, root: &ObjectIdentifier) -> impl Iterator<Item = (ObjectIdentifier, &'a V)> + 'a {
+        let prefix = oids::components(root).to_vec();
+        self.entries
+            .range((Included(prefix.clone()), Unbounded))
+            .take_while(move |&(oid, _)| oid.starts_with(&prefix))
+            .map(|(oid, value)| (oids::oid(oid), value))
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (ObjectIdentifier, &'a V)> + 'a {
+        self.entries.iter().map(|(oid, value)| (oids::oid(oid), value))
+    }
+
+    fn after<'a>(&'a self, oid: &ObjectIdentifier) -> impl Iterator<Item = (ObjectIdentifier, &'a V)> + 'a {
+        let start = oids::components(oid).to_vec();
+        self.entries
+            .range((Excluded(start), Unbounded))
+            .map(|(oid, value)| (oids::oid(oid), value))
+    }
+}
+
+/// Read-only instrumentation serving the stored values.
+///
+/// Missing instances are reported as `NoSuchInstance` when the tree holds other instances
+/// under the same parent OID, as `NoSuchObject` otherwise.
+impl MibHandler for MibTree<Variable> {
+    fn get(&mut self, oid: &ObjectIdentifier) -> Variable {
+        if let Some(value) = MibTree::get(self, oid) {
+            return value.clone();
+        }
+
+        let components = oids::components(oid);
+        let parent = match components.split_last() {
+            Some((_, parent)) if !parent.is_empty() => parent,
+            _ => return Variable::NoSuchObject,
+        };
+        match self.subtree(&oids::oid(parent)).next() {
+            Some(_) => Variable::NoSuchInstance,
+            None => Variable::NoSuchObject,
+        }
+    }
+
+    fn get_next(&mut self, oid: &ObjectIdentifier) -> Option<VarBind> {
+        MibTree::get_next(self, oid).map(|(oid, value)| VarBind::new(oid, value.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oid(components: &[u64]) -> ObjectIdentifier {
+        oids::oid(components)
+    }
+
+    fn tree() -> MibTree<Variable> {
+        let mut tree = MibTree::new();
+        for &(components, value) in &[
+            (&[1, 3, 6, 1, 10, 0][..], 10),
+            (&[1, 3, 6, 1, 2, 1, 1, 0][..], 1),
+            (&[1, 3, 6, 1, 2, 1, 2, 0][..], 2),
+            (&[1, 3, 6, 1, 2, 1, 10, 0][..], 3),
+            (&[1, 3, 6, 1, 2, 1, 9, 1][..], 4),
+        ] {
+            tree.insert(&oid(components), Variable::Integer(value));
+        }
+        tree
+    }
+
+    fn keys<'a, I: Iterator<Item = (ObjectIdentifier, &'a Variable)>>(entries: I) -> Vec<ObjectIdentifier> {
+        entries.map(|(oid, _)| oid).collect()
+    }
+
+    #[test]
+    fn ordering() {
+        assert_eq!(
+            keys(tree().iter()),
+            vec![
+                oid(&[1, 3, 6, 1, 2, 1, 1, 0]),
+                oid(&[1, 3, 6, 1, 2, 1, 2, 0]),
+                oid(&[1, 3, 6, 1, 2, 1, 9, 1]),
+                oid(&[1, 3, 6, 1, 2, 1, 10, 0]),
+                oid(&[1, 3, 6, 1, 10, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn lookups() {
+        let tree = tree();
+        assert_eq!(tree.get(&oid(&[1, 3, 6, 1, 2, 1, 2, 0])), Some(&Variable::Integer(2)));
+        assert_eq!(tree.get(&oid(&[1, 3, 6, 1, 2, 1, 2])), None);
+
+        assert_eq!(tree.get_next(&oid(&[1, 3, 6, 1, 2, 1, 2, 0])).unwrap().0, oid(&[1, 3, 6, 1, 2, 1, 9, 1]));
+        assert_eq!(tree.get_next(&oid(&[1, 3, 6, 1, 2, 1, 9])).unwrap().0, oid(&[1, 3, 6, 1, 2, 1, 9, 1]));
+        assert_eq!(tree.get_next(&oid(&[1, 3])).unwrap().0, oid(&[1, 3, 6, 1, 2, 1, 1, 0]));
+        assert!(tree.get_next(&oid(&[1, 3, 6, 1, 10, 0])).is_none());
+
+        assert_eq!(
+            keys(tree.subtree(&oid(&[1, 3, 6, 1, 2, 1]))),
+            keys(tree.iter().take(4))
+        );
+        assert_eq!(keys(tree.subtree(&oid(&[1, 3, 6, 1, 2, 1, 1]))), vec![oid(&[1, 3, 6, 1, 2, 1, 1, 0])]);
+        assert_eq!(tree.subtree(&oid(&[1, 3, 6, 1, 3])).count(), 0);
+
+        assert_eq!(
+            keys(tree.bulk(&oid(&[1, 3, 6, 1, 2, 1, 1, 0]), 2).into_iter()),
+            vec![oid(&[1, 3, 6, 1, 2, 1, 2, 0]), oid(&[1, 3, 6, 1, 2, 1, 9, 1])]
+        );
+    }
+
+    #[test]
+    fn handler() {
+        let mut tree = tree();
+        let handler: &mut MibHandler = &mut tree;
+        assert_eq!(handler.get(&oid(&[1, 3, 6, 1, 2, 1, 1, 0])), Variable::Integer(1));
+        assert_eq!(handler.get(&oid(&[1, 3, 6, 1, 2, 1, 1, 1])), Variable::NoSuchInstance);
+        assert_eq!(handler.get(&oid(&[1, 3, 6, 1, 2, 1, 3, 0])), Variable::NoSuchObject);
+        assert_eq!(
+            handler.get_next(&oid(&[1, 3, 6, 1, 2, 1, 10, 0])),
+            Some(VarBind::new(oid(&[1, 3, 6, 1, 10, 0]), Variable::Integer(10)))
+        );
+    }
+}
@@ -1,6 +1,9 @@
 use asn1_exp::{self, info, Tag, Asn1Serialize, Asn1Serializer, Asn1Deserialize, Asn1Deserializer, Asn1Visitor};
 use asn1_exp::info::universal::*;
-use asn1_exp::de::{Asn1Error, SeqAccess};
+use asn1_exp::de::Asn1Error;
+
+use std::convert::TryFrom;
+use std::net::Ipv4Addr;
 
 use ::types::*;
 
@@ -72,6 +75,11 @@ pub const TAG_CTX_SET_REQUEST: Tag = Tag {
     tagnum: 0x03,
     content_type: info::ContentType::Constructed,
 };
+pub const TAG_CTX_TRAP_V1: Tag = Tag {
+    class: info::ContextSpecific,
+    tagnum: 0x04,
+    content_type: info::ContentType::Constructed,
+};
 pub const TAG_CTX_GET_BULK_REQUEST: Tag = Tag {
     class: info::ContextSpecific,
     tagnum: 0x05,
@@ -98,20 +106,33 @@ pub const TAG_CTX_REPORT: Tag = Tag {
 
 impl Asn1Serialize for Version {
     fn asn1_serialize<S: Asn1Serializer>(&self, serializer: S) -> Result<S::Ok, S::Err> {
-        serializer.serialize_u8(*self as u8)
+        serializer.serialize_u8((*self).into())
     }
 }
 
 impl Asn1Deserialize for Version {
     fn asn1_deserialize<'de, D: Asn1Deserializer<'de>>(deserializer: D) -> Result<Self, D::Err> {
-        let u: u8 = Asn1Deserialize::asn1_deserialize(deserializer)?;
-        match u {
-            0 => Ok(Version::Version1),
-            1 => Ok(Version::Version2),
-            3 => Ok(Version::Version3),
-            _ => Err(Asn1Error::invalid_value(
-                "Version value must be within [0, 3]",
-            )),
+        let v: i64 = Asn1Deserialize::asn1_deserialize(deserializer)?;
+        Version::try_from(v).map_err(|e| match e {
+            VersionError::Unsupported(_) => Asn1Error::invalid_value("SNMPv2u/SNMPv2* (version 2) is not supported"),
+            VersionError::Invalid(_) | VersionError::NotCommunityBased => {
+                Asn1Error::invalid_value("Version value must be 0, 1 or 3")
+            }
+        })
+    }
+}
+
+impl Asn1Serialize for MsgVersion {
+    fn asn1_serialize<S: Asn1Serializer>(&self, serializer: S) -> Result<S::Ok, S::Err> {
+        Version::Version3.asn1_serialize(serializer)
+    }
+}
+
+impl Asn1Deserialize for MsgVersion {
+    fn asn1_deserialize<'de, D: Asn1Deserializer<'de>>(deserializer: D) -> Result<Self, D::Err> {
+        match Asn1Deserialize::asn1_deserialize(deserializer)? {
+            Version::Version3 => Ok(MsgVersion),
+            _ => Err(Asn1Error::invalid_value("SNMPv3 message version must be 3")),
         }
     }
 }
@@ -226,11 +247,11 @@ impl Asn1Deserialize for Variable {
                     }
                     TAG_CTX_NO_SUCH_INSTANCE => {
                         let () = Asn1Deserialize::asn1_deserialize(deserializer)?;
-                        Ok(Variable::NoSuchObject)
+                        Ok(Variable::NoSuchInstance)
                     }
                     TAG_CTX_END_OF_MIB_VIEW => {
                         let () = Asn1Deserialize::asn1_deserialize(deserializer)?;
-                        Ok(Variable::NoSuchObject)
+                        Ok(Variable::EndOfMibView)
                     }
                     _ => Err(Asn1Error::invalid_tag("expected Variable related tag")),
                 }
@@ -279,6 +300,10 @@ impl Asn1Serialize for Pdu {
                 let s = serializer.serialize_implicit(TAG_CTX_REPORT)?;
                 pdu.asn1_serialize(s)
             }
+            &TrapV1(ref pdu) => {
+                let s = serializer.serialize_implicit(TAG_CTX_TRAP_V1)?;
+                pdu.asn1_serialize(s)
+            }
         }
     }
 }
@@ -326,6 +351,10 @@ impl Asn1Deserialize for Pdu {
                         let v = Asn1Deserialize::asn1_deserialize(deserializer)?;
                         Ok(Pdu::Report(v))
                     }
+                    TAG_CTX_TRAP_V1 => {
+                        let v = Asn1Deserialize::asn1_deserialize(deserializer)?;
+                        Ok(Pdu::TrapV1(v))
+                    }
                     _ => Err(Asn1Error::invalid_tag("expected PDU related tag")),
                 }
             }
@@ -334,6 +363,112 @@ impl Asn1Deserialize for Pdu {
     }
 }
 
+impl Asn1Serialize for GenericTrap {
+    fn asn1_serialize<S: Asn1Serializer>(&self, serializer: S) -> Result<S::Ok, S::Err> {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+impl Asn1Deserialize for GenericTrap {
+    fn asn1_deserialize<'de, D: Asn1Deserializer<'de>>(deserializer: D) -> Result<Self, D::Err> {
+        let u: u8 = Asn1Deserialize::asn1_deserialize(deserializer)?;
+        match u {
+            0 => Ok(GenericTrap::ColdStart),
+            1 => Ok(GenericTrap::WarmStart),
+            2 => Ok(GenericTrap::LinkDown),
+            3 => Ok(GenericTrap::LinkUp),
+            4 => Ok(GenericTrap::AuthenticationFailure),
+            5 => Ok(GenericTrap::EgpNeighborLoss),
+            6 => Ok(GenericTrap::EnterpriseSpecific),
+            _ => Err(Asn1Error::invalid_value(
+                "generic-trap value must be within [0, 6]",
+            )),
+        }
+    }
+}
+
+impl Asn1Serialize for ErrorStatus {
+    fn asn1_serialize<S: Asn1Serializer>(&self, serializer: S) -> Result<S::Ok, S::Err> {
+        serializer.serialize_u32((*self).into())
+    }
+}
+
+impl Asn1Deserialize for ErrorStatus {
+    fn asn1_deserialize<'de, D: Asn1Deserializer<'de>>(deserializer: D) -> Result<Self, D::Err> {
+        let v: u32 = Asn1Deserialize::asn1_deserialize(deserializer)?;
+        Ok(ErrorStatus::from(v))
+    }
+}
+
+impl Asn1Serialize for NetworkAddress {
+    fn asn1_serialize<S: asn1_exp::ser::Asn1Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Err> {
+        serializer
+            .serialize_implicit(TAG_APP_IP_ADDRESS)?
+            .serialize_bytes(&self.0.octets())
+    }
+}
+
+impl Asn1Deserialize for NetworkAddress {
+    fn asn1_deserialize<'de, D: Asn1Deserializer<'de>>(deserializer: D) -> Result<Self, D::Err> {
+        struct NetworkAddressVisitor;
+        impl<'de> Asn1Visitor<'de> for NetworkAddressVisitor {
+            type Value = NetworkAddress;
+
+            fn visit_choice<A>(self, tag: &Tag, deserializer: A) -> Result<Self::Value, A::Err>
+            where
+                A: Asn1Deserializer<'de>,
+            {
+                match *tag {
+                    TAG_APP_IP_ADDRESS => {
+                        let v: Vec<u8> = Asn1Deserialize::asn1_deserialize(deserializer)?;
+                        if v.len() != 4 {
+                            return Err(Asn1Error::invalid_length("IpAddress must be encoded in 4 bytes"));
+                        }
+                        Ok(NetworkAddress(Ipv4Addr::new(v[0], v[1], v[2], v[3])))
+                    }
+                    _ => Err(Asn1Error::invalid_tag("expected IpAddress tag")),
+                }
+            }
+        }
+        deserializer.deserialize_choice(NetworkAddressVisitor)
+    }
+}
+
+impl Asn1Serialize for TimeStamp {
+    fn asn1_serialize<S: asn1_exp::ser::Asn1Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Err> {
+        self.0.asn1_serialize(serializer.serialize_implicit(TAG_APP_TIME_TICKS)?)
+    }
+}
+
+impl Asn1Deserialize for TimeStamp {
+    fn asn1_deserialize<'de, D: Asn1Deserializer<'de>>(deserializer: D) -> Result<Self, D::Err> {
+        struct TimeStampVisitor;
+        impl<'de> Asn1Visitor<'de> for TimeStampVisitor {
+            type Value = TimeStamp;
+
+            fn visit_choice<A>(self, tag: &Tag, deserializer: A) -> Result<Self::Value, A::Err>
+            where
+                A: Asn1Deserializer<'de>,
+            {
+                match *tag {
+                    TAG_APP_TIME_TICKS => {
+                        let v = Asn1Deserialize::asn1_deserialize(deserializer)?;
+                        Ok(TimeStamp(v))
+                    }
+                    _ => Err(Asn1Error::invalid_tag("expected TimeTicks tag")),
+                }
+            }
+        }
+        deserializer.deserialize_choice(TimeStampVisitor)
+    }
+}
+
 impl Asn1Serialize for MessageFlags {
     fn asn1_serialize<S: asn1_exp::ser::Asn1Serializer>(
         &self,
@@ -341,7 +476,7 @@ impl Asn1Serialize for MessageFlags {
     ) -> Result<S::Ok, S::Err> {
         let value: u8 = {
             let reportable: u8 = if self.reportable { 0x04 } else { 0x00 };
-            self.security_level as u8 & reportable
+            self.security_level as u8 | reportable
         };
 
         serializer.serialize_bytes(&[value])
@@ -420,6 +555,34 @@ impl Asn1Deserialize for SecurityModel {
     }
 }
 
+impl Asn1Serialize for SecurityParameter {
+    fn asn1_serialize<S: asn1_exp::ser::Asn1Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Err> {
+        serializer.serialize_bytes(&self.to_usm_bytes())
+    }
+}
+
+impl Asn1Deserialize for SecurityParameter {
+    fn asn1_deserialize<'de, D: Asn1Deserializer<'de>>(deserializer: D) -> Result<Self, D::Err> {
+        struct SecurityParameterVisitor;
+        impl<'de> Asn1Visitor<'de> for SecurityParameterVisitor {
+            type Value = SecurityParameter;
+
+            fn visit_byte_string<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+                where E: Asn1Error
+            {
+                SecurityParameter::from_usm_bytes(&v).ok_or_else(|| {
+                    Asn1Error::invalid_value("msgSecurityParameters must contain UsmSecurityParameters")
+                })
+            }
+        }
+
+        deserializer.deserialize_bytes(SecurityParameterVisitor)
+    }
+}
+
 impl Asn1Serialize for PduV3 {
     fn asn1_serialize<S: asn1_exp::ser::Asn1Serializer>(
         &self,
@@ -463,45 +626,3 @@ impl Asn1Deserialize for PduV3 {
         deserializer.deserialize_choice(PduV3Visitor)
     }
 }
-
-// impl Asn1Serialize for PacketV3 {
-//     fn asn1_serialize<S: asn1_exp::ser::Asn1Serializer>(
-//         &self,
-//         serializer: S,
-//     ) -> Result<S::Ok, S::Err> {
-
-//     }
-// }
-
-impl Asn1Deserialize for PacketV3 {
-    fn asn1_deserialize<'de, D: Asn1Deserializer<'de>>(deserializer: D) -> Result<Self, D::Err> {
-        struct PacketVisitor;
-        impl<'de> Asn1Visitor<'de> for PacketVisitor {
-            type Value = PacketV3;
-
-            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Err>
-                where A: SeqAccess<'de>
-            {
-                let version: Version = seq.next_field()?;
-                let msg_id: MessageID = seq.next_field()?;
-                let max_size: MaxSize = seq.next_field()?;
-                let flags: MessageFlags = seq.next_field()?;
-                let security_model: SecurityModel = seq.next_field()?;
-                let security_parameters: SecurityParameter = seq.next_field()?;
-                let pdu: PduV3 = seq.next_field()?;
-
-                Ok(PacketV3 {
-                    version: version,
-                    msg_id: msg_id,
-                    max_size: max_size,
-                    flags: flags,
-                    security_model: security_model,
-                    security_parameters: security_parameters,
-                    pdu: pdu,
-                })
-            }
-        }
-
-        deserializer.deserialize_seq(PacketVisitor)
-    }
-}
\ No newline at end of file
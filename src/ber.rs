@@ -0,0 +1,163 @@
+//! Minimal BER primitives used where the generic `asn1_exp` machinery is not enough:
+//! nested encodings and byte offsets inside already serialized messages.
+
+use std::ops::Range;
+
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_SEQUENCE: u8 = 0x30;
+
+/// Single TLV found by `Reader`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    /// Absolute offset of the first content byte.
+    pub offset: usize,
+    pub value: &'a [u8],
+    /// Whole encoding, identifier and length octets included.
+    pub raw: &'a [u8],
+}
+
+impl<'a> Tlv<'a> {
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.value.len()
+    }
+
+    /// Reader over the contents of a constructed TLV, keeping absolute offsets.
+    pub fn reader(&self) -> Reader<'a> {
+        Reader {
+            buf: self.value,
+            pos: 0,
+            base: self.offset,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    base: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Reader {
+            buf: buf,
+            pos: 0,
+            base: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    /// Reads the next TLV. Only low tag numbers (single identifier octet) are supported,
+    /// which is all SNMP ever uses.
+    pub fn read(&mut self) -> Option<Tlv<'a>> {
+        let buf = self.buf;
+        let mut pos = self.pos;
+
+        let tag = *buf.get(pos)?;
+        if tag & 0x1f == 0x1f {
+            return None;
+        }
+        pos += 1;
+
+        let first = *buf.get(pos)? as usize;
+        pos += 1;
+        let len = if first & 0x80 == 0 {
+            first
+        } else {
+            let n = first & 0x7f;
+            if n == 0 || n > 4 {
+                return None;
+            }
+            let mut len = 0usize;
+            for _ in 0..n {
+                len = (len << 8) | *buf.get(pos)? as usize;
+                pos += 1;
+            }
+            len
+        };
+
+        if buf.len() - pos < len {
+            return None;
+        }
+
+        let tlv = Tlv {
+            tag: tag,
+            offset: self.base + pos,
+            value: &buf[pos..pos + len],
+            raw: &buf[self.pos..pos + len],
+        };
+        self.pos = pos + len;
+        Some(tlv)
+    }
+
+    /// Reads the next TLV and checks its tag.
+    pub fn expect(&mut self, tag: u8) -> Option<Tlv<'a>> {
+        self.read().and_then(|tlv| if tlv.tag == tag { Some(tlv) } else { None })
+    }
+}
+
+/// Decodes INTEGER contents as a signed 64-bit value.
+pub fn decode_integer(value: &[u8]) -> Option<i64> {
+    if value.is_empty() || value.len() > 8 {
+        return None;
+    }
+    let mut v: i64 = if value[0] & 0x80 != 0 { -1 } else { 0 };
+    for b in value {
+        v = (v << 8) | *b as i64;
+    }
+    Some(v)
+}
+
+/// Decodes INTEGER contents of an unsigned type such as Counter64, which may need a ninth octet.
+pub fn decode_unsigned(value: &[u8]) -> Option<u64> {
+    let value = match value.split_first() {
+        Some((&0, rest)) if !rest.is_empty() => rest,
+        Some((first, _)) if first & 0x80 == 0 => value,
+        _ => return None,
+    };
+    if value.len() > 8 {
+        return None;
+    }
+    Some(value.iter().fold(0, |v, b| (v << 8) | *b as u64))
+}
+
+pub fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = [(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (4 - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+pub fn write_tlv(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    write_length(out, value.len());
+    out.extend_from_slice(value);
+}
+
+pub fn write_integer(out: &mut Vec<u8>, tag: u8, v: i64) {
+    let bytes = [
+        (v >> 56) as u8, (v >> 48) as u8, (v >> 40) as u8, (v >> 32) as u8,
+        (v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8,
+    ];
+    // Strip redundant leading octets while keeping the sign bit intact.
+    let mut skip = 0;
+    while skip < 7 {
+        let (cur, next) = (bytes[skip], bytes[skip + 1]);
+        if (cur == 0x00 && next & 0x80 == 0) || (cur == 0xff && next & 0x80 != 0) {
+            skip += 1;
+        } else {
+            break;
+        }
+    }
+    write_tlv(out, tag, &bytes[skip..]);
+}
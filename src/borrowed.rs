@@ -0,0 +1,445 @@
+//! Borrowed views of v1/v2c messages, decoded lazily from the receive buffer.
+//!
+//! Only the message header is decoded up front; bindings are decoded one at a time while
+//! iterating, without allocating. Owned types are built on demand.
+
+use std::convert::TryFrom;
+
+use asn1_exp::from_asn1;
+
+use ber;
+use error::Error;
+use message::Message;
+use types::*;
+
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_NULL: u8 = 0x05;
+const TAG_OBJECT_IDENTIFIER: u8 = 0x06;
+const TAG_IP_ADDRESS: u8 = 0x40;
+const TAG_COUNTER32: u8 = 0x41;
+const TAG_GAUGE32: u8 = 0x42;
+const TAG_TIME_TICKS: u8 = 0x43;
+const TAG_OPAQUE: u8 = 0x44;
+const TAG_COUNTER64: u8 = 0x46;
+const TAG_NO_SUCH_OBJECT: u8 = 0x80;
+const TAG_NO_SUCH_INSTANCE: u8 = 0x81;
+const TAG_END_OF_MIB_VIEW: u8 = 0x82;
+
+/// PDU tags, GetRequest (0xa0) to Report (0xa8).
+const TAG_PDU_FIRST: u8 = 0xa0;
+const TAG_PDU_LAST: u8 = 0xa8;
+const TAG_TRAP_V1: u8 = 0xa4;
+
+/// Community based message borrowed from its encoding.
+#[derive(Debug, Clone, Copy)]
+pub struct PacketRef<'a> {
+    buf: &'a [u8],
+    version: Version,
+    community: &'a [u8],
+    pdu_tag: u8,
+    request_id: Option<u32>,
+    binds: &'a [u8],
+}
+
+impl<'a> PacketRef<'a> {
+    /// Decodes the header of a v1/v2c message; bindings are only checked while iterating.
+    pub fn parse(buf: &'a [u8]) -> Result<PacketRef<'a>, Error> {
+        let message = ber::Reader::new(buf).expect(ber::TAG_SEQUENCE).ok_or(Error::Decode("Message"))?;
+        let mut fields = message.reader();
+
+        let version = fields
+            .expect(ber::TAG_INTEGER)
+            .and_then(|version| ber::decode_integer(version.value))
+            .ok_or(Error::Decode("version"))?;
+        let version = Version::try_from(version)?;
+        let community = fields.expect(ber::TAG_OCTET_STRING).ok_or(Error::Decode("community"))?;
+
+        let pdu = match fields.read() {
+            Some(pdu) if fields.is_empty() => pdu,
+            _ => return Err(Error::Decode("data")),
+        };
+        let (request_id, binds) = pdu_fields(pdu)?;
+
+        Ok(PacketRef {
+            buf: buf,
+            version: version,
+            community: community.value,
+            pdu_tag: pdu.tag,
+            request_id: request_id,
+            binds: binds,
+        })
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn community(&self) -> &'a [u8] {
+        self.community
+    }
+
+    /// Context-specific tag of the PDU, 0xa0 for GetRequest to 0xa8 for Report.
+    pub fn pdu_tag(&self) -> u8 {
+        self.pdu_tag
+    }
+
+    /// Request ID of the PDU, `None` for v1 traps.
+    pub fn request_id(&self) -> Option<u32> {
+        self.request_id
+    }
+
+    pub fn binds(&self) -> VarBindsRef<'a> {
+        VarBindsRef {
+            reader: ber::Reader::new(self.binds),
+        }
+    }
+
+    /// Fully decodes the message into the owned representation.
+    pub fn to_packet(&self) -> Result<PacketV2, Error> {
+        from_asn1(self.buf).map_err(|_| decode_error(self.buf))
+    }
+}
+
+/// Request ID and encoded bindings of a PDU, `None` for v1 traps.
+fn pdu_fields<'a>(pdu: ber::Tlv<'a>) -> Result<(Option<u32>, &'a [u8]), Error> {
+    if pdu.tag < TAG_PDU_FIRST || pdu.tag > TAG_PDU_LAST {
+        return Err(Error::Decode("data"));
+    }
+
+    // Bindings come last in every PDU layout, TrapV1 included.
+    let mut fields = pdu.reader();
+    let mut first = None;
+    let mut last = None;
+    while let Some(field) = fields.read() {
+        first = first.or(Some(field));
+        last = Some(field);
+    }
+    let request_id = match first {
+        Some(_) if pdu.tag == TAG_TRAP_V1 => None,
+        Some(field) if field.tag == ber::TAG_INTEGER => match ber::decode_integer(field.value) {
+            Some(v) if v >= 0 && v <= u32::max_value() as i64 => Some(v as u32),
+            _ => return Err(Error::Decode("request-id")),
+        },
+        _ => return Err(Error::Decode("request-id")),
+    };
+    match last {
+        Some(binds) if binds.tag == ber::TAG_SEQUENCE && fields.is_empty() => Ok((request_id, binds.value)),
+        _ => Err(Error::Decode("variable-bindings")),
+    }
+}
+
+/// Bindings of a v3 message, none if the scoped PDU is encrypted.
+fn scoped_binds<'a>(buf: &'a [u8]) -> Result<VarBindsRef<'a>, Error> {
+    let message = ber::Reader::new(buf).expect(ber::TAG_SEQUENCE).ok_or(Error::Decode("SNMPv3Message"))?;
+    let mut fields = message.reader();
+    fields.expect(ber::TAG_INTEGER).ok_or(Error::Decode("msgVersion"))?;
+    fields.expect(ber::TAG_SEQUENCE).ok_or(Error::Decode("msgGlobalData"))?;
+    fields.expect(ber::TAG_OCTET_STRING).ok_or(Error::Decode("msgSecurityParameters"))?;
+    let scoped = match fields.read() {
+        Some(data) if data.tag == ber::TAG_SEQUENCE => data,
+        Some(data) if data.tag == ber::TAG_OCTET_STRING => return Ok(VarBindsRef { reader: ber::Reader::new(&[]) }),
+        _ => return Err(Error::Decode("msgData")),
+    };
+
+    let mut fields = scoped.reader();
+    fields.expect(ber::TAG_OCTET_STRING).ok_or(Error::Decode("contextEngineID"))?;
+    fields.expect(ber::TAG_OCTET_STRING).ok_or(Error::Decode("contextName"))?;
+    let pdu = fields.read().ok_or(Error::Decode("data"))?;
+    let (_, binds) = pdu_fields(pdu)?;
+    Ok(VarBindsRef {
+        reader: ber::Reader::new(binds),
+    })
+}
+
+/// Error for a message of any version `from_asn1` rejected, naming its first malformed
+/// component down to the binding values; the whole message if none stands out.
+pub(crate) fn decode_error(buf: &[u8]) -> Error {
+    let (binds, message) = match Message::peek_version(buf) {
+        Ok(Version::Version3) => (scoped_binds(buf), "SNMPv3Message"),
+        Ok(_) => (PacketRef::parse(buf).map(|packet| packet.binds()), "Message"),
+        Err(e) => (Err(e), "Message"),
+    };
+    let checked = binds.and_then(|binds| {
+        for bind in binds {
+            let bind = bind?;
+            bind.value()?;
+            bind.to_var_bind()?;
+        }
+        Ok(())
+    });
+    checked.err().unwrap_or(Error::Decode(message))
+}
+
+/// Iterator over the bindings of a `PacketRef`, stopping after the first malformed one.
+#[derive(Debug, Clone)]
+pub struct VarBindsRef<'a> {
+    reader: ber::Reader<'a>,
+}
+
+impl<'a> Iterator for VarBindsRef<'a> {
+    type Item = Result<VarBindRef<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.reader.is_empty() {
+            return None;
+        }
+        let bind = self.reader.expect(ber::TAG_SEQUENCE).ok_or(Error::Decode("variable-bindings"));
+        let bind = bind.and_then(|bind| {
+            let mut fields = bind.reader();
+            let oid = fields.expect(TAG_OBJECT_IDENTIFIER).ok_or(Error::Decode("name"))?;
+            let value = match fields.read() {
+                Some(value) if fields.is_empty() => value,
+                _ => return Err(Error::Decode("value")),
+            };
+            Ok(VarBindRef {
+                raw: bind.raw,
+                oid: oid.value,
+                value_tag: value.tag,
+                value: value.value,
+            })
+        });
+        if bind.is_err() {
+            self.reader = ber::Reader::new(&[]);
+        }
+        Some(bind)
+    }
+}
+
+/// Binding borrowed from its encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VarBindRef<'a> {
+    raw: &'a [u8],
+    oid: &'a [u8],
+    value_tag: u8,
+    value: &'a [u8],
+}
+
+impl<'a> VarBindRef<'a> {
+    /// Contents octets of the OID, comparable byte-wise with other encoded OIDs.
+    pub fn oid_bytes(&self) -> &'a [u8] {
+        self.oid
+    }
+
+    pub fn oid_components(&self) -> OidComponents<'a> {
+        OidComponents {
+            bytes: self.oid,
+            pending: None,
+            first: true,
+        }
+    }
+
+    pub fn value(&self) -> Result<VariableRef<'a>, Error> {
+        let value = self.value;
+        let unsigned = || match ber::decode_unsigned(value) {
+            Some(v) if v <= u32::max_value() as u64 => Ok(v as u32),
+            _ => Err(Error::Decode("value")),
+        };
+        let variable = match self.value_tag {
+            ber::TAG_INTEGER => match ber::decode_integer(value) {
+                Some(v) if v >= i32::min_value() as i64 && v <= i32::max_value() as i64 => {
+                    VariableRef::Integer(v as i32)
+                }
+                _ => return Err(Error::Decode("value")),
+            },
+            TAG_BIT_STRING => VariableRef::BitString(value),
+            ber::TAG_OCTET_STRING => VariableRef::OctetString(value),
+            TAG_NULL => VariableRef::Null,
+            TAG_OBJECT_IDENTIFIER => VariableRef::Oid(value),
+            TAG_IP_ADDRESS if value.len() == 4 => VariableRef::IpAddress(value[0], value[1], value[2], value[3]),
+            TAG_COUNTER32 => VariableRef::Counter(unsigned()?),
+            TAG_GAUGE32 => VariableRef::Gauge(unsigned()?),
+            TAG_TIME_TICKS => VariableRef::TimeTicks(unsigned()?),
+            TAG_OPAQUE => VariableRef::Opaque(value),
+            TAG_COUNTER64 => VariableRef::Counter64(ber::decode_unsigned(value).ok_or(Error::Decode("value"))?),
+            TAG_NO_SUCH_OBJECT => VariableRef::NoSuchObject,
+            TAG_NO_SUCH_INSTANCE => VariableRef::NoSuchInstance,
+            TAG_END_OF_MIB_VIEW => VariableRef::EndOfMibView,
+            _ => return Err(Error::Decode("value")),
+        };
+        Ok(variable)
+    }
+
+    pub fn to_var_bind(&self) -> Result<VarBind, Error> {
+        from_asn1(self.raw).map_err(|_| Error::Decode("variable-bindings"))
+    }
+}
+
+/// Value of a `VarBindRef`; strings and OIDs are left encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableRef<'a> {
+    Integer(i32),
+    BitString(&'a [u8]),
+    OctetString(&'a [u8]),
+    Null,
+    /// Contents octets, see `VarBindRef::oid_bytes`.
+    Oid(&'a [u8]),
+    IpAddress(u8, u8, u8, u8),
+    Counter(u32),
+    Gauge(u32),
+    TimeTicks(u32),
+    Opaque(&'a [u8]),
+    Counter64(u64),
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+/// Sub-identifiers of an encoded OID; stops early on a truncated or oversized one.
+#[derive(Debug, Clone)]
+pub struct OidComponents<'a> {
+    bytes: &'a [u8],
+    pending: Option<u64>,
+    first: bool,
+}
+
+impl<'a> Iterator for OidComponents<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if let Some(component) = self.pending.take() {
+            return Some(component);
+        }
+
+        let mut v: u64 = 0;
+        let mut len = 0;
+        loop {
+            let b = *self.bytes.get(len)?;
+            if v > u64::max_value() >> 7 {
+                return None;
+            }
+            v = (v << 7) | (b & 0x7f) as u64;
+            len += 1;
+            if b & 0x80 == 0 {
+                break;
+            }
+        }
+        self.bytes = &self.bytes[len..];
+
+        if !self.first {
+            return Some(v);
+        }
+        // The first sub-identifier packs the first two arcs (X.690 section 8.19.4).
+        self.first = false;
+        let arc = if v < 80 { v / 40 } else { 2 };
+        self.pending = Some(v - arc * 40);
+        Some(arc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asn1_exp::to_asn1;
+
+    use super::*;
+    use oids;
+
+    fn binds() -> VarBindList {
+        vec![
+            VarBind::new(oids::oid(oids::SYS_UP_TIME), Variable::TimeTicks(123456)),
+            VarBind::new(
+                oids::oid(oids::SNMP_TRAP_OID),
+                Variable::Oid(oids::oid(&[1, 3, 6, 1, 4, 1, 8072, 2, 3, 0, 1])),
+            ),
+            VarBind::new(oids::oid(&[1, 3, 6, 1, 2, 1, 2, 2, 1, 10, 300]), Variable::Counter(u32::max_value())),
+            VarBind::new(oids::oid(&[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 6, 1]), Variable::Counter64(u64::max_value())),
+            VarBind::new(oids::oid(&[2, 999, 1]), Variable::Integer(-5)),
+            VarBind::new(
+                oids::oid(&[1, 3, 6, 1, 2, 1, 1, 5, 0]),
+                Variable::OctetString(OctetString::from(b"host".to_vec())),
+            ),
+            VarBind::new(oids::oid(&[1, 3, 6, 1, 2, 1, 4, 20, 1, 1, 10, 0, 0, 1]), Variable::IpAddress(10, 0, 0, 1)),
+            VarBind::new(oids::oid(&[1, 3, 6, 1, 2, 1, 1, 9, 0]), Variable::NoSuchInstance),
+        ]
+    }
+
+    #[test]
+    fn packet() {
+        let packet = PacketV2::new(
+            Version::Version2,
+            Community::new("public"),
+            Pdu::Trap(InnerPdu::new(1234, ErrorStatus::NoError, 0, binds())),
+        );
+        let buf = to_asn1(&packet).unwrap();
+
+        let view = PacketRef::parse(&buf).unwrap();
+        assert_eq!(view.version(), Version::Version2);
+        assert_eq!(view.community(), b"public");
+        assert_eq!(view.pdu_tag(), 0xa7);
+        assert_eq!(view.request_id(), Some(1234));
+        assert_eq!(view.to_packet().unwrap(), packet);
+
+        let views = view.binds().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(views.len(), binds().len());
+        for (view, bind) in views.iter().zip(binds()) {
+            assert_eq!(view.oid_components().collect::<Vec<_>>(), oids::components(bind.oid()));
+            assert_eq!(view.to_var_bind().unwrap(), bind);
+        }
+
+        assert_eq!(views[0].value().unwrap(), VariableRef::TimeTicks(123456));
+        assert_eq!(views[2].value().unwrap(), VariableRef::Counter(u32::max_value()));
+        assert_eq!(views[3].value().unwrap(), VariableRef::Counter64(u64::max_value()));
+        assert_eq!(views[4].value().unwrap(), VariableRef::Integer(-5));
+        assert_eq!(views[5].value().unwrap(), VariableRef::OctetString(b"host"));
+        assert_eq!(views[6].value().unwrap(), VariableRef::IpAddress(10, 0, 0, 1));
+        assert_eq!(views[7].value().unwrap(), VariableRef::NoSuchInstance);
+    }
+
+    #[test]
+    fn trap_v1() {
+        let trap = TrapV1Pdu::new(
+            oids::oid(&[1, 3, 6, 1, 4, 1, 318]),
+            ::std::net::Ipv4Addr::new(192, 168, 1, 10),
+            GenericTrap::EnterpriseSpecific,
+            5,
+            123456,
+            binds(),
+        );
+        let buf = to_asn1(&PacketV2::new(Version::Version1, Community::new("public"), Pdu::TrapV1(trap))).unwrap();
+
+        let view = PacketRef::parse(&buf).unwrap();
+        assert_eq!(view.request_id(), None);
+        assert_eq!(view.binds().count(), binds().len());
+    }
+
+    #[test]
+    fn request_id_range() {
+        let pdu = Pdu::new_request(RequestType::Get, u32::max_value(), 0, 0, Vec::new());
+        let buf = to_asn1(&PacketV2::new(Version::Version1, Community::new("public"), pdu)).unwrap();
+        assert_eq!(PacketRef::parse(&buf).unwrap().request_id(), Some(u32::max_value()));
+
+        // Request ID 1 is the single octet after the PDU header; -1 must not become 0xffffffff.
+        let pdu = Pdu::new_request(RequestType::Get, 1, 0, 0, Vec::new());
+        let mut buf = to_asn1(&PacketV2::new(Version::Version1, Community::new("public"), pdu)).unwrap();
+        assert_eq!(&buf[13..18], &[0xa0, 0x0b, 0x02, 0x01, 0x01]);
+        buf[17] = 0xff;
+        match PacketRef::parse(&buf) {
+            Err(Error::Decode("request-id")) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let pdu = Pdu::new_request(RequestType::Get, 1, 0, 0, binds());
+        let packet = PacketV2::new(Version::Version1, Community::new("public"), pdu);
+        let mut buf = to_asn1(&packet).unwrap();
+
+        // Truncating the last binding is only noticed when reaching it.
+        let len = buf.len();
+        let last = to_asn1(&binds()[7]).unwrap().len();
+        buf[len - last + 1] += 1;
+        let view = PacketRef::parse(&buf).unwrap();
+        let results: Vec<_> = view.binds().collect();
+        assert_eq!(results.len(), 8);
+        assert!(results[..7].iter().all(|r| r.is_ok()));
+        match results[7] {
+            Err(Error::Decode("variable-bindings")) => {}
+            ref other => panic!("unexpected result: {:?}", other),
+        }
+
+        match PacketRef::parse(&buf[..10]) {
+            Err(Error::Decode("Message")) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
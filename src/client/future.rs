@@ -0,0 +1,384 @@
+//! Asynchronous session on top of tokio UDP socket.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use futures::{Async, Future, Poll, Stream};
+use tokio::net::UdpSocket;
+use tokio::timer::Delay;
+
+use client::{request_pdu, response_binds, CommunityCodec, Error, RequestIds, SessionOptions, MAX_DATAGRAM_SIZE};
+use client::walk::WalkState;
+use types::*;
+
+/// Community based session bound to a single agent.
+///
+/// Every request borrows the session mutably, so requests are issued one at a time.
+#[derive(Debug)]
+pub struct AsyncSession {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    codec: CommunityCodec,
+    options: SessionOptions,
+    ids: RequestIds,
+    buf: Vec<u8>,
+}
+
+impl AsyncSession {
+    pub fn new(peer: SocketAddr, version: Version, community: Community) -> io::Result<Self> {
+        let local: SocketAddr = if peer.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+        let socket = UdpSocket::bind(&local)?;
+
+        Ok(AsyncSession {
+            socket: socket,
+            peer: peer,
+            codec: CommunityCodec {
+                version: version,
+                community: community,
+            },
+            options: SessionOptions::default(),
+            ids: RequestIds::new(),
+            buf: vec![0; MAX_DATAGRAM_SIZE],
+        })
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn options(&self) -> &SessionOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, value: SessionOptions) {
+        self.options = value
+    }
+
+    pub fn get(&mut self, oids: &[ObjectIdentifier]) -> Response {
+        let pdu = request_pdu(RequestType::Get, 0, oids);
+        self.request(pdu)
+    }
+
+    pub fn get_next(&mut self, oids: &[ObjectIdentifier]) -> Response {
+        let pdu = request_pdu(RequestType::GetNext, 0, oids);
+        self.request(pdu)
+    }
+
+    pub fn get_bulk(&mut self, non_repeaters: u32, max_repetitions: u32, oids: &[ObjectIdentifier]) -> Response {
+        let binds = oids.iter().cloned().map(VarBind::new_null).collect();
+        let pdu = Pdu::new_bulk_request(0, non_repeaters, max_repetitions, binds);
+        self.request(pdu)
+    }
+
+    pub fn set(&mut self, binds: VarBindList) -> Response {
+        let pdu = Pdu::new_request(RequestType::Set, 0, 0, 0, binds);
+        self.request(pdu)
+    }
+
+    /// Streams the subtree under `root`, with GetBulk on v2c and GetNext on v1.
+    pub fn walk(&mut self, root: &ObjectIdentifier) -> WalkStream {
+        let state = WalkState::new(root, self.codec.version, self.options.max_repetitions);
+        WalkStream {
+            session: Some(self),
+            pending: None,
+            state: state,
+        }
+    }
+
+    /// Sends an arbitrary request PDU, replacing its request ID with a fresh one.
+    pub fn request(&mut self, mut pdu: Pdu) -> Response {
+        let request_id = self.ids.next();
+        pdu.set_request_id(request_id);
+
+        let (message, error) = match self.codec.encode(pdu) {
+            Ok(message) => (message, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        let retries = self.options.retries;
+
+        Response {
+            session: self,
+            request_id: request_id,
+            message: message,
+            error: error,
+            retries_left: retries,
+            state: State::Send,
+            delay: Delay::new(Instant::now()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Send,
+    Receive,
+}
+
+/// Future of a single request, resolving to the GetResponse bindings.
+#[derive(Debug)]
+pub struct Response<'a> {
+    session: &'a mut AsyncSession,
+    request_id: u32,
+    message: Vec<u8>,
+    error: Option<Error>,
+    retries_left: u32,
+    state: State,
+    delay: Delay,
+}
+
+impl<'a> Response<'a> {
+    pub fn request_id(&self) -> u32 {
+        self.request_id
+    }
+
+    /// Polls for the matching response; `Ready(None)` means the attempt timed out.
+    fn poll_receive(&mut self) -> Poll<Option<Pdu>, Error> {
+        loop {
+            let session = &mut *self.session;
+            match session.socket.poll_recv_from(&mut session.buf) {
+                Ok(Async::Ready((len, from))) => {
+                    if from != session.peer {
+                        continue;
+                    }
+                    // Stale, foreign and malformed datagrams are dropped.
+                    if let Ok(Some(pdu)) = session.codec.decode(&session.buf[..len]) {
+                        if pdu.is_response_type() && pdu.request_id() == Some(self.request_id) {
+                            return Ok(Async::Ready(Some(pdu)));
+                        }
+                    }
+                }
+                Ok(Async::NotReady) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        match self.delay.poll() {
+            Ok(Async::Ready(())) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => Err(Error::Io(io::Error::new(io::ErrorKind::Other, e))),
+        }
+    }
+}
+
+impl<'a> Future for Response<'a> {
+    type Item = VarBindList;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<VarBindList, Error> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+
+        loop {
+            match self.state {
+                State::Send => {
+                    {
+                        let session = &mut *self.session;
+                        try_ready!(session.socket.poll_send_to(&self.message, &session.peer));
+                    }
+                    self.delay.reset(Instant::now() + self.session.options.timeout);
+                    self.state = State::Receive;
+                }
+                State::Receive => match self.poll_receive()? {
+                    Async::Ready(Some(pdu)) => return response_binds(pdu).map(Async::Ready),
+                    Async::Ready(None) => {
+                        if self.retries_left == 0 {
+                            return Err(Error::Timeout);
+                        }
+                        self.retries_left -= 1;
+                        self.state = State::Send;
+                    }
+                    Async::NotReady => return Ok(Async::NotReady),
+                },
+            }
+        }
+    }
+}
+
+/// Stream over a subtree, see `AsyncSession::walk`.
+///
+/// Yields an error and ends if a request fails or the agent returns non-increasing OIDs.
+#[derive(Debug)]
+pub struct WalkStream<'a> {
+    // The session is lent to the pending request and taken back once it completes.
+    session: Option<&'a mut AsyncSession>,
+    pending: Option<Response<'a>>,
+    state: WalkState,
+}
+
+impl<'a> Stream for WalkStream<'a> {
+    type Item = VarBind;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<VarBind>, Error> {
+        loop {
+            if let Some(bind) = self.state.pop() {
+                return Ok(Async::Ready(Some(bind)));
+            }
+            if self.state.is_done() {
+                return Ok(Async::Ready(None));
+            }
+
+            if self.pending.is_none() {
+                let session = self.session.take().expect("walk session is lent to a finished request");
+                self.pending = Some(session.request(self.state.request()));
+            }
+
+            let response = match self.pending.as_mut().unwrap().poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(binds)) => Ok(binds),
+                Err(e) => Err(e),
+            };
+            let finished = self.pending.take().unwrap();
+            self.session = Some(finished.session);
+
+            self.state.handle_response(response)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket as StdUdpSocket;
+    use std::thread;
+    use std::time::Duration;
+
+    use asn1_exp::{from_asn1, to_asn1};
+    use tokio::runtime::current_thread::Runtime;
+
+    use super::*;
+    use oids;
+
+    /// Agent that ignores the first request and answers the following ones.
+    fn responder() -> SocketAddr {
+        let socket = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+            let mut first = true;
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).unwrap();
+                if first {
+                    first = false;
+                    continue;
+                }
+                let request: PacketV2 = from_asn1(&buf[..len]).unwrap();
+                let binds = vec![VarBind::new(oids::oid(oids::SYS_UP_TIME), Variable::TimeTicks(42))];
+                let pdu = Pdu::GetResponse(InnerPdu::new(request.pdu().request_id().unwrap(), ErrorStatus::NoError, 0, binds));
+                let response = PacketV2::new(request.version(), request.community().clone(), pdu);
+                socket.send_to(&to_asn1(&response).unwrap(), from).unwrap();
+            }
+        });
+
+        addr
+    }
+
+    const OBJECTS: [[u64; 9]; 3] = [
+        [1, 3, 6, 1, 2, 1, 1, 1, 0],
+        [1, 3, 6, 1, 2, 1, 1, 2, 0],
+        [1, 3, 6, 1, 2, 1, 2, 1, 0],
+    ];
+
+    fn next_object(oid: &[u64]) -> VarBind {
+        match OBJECTS.iter().position(|o| &o[..] > oid) {
+            Some(i) => VarBind::new(oids::oid(&OBJECTS[i]), Variable::Integer(i as i32 + 1)),
+            None => VarBind::new(oids::oid(oid), Variable::EndOfMibView),
+        }
+    }
+
+    /// Agent serving `OBJECTS` to GetNext and GetBulk requests.
+    fn walk_responder() -> SocketAddr {
+        let socket = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).unwrap();
+                let request: PacketV2 = from_asn1(&buf[..len]).unwrap();
+
+                let binds = match *request.pdu() {
+                    // v1 has no exception values, the end of the MIB view is noSuchName.
+                    Pdu::GetNextRequest(ref inner) if request.version() == Version::Version1 => {
+                        let bind = next_object(oids::components(inner.binds()[0].oid()));
+                        if *bind.value() == Variable::EndOfMibView {
+                            let pdu = InnerPdu::new(inner.request_id(), ErrorStatus::NoSuchName, 1, inner.binds().clone());
+                            let response = PacketV2::new(request.version(), request.community().clone(), Pdu::GetResponse(pdu));
+                            socket.send_to(&to_asn1(&response).unwrap(), from).unwrap();
+                            continue;
+                        }
+                        vec![bind]
+                    }
+                    Pdu::GetNextRequest(ref inner) => vec![next_object(oids::components(inner.binds()[0].oid()))],
+                    Pdu::GetBulkRequest(ref inner) => {
+                        let mut oid = oids::components(inner.binds()[0].oid()).to_vec();
+                        let mut binds = Vec::new();
+                        for _ in 0..inner.max_repetitions() {
+                            let bind = next_object(&oid);
+                            oid = oids::components(bind.oid()).to_vec();
+                            binds.push(bind);
+                        }
+                        binds
+                    }
+                    _ => panic!("unexpected request"),
+                };
+
+                let pdu = Pdu::GetResponse(InnerPdu::new(request.pdu().request_id().unwrap(), ErrorStatus::NoError, 0, binds));
+                let response = PacketV2::new(request.version(), request.community().clone(), pdu);
+                socket.send_to(&to_asn1(&response).unwrap(), from).unwrap();
+            }
+        });
+
+        addr
+    }
+
+    fn walk_values(version: Version, root: &[u64]) -> Vec<Variable> {
+        let mut session = AsyncSession::new(walk_responder(), version, Community::new("public")).unwrap();
+        session.set_options(SessionOptions {
+            timeout: Duration::from_millis(500),
+            retries: 0,
+            max_repetitions: 2,
+        });
+
+        let mut runtime = Runtime::new().unwrap();
+        let binds = runtime.block_on(session.walk(&oids::oid(root)).collect()).unwrap();
+        binds.iter().map(|b| b.value().clone()).collect()
+    }
+
+    #[test]
+    fn walk_stops_at_subtree_boundary() {
+        for version in [Version::Version1, Version::Version2].iter() {
+            let values = walk_values(*version, &[1, 3, 6, 1, 2, 1, 1]);
+            assert_eq!(values, vec![Variable::Integer(1), Variable::Integer(2)]);
+        }
+    }
+
+    #[test]
+    fn walk_stops_at_end_of_mib_view() {
+        for version in [Version::Version1, Version::Version2].iter() {
+            let values = walk_values(*version, &[1, 3, 6, 1, 2, 1]);
+            assert_eq!(values, vec![Variable::Integer(1), Variable::Integer(2), Variable::Integer(3)]);
+        }
+    }
+
+    #[test]
+    fn get_with_retry() {
+        let mut session = AsyncSession::new(responder(), Version::Version2, Community::new("public")).unwrap();
+        session.set_options(SessionOptions {
+            timeout: Duration::from_millis(200),
+            retries: 1,
+            ..SessionOptions::default()
+        });
+
+        let mut runtime = Runtime::new().unwrap();
+        let binds = runtime.block_on(session.get(&[oids::oid(oids::SYS_UP_TIME)])).unwrap();
+
+        assert_eq!(binds, vec![VarBind::new(oids::oid(oids::SYS_UP_TIME), Variable::TimeTicks(42))]);
+    }
+}
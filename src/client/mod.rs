@@ -0,0 +1,369 @@
+//! SNMP manager (command generator) sessions.
+
+use std::time::{Duration, Instant};
+
+use asn1_exp::{from_asn1, to_asn1};
+use openssl::rand::rand_bytes;
+
+use borrowed;
+use security::{self, EngineError, EngineState, KeyCache, LocalizedKeys, SaltGenerator, UsmError, UsmUser};
+use types::*;
+
+#[cfg(feature = "async")]
+pub mod future;
+pub mod sync;
+mod walk;
+
+#[cfg(feature = "async")]
+pub use self::future::{AsyncSession, WalkStream};
+pub use self::sync::{SyncSession, Walk};
+pub use error::Error;
+
+/// Largest UDP payload we are willing to receive.
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+
+/// Timeout, retry and walk policy of a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionOptions {
+    /// Time to wait for a response to each attempt.
+    pub timeout: Duration,
+    /// Number of retransmissions after the first attempt.
+    pub retries: u32,
+    /// max-repetitions of GetBulk requests issued by walks; 0 forces GetNext.
+    pub max_repetitions: u32,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        SessionOptions {
+            timeout: Duration::from_secs(1),
+            retries: 2,
+            max_repetitions: 10,
+        }
+    }
+}
+
+/// Generator of request IDs, started at a random point and kept within INTEGER32 positive range.
+#[derive(Debug, Clone)]
+pub(crate) struct RequestIds {
+    next: u32,
+}
+
+impl RequestIds {
+    pub fn new() -> Self {
+        let mut buf = [0u8; 4];
+        let next = match rand_bytes(&mut buf) {
+            Ok(()) => buf.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32),
+            Err(_) => 1,
+        };
+        RequestIds { next: next & 0x7fff_ffff }
+    }
+
+    pub fn next(&mut self) -> u32 {
+        let id = self.next;
+        self.next = (self.next + 1) & 0x7fff_ffff;
+        id
+    }
+}
+
+/// Community based (v1/v2c) message processing.
+#[derive(Debug, Clone)]
+pub(crate) struct CommunityCodec {
+    pub version: Version,
+    pub community: Community,
+}
+
+impl CommunityCodec {
+    pub fn encode(&self, pdu: Pdu) -> Result<Vec<u8>, Error> {
+        let packet = PacketV2::new(self.version, self.community.clone(), pdu);
+        to_asn1(&packet).map_err(|_| Error::Encode)
+    }
+
+    /// Decodes a datagram, returning `None` for messages not belonging to the session.
+    pub fn decode(&self, buf: &[u8]) -> Result<Option<Pdu>, Error> {
+        let packet: PacketV2 = from_asn1(buf).map_err(|_| borrowed::decode_error(buf))?;
+        if packet.version() != self.version || packet.community() != &self.community {
+            return Ok(None);
+        }
+        Ok(Some(packet.into_pdu()))
+    }
+}
+
+/// User based (v3) message processing on the non-authoritative side.
+#[derive(Debug)]
+pub(crate) struct UsmCodec {
+    user: UsmUser,
+    engine: EngineState,
+    /// Keys localized to the engine they were computed for.
+    keys: Option<(EngineID, LocalizedKeys)>,
+    key_cache: KeyCache,
+    salts: SaltGenerator,
+    msg_ids: RequestIds,
+    context_name: ContextName,
+}
+
+impl UsmCodec {
+    pub fn new(user: UsmUser) -> Result<Self, Error> {
+        Ok(UsmCodec {
+            user: user,
+            engine: EngineState::new(),
+            keys: None,
+            key_cache: KeyCache::new(),
+            salts: SaltGenerator::new().map_err(UsmError::from)?,
+            msg_ids: RequestIds::new(),
+            context_name: ContextName::new(""),
+        })
+    }
+
+    pub fn engine(&self) -> &EngineState {
+        &self.engine
+    }
+
+    pub fn set_context_name(&mut self, value: ContextName) {
+        self.context_name = value
+    }
+
+    /// Whether engine ID (and, for authenticated users, engine time) still has to be learnt.
+    pub fn needs_discovery(&self) -> bool {
+        !self.engine.is_discovered()
+            || (self.user.security_level() != SecurityLevel::NoAuthNoPriv && !self.engine.is_synchronized())
+    }
+
+    /// Builds the unauthenticated discovery request, returning its msgID.
+    pub fn encode_discovery(&mut self, request_id: u32) -> Result<(u32, Vec<u8>), Error> {
+        let msg_id = self.msg_ids.next();
+        let packet = EngineState::discovery_packet(msg_id, request_id, MAX_DATAGRAM_SIZE as u32);
+        let message = to_asn1(&packet).map_err(|_| Error::Encode)?;
+        Ok((msg_id, message))
+    }
+
+    /// Builds a request protected at the user security level, returning its msgID.
+    pub fn encode(&mut self, pdu: Pdu) -> Result<(u32, Vec<u8>), Error> {
+        let engine_id = self.engine.engine_id().cloned().ok_or(Error::Engine(EngineError::UnknownEngineID))?;
+        let keys = self.localized_keys()?;
+
+        let msg_id = self.msg_ids.next();
+        let header = HeaderData::new(
+            msg_id,
+            MAX_DATAGRAM_SIZE as u32,
+            MessageFlags::new(true, self.user.security_level()),
+            SecurityModel::UserBasedSecurityModel,
+        );
+        let mut params = SecurityParameter::new_discovery();
+        params.set_user_name(self.user.name().clone());
+        self.engine.prepare(&mut params, Instant::now());
+
+        let scoped = ScopedPdu::new(
+            ContextEngineID::new(engine_id.as_bytes().to_vec()),
+            self.context_name.clone(),
+            pdu,
+        );
+        let packet = PacketV3::new(header, params, PduV3::Scoped(scoped));
+        let message = security::usm::protect(packet, &keys, &mut self.salts)?;
+        Ok((msg_id, message))
+    }
+
+    /// Decodes a datagram, returning `None` for messages not answering `msg_id`, for
+    /// messages other than Reports below or above the user security level, for authenticated messages that do not come from the discovered engine or fail
+    /// authentication, and for messages the engine state rejects: unauthenticated Reports
+    /// may discover the engine ID but never replace it or resynchronize the clock (RFC 3414
+    /// section 3.2). The engine state is updated from every returned message.
+    pub fn decode(&mut self, buf: &[u8], msg_id: u32) -> Result<Option<Pdu>, Error> {
+        let mut packet: PacketV3 = from_asn1(buf).map_err(|_| borrowed::decode_error(buf))?;
+        if packet.msg_id() != msg_id {
+            return Ok(None);
+        }
+        if packet.flags().security_level != self.user.security_level() && !is_report(&packet) {
+            return Ok(None);
+        }
+
+        if packet.flags().is_authenticated() {
+            if self.engine.engine_id() != Some(packet.security_parameters().engine_id()) {
+                return Ok(None);
+            }
+            let keys = self.localized_keys()?;
+            if security::usm::unprotect(buf, &mut packet, &keys).is_err() {
+                return Ok(None);
+            }
+        }
+        if self.engine.handle_packet(&packet, Instant::now()).is_err() {
+            return Ok(None);
+        }
+
+        match packet.into_pdu() {
+            PduV3::Scoped(scoped) => Ok(Some(scoped.into_pdu())),
+            PduV3::Crypted(_) => Err(Error::Decode("msgData")),
+        }
+    }
+
+    /// Keys of the user localized to the discovered engine, the only one we ever localize for.
+    fn localized_keys(&mut self) -> Result<LocalizedKeys, Error> {
+        let engine_id = self.engine.engine_id().cloned().ok_or(Error::Engine(EngineError::UnknownEngineID))?;
+        if let Some((ref id, ref keys)) = self.keys {
+            if *id == engine_id {
+                return Ok(keys.clone());
+            }
+        }
+
+        let keys = self.user
+            .localize(&engine_id, &mut self.key_cache)
+            .map_err(|e| Error::Usm(UsmError::Auth(e)))?;
+        self.keys = Some((engine_id, keys.clone()));
+        Ok(keys)
+    }
+}
+
+/// Whether `packet` carries a Report, which the agent may send below the security level of
+/// the request: `usmStatsNotInTimeWindows` at authNoPriv, the other USM errors unauthenticated
+/// (RFC 3414 section 3.2). Unauthenticated Reports only surface as errors, the engine state
+/// keeps them from replacing the engine ID or the clock.
+fn is_report(packet: &PacketV3) -> bool {
+    match *packet.pdu() {
+        PduV3::Scoped(ref scoped) => match *scoped.pdu() {
+            Pdu::Report(_) => true,
+            _ => false,
+        },
+        PduV3::Crypted(_) => false,
+    }
+}
+
+pub(crate) fn request_pdu(request_type: RequestType, request_id: u32, oids: &[ObjectIdentifier]) -> Pdu {
+    let binds = oids.iter().cloned().map(VarBind::new_null).collect();
+    Pdu::new_request(request_type, request_id, 0, 0, binds)
+}
+
+/// Accepts a response matched by msgID only if it also answers request `expected`.
+pub(crate) fn check_request_id(pdu: Pdu, expected: Option<u32>) -> Result<Pdu, Error> {
+    let received = pdu.request_id();
+    if received == expected {
+        return Ok(pdu);
+    }
+    match (expected, received) {
+        (Some(expected), Some(received)) if pdu.is_response_type() => Err(Error::RequestIdMismatch {
+            expected: expected,
+            received: received,
+        }),
+        _ => Err(Error::UnexpectedPdu(pdu)),
+    }
+}
+
+/// Turns a GetResponse into its bindings or an agent error.
+pub(crate) fn response_binds(pdu: Pdu) -> Result<VarBindList, Error> {
+    match pdu {
+        Pdu::GetResponse(inner) => {
+            if inner.error_status() == ErrorStatus::NoError {
+                return Ok(inner.into_binds());
+            }
+            let index = inner.error_index();
+            let bind = if index > 0 {
+                inner.binds().get(index as usize - 1).cloned()
+            } else {
+                None
+            };
+            Err(Error::Agent {
+                status: inner.error_status(),
+                index: index.into(),
+                bind: bind,
+            })
+        }
+        pdu => Err(Error::UnexpectedPdu(pdu)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oids;
+    use security::{testing, AuthProtocol, PrivProtocol, UsmReport};
+
+    fn engine_id(last: u8) -> EngineID {
+        EngineID::new(vec![0x80, 0x00, 0x1f, 0x88, 0x04, last])
+    }
+
+    /// Authenticated GetResponse to msgID 9 from `engine_id`.
+    fn response(user: &UsmUser, engine_id: &EngineID) -> Vec<u8> {
+        let flags = MessageFlags::new(false, SecurityLevel::AuthNoPriv);
+        let pdu = Pdu::GetResponse(InnerPdu::new(1, ErrorStatus::NoError, 0, Vec::new()));
+        testing::protect(testing::packet(9, flags, engine_id.as_bytes(), 3, 1234, "user", pdu), user)
+    }
+
+    /// Report to msgID 9 from `engine_id`, authenticated for `user` unless `level` is noAuthNoPriv.
+    fn report(user: &UsmUser, level: SecurityLevel, oid: &[u64], engine_id: &EngineID) -> Vec<u8> {
+        let flags = MessageFlags::new(false, level);
+        let bind = VarBind::new(oids::oid(oid), Variable::Counter(1));
+        let pdu = Pdu::Report(InnerPdu::new(1, ErrorStatus::NoError, 0, vec![bind]));
+        let packet = testing::packet(9, flags, engine_id.as_bytes(), 3, 1234, "user", pdu);
+        if level == SecurityLevel::NoAuthNoPriv {
+            to_asn1(&packet).unwrap()
+        } else {
+            testing::protect(packet, user)
+        }
+    }
+
+    #[test]
+    fn usm_decode_discards_unauthenticated_reports() {
+        let user = UsmUser::new("user").with_auth(AuthProtocol::Sha1, b"maplesyrup");
+        let mut codec = UsmCodec::new(user.clone()).unwrap();
+        codec.engine = EngineState::with_engine_id(engine_id(1));
+
+        let unknown = report(&user, SecurityLevel::NoAuthNoPriv, oids::USM_STATS_UNKNOWN_ENGINE_IDS, &engine_id(2));
+        assert!(codec.decode(&unknown, 9).unwrap().is_none());
+        assert_eq!(codec.engine().engine_id(), Some(&engine_id(1)));
+
+        let window = report(&user, SecurityLevel::NoAuthNoPriv, oids::USM_STATS_NOT_IN_TIME_WINDOWS, &engine_id(1));
+        assert!(codec.decode(&window, 9).unwrap().is_none());
+        assert!(!codec.engine().is_synchronized());
+
+        let window = report(&user, SecurityLevel::AuthNoPriv, oids::USM_STATS_NOT_IN_TIME_WINDOWS, &engine_id(1));
+        assert!(codec.decode(&window, 9).unwrap().is_some());
+        assert_eq!(codec.engine().engine_boots(), 3);
+    }
+
+    #[test]
+    fn usm_decode_discards_other_security_levels() {
+        let user = UsmUser::new("user").with_auth(AuthProtocol::Sha1, b"maplesyrup");
+        let mut codec = UsmCodec::new(user.clone()).unwrap();
+        codec.engine = EngineState::with_engine_id(engine_id(1));
+
+        // Spoofed plaintext answer carrying the right msgID and the discovered engine ID.
+        let pdu = Pdu::GetResponse(InnerPdu::new(1, ErrorStatus::NoError, 0, Vec::new()));
+        let flags = MessageFlags::new(false, SecurityLevel::NoAuthNoPriv);
+        let spoofed = to_asn1(&testing::packet(9, flags, engine_id(1).as_bytes(), 3, 1234, "user", pdu)).unwrap();
+        assert!(codec.decode(&spoofed, 9).unwrap().is_none());
+        assert!(codec.decode(&response(&user, &engine_id(1)), 9).unwrap().is_some());
+
+        // Error Reports come unauthenticated and are returned without touching the clock.
+        let boots = codec.engine().engine_boots();
+        let unknown_user = report(&user, SecurityLevel::NoAuthNoPriv, oids::USM_STATS_UNKNOWN_USER_NAMES, &engine_id(1));
+        let pdu = codec.decode(&unknown_user, 9).unwrap().unwrap();
+        assert_eq!(UsmReport::from_pdu(&pdu), Some(UsmReport::UnknownUserName));
+        assert_eq!(codec.engine().engine_boots(), boots);
+
+        // authNoPriv answers to an authPriv user are dropped, except for the time window Report.
+        let user = user.with_privacy(PrivProtocol::Aes128, b"maplesyrup");
+        let mut codec = UsmCodec::new(user.clone()).unwrap();
+        codec.engine = EngineState::with_engine_id(engine_id(1));
+        assert!(codec.decode(&response(&user, &engine_id(1)), 9).unwrap().is_none());
+        let window = report(&user, SecurityLevel::AuthNoPriv, oids::USM_STATS_NOT_IN_TIME_WINDOWS, &engine_id(1));
+        assert!(codec.decode(&window, 9).unwrap().is_some());
+    }
+
+    #[test]
+    fn usm_decode_discards_unauthentic_messages() {
+        let user = UsmUser::new("user").with_auth(AuthProtocol::Sha1, b"maplesyrup");
+        let mut codec = UsmCodec::new(user.clone()).unwrap();
+        codec.engine = EngineState::with_engine_id(engine_id(1));
+
+        // Messages from other engines are dropped without localizing keys for them.
+        assert!(codec.decode(&response(&user, &engine_id(2)), 9).unwrap().is_none());
+        assert!(codec.keys.is_none());
+
+        let mut message = response(&user, &engine_id(1));
+        let last = message.len() - 1;
+        message[last] ^= 0x01;
+        assert!(codec.decode(&message, 9).unwrap().is_none());
+
+        message[last] ^= 0x01;
+        assert!(codec.decode(&message, 9).unwrap().is_some());
+    }
+}
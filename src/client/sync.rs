@@ -0,0 +1,499 @@
+//! Blocking session on top of `std::net::UdpSocket`.
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Instant;
+
+use client::{check_request_id, request_pdu, response_binds, CommunityCodec, Error, RequestIds, SessionOptions, UsmCodec,
+             MAX_DATAGRAM_SIZE};
+use client::walk::WalkState;
+use security::{EngineState, UsmReport, UsmUser};
+use types::*;
+
+#[derive(Debug)]
+enum Codec {
+    Community(CommunityCodec),
+    Usm(UsmCodec),
+}
+
+/// Blocking session bound to a single agent.
+#[derive(Debug)]
+pub struct SyncSession {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    codec: Codec,
+    options: SessionOptions,
+    ids: RequestIds,
+    buf: Vec<u8>,
+}
+
+impl SyncSession {
+    /// Community based v1/v2c session.
+    pub fn new(peer: SocketAddr, version: Version, community: Community) -> io::Result<Self> {
+        let codec = CommunityCodec {
+            version: version,
+            community: community,
+        };
+        Self::with_codec(peer, Codec::Community(codec))
+    }
+
+    /// User based v3 session. The engine is discovered on the first request.
+    pub fn new_v3(peer: SocketAddr, user: UsmUser) -> Result<Self, Error> {
+        let codec = Codec::Usm(UsmCodec::new(user)?);
+        Ok(Self::with_codec(peer, codec)?)
+    }
+
+    fn with_codec(peer: SocketAddr, codec: Codec) -> io::Result<Self> {
+        let local: SocketAddr = if peer.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+
+        Ok(SyncSession {
+            socket: UdpSocket::bind(local)?,
+            peer: peer,
+            codec: codec,
+            options: SessionOptions::default(),
+            ids: RequestIds::new(),
+            buf: vec![0; MAX_DATAGRAM_SIZE],
+        })
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn version(&self) -> Version {
+        match self.codec {
+            Codec::Community(ref codec) => codec.version,
+            Codec::Usm(_) => Version::Version3,
+        }
+    }
+
+    pub fn options(&self) -> &SessionOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, value: SessionOptions) {
+        self.options = value
+    }
+
+    /// State of the remote engine, for v3 sessions.
+    pub fn engine(&self) -> Option<&EngineState> {
+        match self.codec {
+            Codec::Usm(ref codec) => Some(codec.engine()),
+            Codec::Community(_) => None,
+        }
+    }
+
+    pub fn get(&mut self, oids: &[ObjectIdentifier]) -> Result<VarBindList, Error> {
+        self.request(request_pdu(RequestType::Get, 0, oids))
+    }
+
+    pub fn get_next(&mut self, oids: &[ObjectIdentifier]) -> Result<VarBindList, Error> {
+        self.request(request_pdu(RequestType::GetNext, 0, oids))
+    }
+
+    pub fn get_bulk(&mut self, non_repeaters: u32, max_repetitions: u32, oids: &[ObjectIdentifier]) -> Result<VarBindList, Error> {
+        let binds = oids.iter().cloned().map(VarBind::new_null).collect();
+        self.request(Pdu::new_bulk_request(0, non_repeaters, max_repetitions, binds))
+    }
+
+    pub fn set(&mut self, binds: VarBindList) -> Result<VarBindList, Error> {
+        self.request(Pdu::new_request(RequestType::Set, 0, 0, 0, binds))
+    }
+
+    /// Iterates over the subtree under `root`, with GetBulk on v2c/v3 and GetNext on v1.
+    pub fn walk(&mut self, root: &ObjectIdentifier) -> Walk {
+        let state = WalkState::new(root, self.version(), self.options.max_repetitions);
+        Walk {
+            session: self,
+            state: state,
+        }
+    }
+
+    /// Sends an already encoded message to the agent without waiting for a response.
+    pub(crate) fn send(&self, message: &[u8]) -> io::Result<()> {
+        self.socket.send_to(message, self.peer).map(|_| ())
+    }
+
+    /// Sends an arbitrary request PDU, replacing its request ID with a fresh one.
+    pub fn request(&mut self, mut pdu: Pdu) -> Result<VarBindList, Error> {
+        let request_id = self.ids.next();
+        pdu.set_request_id(request_id);
+
+        let response = if self.version() == Version::Version3 {
+            self.exchange_v3(pdu)?
+        } else {
+            self.exchange_v2(pdu)?
+        };
+        response_binds(response)
+    }
+
+    fn exchange_v2(&mut self, pdu: Pdu) -> Result<Pdu, Error> {
+        let request_id = pdu.request_id();
+        let codec = match self.codec {
+            Codec::Community(ref codec) => codec,
+            Codec::Usm(_) => unreachable!(),
+        };
+        let message = codec.encode(pdu)?;
+
+        exchange(&self.socket, self.peer, &self.options, &mut self.buf, &message, |buf| {
+            match codec.decode(buf) {
+                Ok(Some(pdu)) => Ok(if pdu.is_response_type() && pdu.request_id() == request_id {
+                    Some(pdu)
+                } else {
+                    None
+                }),
+                Ok(None) | Err(Error::Decode(_)) | Err(Error::Version(_)) => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn exchange_v3(&mut self, pdu: Pdu) -> Result<Pdu, Error> {
+        let request_id = pdu.request_id();
+
+        if self.usm_codec().needs_discovery() {
+            self.discover()?;
+        }
+
+        // One extra round when the agent asks us to resynchronize the engine time.
+        let mut resynchronized = false;
+        loop {
+            let (msg_id, message) = self.usm_codec().encode(pdu.clone())?;
+            let response = self.exchange_usm(msg_id, &message)?;
+
+            match UsmReport::from_pdu(&response) {
+                Some(UsmReport::NotInTimeWindow) if !resynchronized => {
+                    resynchronized = true;
+                    continue;
+                }
+                Some(report) => return Err(Error::Report(report)),
+                None => return check_request_id(response, request_id),
+            }
+        }
+    }
+
+    /// RFC 3414 section 4: learn engine ID, then engine boots and time for authenticated users.
+    fn discover(&mut self) -> Result<(), Error> {
+        let request_id = self.ids.next();
+        let (msg_id, message) = self.usm_codec().encode_discovery(request_id)?;
+        let response = self.exchange_usm(msg_id, &message)?;
+        match UsmReport::from_pdu(&response) {
+            Some(UsmReport::UnknownEngineID) => {}
+            Some(report) => return Err(Error::Report(report)),
+            None => return Err(Error::UnexpectedPdu(response)),
+        }
+
+        if self.usm_codec().needs_discovery() {
+            let pdu = Pdu::new_request(RequestType::Get, self.ids.next(), 0, 0, Vec::new());
+            let (msg_id, message) = self.usm_codec().encode(pdu)?;
+            let response = self.exchange_usm(msg_id, &message)?;
+            match UsmReport::from_pdu(&response) {
+                Some(UsmReport::NotInTimeWindow) | None => {}
+                Some(report) => return Err(Error::Report(report)),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn exchange_usm(&mut self, msg_id: u32, message: &[u8]) -> Result<Pdu, Error> {
+        let codec = match self.codec {
+            Codec::Usm(ref mut codec) => codec,
+            Codec::Community(_) => unreachable!(),
+        };
+
+        exchange(&self.socket, self.peer, &self.options, &mut self.buf, message, |buf| {
+            match codec.decode(buf, msg_id) {
+                // Undecodable or unauthentic messages are discarded (RFC 3414 section 3.2).
+                Err(Error::Decode(_)) | Err(Error::Version(_)) | Err(Error::Usm(_)) | Err(Error::Engine(_)) => Ok(None),
+                result => result,
+            }
+        })
+    }
+
+    fn usm_codec(&mut self) -> &mut UsmCodec {
+        match self.codec {
+            Codec::Usm(ref mut codec) => codec,
+            Codec::Community(_) => unreachable!(),
+        }
+    }
+}
+
+/// Iterator over a subtree, see `SyncSession::walk`.
+///
+/// Yields an error and stops if a request fails or the agent returns non-increasing OIDs.
+#[derive(Debug)]
+pub struct Walk<'a> {
+    session: &'a mut SyncSession,
+    state: WalkState,
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = Result<VarBind, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(bind) = self.state.pop() {
+                return Some(Ok(bind));
+            }
+            if self.state.is_done() {
+                return None;
+            }
+
+            let response = self.session.request(self.state.request());
+            if let Err(e) = self.state.handle_response(response) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// Sends `message` and waits for a datagram accepted by `accept`, retransmitting on timeout.
+/// Datagrams from other peers and those `accept` maps to `None` are discarded.
+fn exchange<T, F>(
+    socket: &UdpSocket,
+    peer: SocketAddr,
+    options: &SessionOptions,
+    buf: &mut [u8],
+    message: &[u8],
+    mut accept: F,
+) -> Result<T, Error>
+where
+    F: FnMut(&[u8]) -> Result<Option<T>, Error>,
+{
+    for _ in 0..options.retries + 1 {
+        socket.send_to(message, peer)?;
+        let deadline = Instant::now() + options.timeout;
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            socket.set_read_timeout(Some(deadline - now))?;
+
+            let (len, from) = match socket.recv_from(buf) {
+                Ok(received) => received,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => break,
+                Err(e) => return Err(e.into()),
+            };
+            if from != peer {
+                continue;
+            }
+            if let Some(response) = accept(&buf[..len])? {
+                return Ok(response);
+            }
+        }
+    }
+
+    Err(Error::Timeout)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use asn1_exp::{from_asn1, to_asn1};
+
+    use super::*;
+    use oids;
+    use security::{testing, AuthProtocol};
+
+    const OBJECTS: [[u64; 9]; 4] = [
+        [1, 3, 6, 1, 2, 1, 1, 1, 0],
+        [1, 3, 6, 1, 2, 1, 1, 2, 0],
+        [1, 3, 6, 1, 2, 1, 1, 3, 0],
+        [1, 3, 6, 1, 2, 1, 2, 1, 0],
+    ];
+
+    fn next_object(oid: &[u64]) -> VarBind {
+        match OBJECTS.iter().position(|o| &o[..] > oid) {
+            Some(i) => VarBind::new(oids::oid(&OBJECTS[i]), Variable::Integer(i as i32 + 1)),
+            None => VarBind::new(oids::oid(oid), Variable::EndOfMibView),
+        }
+    }
+
+    /// Agent serving `OBJECTS`, sending a stale response before each real one.
+    fn responder() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).unwrap();
+                let request: PacketV2 = from_asn1(&buf[..len]).unwrap();
+                let request_id = request.pdu().request_id().unwrap();
+
+                let binds = match *request.pdu() {
+                    Pdu::GetRequest(ref inner) => {
+                        vec![VarBind::new(inner.binds()[0].oid().clone(), Variable::Integer(7))]
+                    }
+                    Pdu::GetNextRequest(ref inner) => {
+                        vec![next_object(oids::components(inner.binds()[0].oid()))]
+                    }
+                    Pdu::GetBulkRequest(ref inner) => {
+                        let mut oid = oids::components(inner.binds()[0].oid()).to_vec();
+                        let mut binds = Vec::new();
+                        for _ in 0..inner.max_repetitions() {
+                            let bind = next_object(&oid);
+                            oid = oids::components(bind.oid()).to_vec();
+                            binds.push(bind);
+                        }
+                        binds
+                    }
+                    _ => panic!("unexpected request"),
+                };
+
+                for &(id, ref binds) in [(request_id.wrapping_sub(1), Vec::new()), (request_id, binds)].iter() {
+                    let pdu = Pdu::GetResponse(InnerPdu::new(id, ErrorStatus::NoError, 0, binds.clone()));
+                    let response = PacketV2::new(request.version(), request.community().clone(), pdu);
+                    socket.send_to(&to_asn1(&response).unwrap(), from).unwrap();
+                }
+            }
+        });
+
+        addr
+    }
+
+    fn session(version: Version) -> SyncSession {
+        let mut session = SyncSession::new(responder(), version, Community::new("public")).unwrap();
+        session.set_options(SessionOptions {
+            timeout: Duration::from_millis(500),
+            retries: 0,
+            max_repetitions: 2,
+        });
+        session
+    }
+
+    #[test]
+    fn get_discards_stale_responses() {
+        let binds = session(Version::Version2).get(&[oids::oid(oids::SYS_UP_TIME)]).unwrap();
+        assert_eq!(binds, vec![VarBind::new(oids::oid(oids::SYS_UP_TIME), Variable::Integer(7))]);
+    }
+
+    #[test]
+    fn get_discards_unknown_versions() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+            let (len, from) = socket.recv_from(&mut buf).unwrap();
+            let request: PacketV2 = from_asn1(&buf[..len]).unwrap();
+            let pdu = Pdu::GetResponse(InnerPdu::new(request.pdu().request_id().unwrap(), ErrorStatus::NoError, 0, Vec::new()));
+            let response = to_asn1(&PacketV2::new(request.version(), request.community().clone(), pdu)).unwrap();
+
+            // Short message: two byte SEQUENCE header, then the INTEGER version.
+            let mut unknown = response.clone();
+            unknown[4] = 7;
+            socket.send_to(&unknown, from).unwrap();
+            socket.send_to(&response, from).unwrap();
+        });
+
+        let mut session = SyncSession::new(addr, Version::Version2, Community::new("public")).unwrap();
+        session.set_options(SessionOptions {
+            timeout: Duration::from_millis(500),
+            retries: 0,
+            ..SessionOptions::default()
+        });
+        assert_eq!(session.get(&[oids::oid(oids::SYS_UP_TIME)]).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn walk_stops_at_subtree_boundary() {
+        for version in [Version::Version1, Version::Version2].iter() {
+            let binds: VarBindList = session(*version)
+                .walk(&oids::oid(&[1, 3, 6, 1, 2, 1, 1]))
+                .collect::<Result<_, _>>()
+                .unwrap();
+            let values: Vec<_> = binds.iter().map(|b| b.value().clone()).collect();
+            assert_eq!(values, vec![Variable::Integer(1), Variable::Integer(2), Variable::Integer(3)]);
+        }
+    }
+
+    /// v3 agent that discovers itself, then rejects every request with an unauthenticated
+    /// usmStatsWrongDigests Report, as it does for a wrong authentication password.
+    fn wrong_digest_responder() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let engine_id = [0x80, 0x00, 0x1f, 0x88, 0x04, 0x01];
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).unwrap();
+                let request: PacketV3 = from_asn1(&buf[..len]).unwrap();
+                let request_id = match *request.pdu() {
+                    PduV3::Scoped(ref scoped) => scoped.pdu().request_id().unwrap(),
+                    PduV3::Crypted(_) => panic!("unexpected encrypted request"),
+                };
+                let report = if request.security_parameters().engine_id().as_bytes().is_empty() {
+                    UsmReport::UnknownEngineID
+                } else {
+                    UsmReport::WrongDigest
+                };
+
+                let bind = VarBind::new(oids::oid(report.oid()), Variable::Counter(1));
+                let pdu = Pdu::Report(InnerPdu::new(request_id, ErrorStatus::NoError, 0, vec![bind]));
+                let flags = MessageFlags::new(false, SecurityLevel::NoAuthNoPriv);
+                let response = testing::packet(request.msg_id(), flags, &engine_id, 3, 1234, "user", pdu);
+                socket.send_to(&to_asn1(&response).unwrap(), from).unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn get_reports_unauthenticated_usm_errors() {
+        let user = UsmUser::new("user").with_auth(AuthProtocol::Sha1, b"maplesyrup");
+        let mut session = SyncSession::new_v3(wrong_digest_responder(), user).unwrap();
+        session.set_options(SessionOptions {
+            timeout: Duration::from_millis(500),
+            retries: 0,
+            ..SessionOptions::default()
+        });
+
+        match session.get(&[oids::oid(oids::SYS_UP_TIME)]) {
+            Err(Error::Report(UsmReport::WrongDigest)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(session.engine().unwrap().is_discovered());
+        assert!(!session.engine().unwrap().is_synchronized());
+    }
+
+    #[test]
+    fn request_id_mismatch() {
+        let response = Pdu::GetResponse(InnerPdu::new(5, ErrorStatus::NoError, 0, Vec::new()));
+        match check_request_id(response.clone(), Some(6)) {
+            Err(Error::RequestIdMismatch { expected: 6, received: 5 }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match check_request_id(Pdu::Trap(InnerPdu::new(5, ErrorStatus::NoError, 0, Vec::new())), Some(6)) {
+            Err(Error::UnexpectedPdu(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(check_request_id(response.clone(), Some(5)).unwrap(), response);
+    }
+
+    #[test]
+    fn timeout() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut session = SyncSession::new(silent.local_addr().unwrap(), Version::Version2, Community::new("public")).unwrap();
+        session.set_options(SessionOptions {
+            timeout: Duration::from_millis(50),
+            retries: 1,
+            ..SessionOptions::default()
+        });
+
+        match session.get(&[oids::oid(oids::SYS_UP_TIME)]) {
+            Err(Error::Timeout) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
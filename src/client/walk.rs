@@ -0,0 +1,131 @@
+//! Transport independent subtree walk logic shared by the sessions.
+
+use std::collections::VecDeque;
+
+use client::Error;
+use oids;
+use types::*;
+
+/// Walk progress: which request to send next and which bindings are ready to be yielded.
+#[derive(Debug, Clone)]
+pub(crate) struct WalkState {
+    prefix: Vec<u64>,
+    last: ObjectIdentifier,
+    bulk: bool,
+    max_repetitions: u32,
+    ready: VecDeque<VarBind>,
+    done: bool,
+}
+
+impl WalkState {
+    /// GetBulk is used for v2c/v3, GetNext for v1.
+    pub fn new(root: &ObjectIdentifier, version: Version, max_repetitions: u32) -> Self {
+        WalkState {
+            prefix: oids::components(root).to_vec(),
+            last: root.clone(),
+            bulk: version != Version::Version1 && max_repetitions > 0,
+            max_repetitions: max_repetitions,
+            ready: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<VarBind> {
+        self.ready.pop_front()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn finish(&mut self) {
+        self.done = true;
+        self.ready.clear();
+    }
+
+    pub fn request(&self) -> Pdu {
+        let binds = vec![VarBind::new_null(self.last.clone())];
+        if self.bulk {
+            Pdu::new_bulk_request(0, 0, self.max_repetitions, binds)
+        } else {
+            Pdu::new_request(RequestType::GetNext, 0, 0, 0, binds)
+        }
+    }
+
+    pub fn handle_response(&mut self, response: Result<VarBindList, Error>) -> Result<(), Error> {
+        let binds = match response {
+            Ok(binds) => binds,
+            // v1 agents signal the end of the MIB view with noSuchName.
+            Err(Error::Agent { status: ErrorStatus::NoSuchName, .. }) if !self.bulk => {
+                self.done = true;
+                return Ok(());
+            }
+            Err(e) => {
+                self.finish();
+                return Err(e);
+            }
+        };
+
+        if binds.is_empty() {
+            self.done = true;
+        }
+
+        for bind in binds {
+            if *bind.value() == Variable::EndOfMibView || !oids::components(bind.oid()).starts_with(&self.prefix) {
+                self.done = true;
+                break;
+            }
+            if oids::components(bind.oid()) <= oids::components(&self.last) {
+                let previous = self.last.clone();
+                self.finish();
+                return Err(Error::OidNotIncreasing {
+                    previous: previous,
+                    received: bind.oid().clone(),
+                });
+            }
+            self.last = bind.oid().clone();
+            self.ready.push_back(bind);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind(components: &[u64]) -> VarBind {
+        VarBind::new(oids::oid(components), Variable::Integer(0))
+    }
+
+    #[test]
+    fn stops_at_boundary() {
+        let mut walk = WalkState::new(&oids::oid(&[1, 3, 6, 1, 2, 1, 1]), Version::Version2, 3);
+        assert!(walk.request().request_type() == Some(RequestType::GetBulk));
+
+        walk.handle_response(Ok(vec![
+            bind(&[1, 3, 6, 1, 2, 1, 1, 1, 0]),
+            bind(&[1, 3, 6, 1, 2, 1, 1, 2, 0]),
+            bind(&[1, 3, 6, 1, 2, 1, 2, 1, 0]),
+        ])).unwrap();
+
+        assert!(walk.is_done());
+        assert_eq!(walk.pop(), Some(bind(&[1, 3, 6, 1, 2, 1, 1, 1, 0])));
+        assert_eq!(walk.pop(), Some(bind(&[1, 3, 6, 1, 2, 1, 1, 2, 0])));
+        assert_eq!(walk.pop(), None);
+    }
+
+    #[test]
+    fn detects_non_increasing_oids() {
+        let mut walk = WalkState::new(&oids::oid(&[1, 3, 6, 1, 2, 1, 1]), Version::Version1, 3);
+        assert!(walk.request().request_type() == Some(RequestType::GetNext));
+
+        walk.handle_response(Ok(vec![bind(&[1, 3, 6, 1, 2, 1, 1, 2, 0])])).unwrap();
+        match walk.handle_response(Ok(vec![bind(&[1, 3, 6, 1, 2, 1, 1, 1, 0])])) {
+            Err(Error::OidNotIncreasing { .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(walk.is_done());
+    }
+}
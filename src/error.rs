@@ -0,0 +1,162 @@
+//! Error type shared by sessions, notification originators and message processing.
+
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::io;
+
+use security::{EngineError, UsmError, UsmReport};
+use types::*;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// No matching response after all retries.
+    Timeout,
+    /// Request could not be serialized.
+    Encode,
+    /// Message could not be deserialized; names the component that failed by its ASN.1
+    /// identifier in RFC 1157/3412/3416, e.g. "request-id" or "msgSecurityParameters", or
+    /// the whole "Message" or "SNMPv3Message" when its outer SEQUENCE is malformed.
+    Decode(&'static str),
+    /// Message version is not one of v1, v2c and v3.
+    Version(VersionError),
+    /// Agent answered with a non-zero error status.
+    Agent {
+        status: ErrorStatus,
+        index: ErrorIndex,
+        /// Binding pointed to by the error index, if any.
+        bind: Option<VarBind>,
+    },
+    /// Response to the message carries the request ID of another request.
+    RequestIdMismatch { expected: u32, received: u32 },
+    /// Agent answered with a PDU other than GetResponse.
+    UnexpectedPdu(Pdu),
+    /// Walked agent returned an OID not greater than the previous one.
+    OidNotIncreasing {
+        previous: ObjectIdentifier,
+        received: ObjectIdentifier,
+    },
+    /// Agent answered with a USM Report PDU: the remote engine rejected the request. Such
+    /// Reports are mostly unauthenticated, so they are returned but never trusted otherwise.
+    Report(UsmReport),
+    /// Local USM processing failed: keys could not be localized or the request protected.
+    /// Responses failing authentication or decryption are discarded rather than reported,
+    /// as RFC 3414 section 3.2 requires, so rejections by the agent always come as `Report`.
+    Usm(UsmError),
+    /// Remote engine not discovered yet, or its response is outside of the time window.
+    Engine(EngineError),
+}
+
+impl From<UsmError> for Error {
+    fn from(e: UsmError) -> Self {
+        Error::Usm(e)
+    }
+}
+
+impl From<EngineError> for Error {
+    fn from(e: EngineError) -> Self {
+        Error::Engine(e)
+    }
+}
+
+impl From<VersionError> for Error {
+    fn from(e: VersionError) -> Self {
+        Error::Version(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Decode(component) => write!(f, "failed to decode {}", component),
+            Error::Version(ref e) => e.fmt(f),
+            Error::Agent { status, index, bind: Some(ref bind) } => {
+                write!(f, "agent error {} at index {}: {}", status, u32::from(index), bind)
+            }
+            Error::Agent { status, index, bind: None } => {
+                write!(f, "agent error {} at index {}", status, u32::from(index))
+            }
+            Error::RequestIdMismatch { expected, received } => {
+                write!(f, "expected response to request {}, received {}", expected, received)
+            }
+            Error::OidNotIncreasing { ref previous, ref received } => {
+                write!(f, "OID {} received after {}", received, previous)
+            }
+            Error::Report(report) => write!(f, "agent reported {}", report),
+            Error::Usm(ref e) => e.fmt(f),
+            Error::Engine(ref e) => e.fmt(f),
+            _ => f.write_str(self.description()),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_) => "I/O error",
+            Error::Timeout => "request timed out",
+            Error::Encode => "failed to encode request",
+            Error::Decode(_) => "failed to decode message",
+            Error::Version(ref e) => e.description(),
+            Error::Agent { .. } => "agent error",
+            Error::RequestIdMismatch { .. } => "request ID mismatch",
+            Error::UnexpectedPdu(_) => "unexpected PDU",
+            Error::OidNotIncreasing { .. } => "OID not increasing",
+            Error::Report(_) => "agent reported USM error",
+            Error::Usm(ref e) => e.description(),
+            Error::Engine(ref e) => e.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::Version(ref e) => Some(e),
+            Error::Usm(ref e) => Some(e),
+            Error::Engine(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local() -> Result<(), Error> {
+        Err::<(), _>(UsmError::UnsupportedSecurityLevel)?;
+        Ok(())
+    }
+
+    fn engine() -> Result<(), Error> {
+        Err::<(), _>(EngineError::NotInTimeWindow)?;
+        Ok(())
+    }
+
+    #[test]
+    fn usm_errors() {
+        match local() {
+            Err(ref e @ Error::Usm(UsmError::UnsupportedSecurityLevel)) => {
+                assert_eq!(e.to_string(), "unsupported security level");
+                assert!(e.cause().is_some());
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match engine() {
+            Err(ref e @ Error::Engine(EngineError::NotInTimeWindow)) => assert!(e.cause().is_some()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let report = Error::Report(UsmReport::WrongDigest);
+        assert_eq!(report.to_string(), "agent reported usmStatsWrongDigests");
+        assert_eq!(report.description(), "agent reported USM error");
+        assert!(report.cause().is_none());
+    }
+}
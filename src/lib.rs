@@ -2,6 +2,7 @@
 #![cfg_attr(test, feature(plugin))]
 // #![cfg_attr(test, plugin(quickcheck_macros))]
 #![feature(conservative_impl_trait)]
+#![feature(try_from)]
 
 #[cfg(test)]
 extern crate test;
@@ -13,9 +14,32 @@ extern crate asn1_exp;
 #[macro_use]
 extern crate serde_derive;
 
+extern crate openssl;
+
+#[cfg(feature = "async")]
+#[macro_use]
+extern crate futures;
+#[cfg(feature = "async")]
+extern crate tokio;
+
 mod types;
 mod asn1;
+mod ber;
+mod borrowed;
+mod error;
+mod message;
 
+pub mod agent;
+pub mod client;
+pub mod mib;
+pub mod notification;
+pub mod oids;
+pub mod security;
+pub mod trap;
+
+pub use borrowed::{OidComponents, PacketRef, VarBindRef, VarBindsRef, VariableRef};
+pub use error::Error;
+pub use message::Message;
 pub use types::*;
 
 #[cfg(test)]
@@ -24,6 +48,8 @@ mod tests {
 
     use super::*;
 
+    use std::convert::TryFrom;
+
     use asn1_exp::{to_asn1, from_asn1, Asn1Serialize, Asn1Deserialize};
     use asn1_exp::der;
 
@@ -43,6 +69,249 @@ mod tests {
         assert_eq!(p, ser_deser(&p))
     }
 
+    #[test]
+    fn version() {
+        for &(wire, version) in [(0u8, Version::Version1), (1, Version::Version2), (3, Version::Version3)].iter() {
+            assert_eq!(Version::try_from(wire), Ok(version));
+            assert_eq!(Version::try_from(wire as i64), Ok(version));
+            assert_eq!(u8::from(version), wire);
+        }
+        assert_eq!(Version::try_from(2u8), Err(VersionError::Unsupported(2)));
+        assert_eq!(Version::try_from(4u8), Err(VersionError::Invalid(4)));
+        assert_eq!(Version::try_from(-1i64), Err(VersionError::Invalid(-1)));
+
+        let p = PacketV2::new(Version::Version2, Community::new("test"), Pdu::new_empty_request(RequestType::Get));
+        let mut message = to_asn1(&p).unwrap();
+        // Version INTEGER right after the outer SEQUENCE header.
+        assert_eq!(&message[2..5], &[0x02, 0x01, 0x01]);
+        let mut errors = Vec::new();
+        for &(wire, error) in [(2u8, VersionError::Unsupported(2)), (4, VersionError::Invalid(4)), (0xff, VersionError::Invalid(-1))].iter() {
+            message[4] = wire;
+            let decoded: Result<PacketV2, _> = from_asn1(&message);
+            errors.push(format!("{:?}", decoded.unwrap_err()));
+            match Message::decode(&message) {
+                Err(Error::Version(e)) => assert_eq!(e, error),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        // Version 2 is known but unsupported, not just out of range.
+        assert!(errors[0] != errors[1]);
+    }
+
+    #[test]
+    fn exception_values() {
+        for value in vec![Variable::NoSuchObject, Variable::NoSuchInstance, Variable::EndOfMibView] {
+            let bind = VarBind::new(oids::oid(oids::SYS_UP_TIME), value);
+            assert_eq!(bind, ser_deser(&bind));
+        }
+    }
+
+    #[test]
+    fn bulk_request() {
+        let oid = |last: u64| oids::oid(&[1, 3, 6, 1, 2, 1, 2, 2, 1, last]);
+        let pdu = Pdu::new_bulk_request(1, 1, 2, vec![VarBind::new_null(oid(0)), VarBind::new_null(oid(2)), VarBind::new_null(oid(10))]);
+        let p = PacketV2::new(Version::Version2, Community::new("public"), pdu.clone());
+        assert_eq!(p, ser_deser(&p));
+
+        // Same wire layout as the other PDUs: non-repeaters and max-repetitions in place of error status and index.
+        let bind = |last: u8| vec![0x30, 0x0d, 0x06, 0x09, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x02, 0x02, 0x01, last, 0x05, 0x00];
+        let mut expected = vec![
+            0x30, 0x45, 0x02, 0x01, 0x01, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c',
+            0xa5, 0x38, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02, 0x30, 0x2d,
+        ];
+        for &last in &[0, 2, 10] {
+            expected.extend(bind(last));
+        }
+        assert_eq!(to_asn1(&p).unwrap(), expected);
+
+        let response: VarBindList = vec![
+            VarBind::new(oid(1), Variable::Integer(1)),
+            VarBind::new(oid(3), Variable::Integer(2)),
+            VarBind::new(oid(11), Variable::Integer(3)),
+            VarBind::new(oid(4), Variable::Integer(4)),
+            VarBind::new(oid(12), Variable::Integer(5)),
+        ];
+        let bulk = match pdu {
+            Pdu::GetBulkRequest(bulk) => bulk,
+            _ => unreachable!(),
+        };
+        let (non_repeaters, rows) = bulk.split_response(&response);
+        assert_eq!(non_repeaters, &response[..1]);
+        assert_eq!(rows, vec![&response[1..3], &response[3..5]]);
+    }
+
+    fn v3_fixture() -> PacketV3 {
+        let flags = MessageFlags::new(true, SecurityLevel::NoAuthNoPriv);
+        security::testing::packet(0x1234, flags, b"", 0, 0, "", Pdu::new_empty_request(RequestType::Get))
+    }
+
+    #[test]
+    fn packet_v3() {
+        let p = v3_fixture();
+
+        assert_eq!(p.version(), Version::Version3);
+        assert_eq!(p, ser_deser(&p))
+    }
+
+    #[test]
+    fn packet_v3_header_encoding() {
+        let buf = to_asn1(&v3_fixture()).unwrap();
+
+        // msgVersion INTEGER 3 followed by msgGlobalData SEQUENCE.
+        assert_eq!(&buf[2..5], &[0x02, 0x01, 0x03]);
+        assert_eq!(
+            &buf[5..22],
+            &[
+                0x30, 0x0f,
+                0x02, 0x02, 0x12, 0x34,
+                0x02, 0x03, 0x00, 0xff, 0xe3,
+                0x04, 0x01, 0x04,
+                0x02, 0x01, 0x03,
+            ]
+        );
+    }
+
+    // net-snmp discovery request: empty noAuthNoPriv GetRequest, reportable.
+    const V3_DISCOVERY: &[u8] = &[
+        0x30, 0x3e,
+        0x02, 0x01, 0x03,
+        0x30, 0x11,
+        0x02, 0x04, 0x1a, 0x2b, 0x3c, 0x4d,
+        0x02, 0x03, 0x00, 0xff, 0xe3,
+        0x04, 0x01, 0x04,
+        0x02, 0x01, 0x03,
+        0x04, 0x10,
+        0x30, 0x0e,
+        0x04, 0x00, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00, 0x04, 0x00,
+        0x30, 0x14,
+        0x04, 0x00, 0x04, 0x00,
+        0xa0, 0x0e,
+        0x02, 0x04, 0x0c, 0x0f, 0xfe, 0xe0,
+        0x02, 0x01, 0x00, 0x02, 0x01, 0x00,
+        0x30, 0x00,
+    ];
+
+    #[test]
+    fn packet_v3_captured() {
+        let mut expected = v3_fixture();
+        expected.header_mut().set_msg_id(0x1a2b3c4d);
+        if let PduV3::Scoped(ref mut scoped) = *expected.pdu_mut() {
+            scoped.set_pdu(Pdu::new_request(RequestType::Get, 0x0c0ffee0, 0, 0, Vec::new()));
+        }
+
+        let p: PacketV3 = from_asn1(V3_DISCOVERY).unwrap();
+        assert_eq!(p, expected);
+        assert_eq!(&to_asn1(&p).unwrap()[..], V3_DISCOVERY);
+    }
+
+    #[test]
+    fn packet_v3_rejects_other_versions() {
+        let mut message = V3_DISCOVERY.to_vec();
+        for &version in [0u8, 1, 2, 4].iter() {
+            message[4] = version;
+            let decoded: Result<PacketV3, _> = from_asn1(&message);
+            assert!(decoded.is_err(), "version {}", version);
+        }
+    }
+
+    #[test]
+    fn message_flags_encoding() {
+        let cases = [
+            (false, SecurityLevel::NoAuthNoPriv, 0x00),
+            (true, SecurityLevel::NoAuthNoPriv, 0x04),
+            (false, SecurityLevel::AuthNoPriv, 0x01),
+            (true, SecurityLevel::AuthNoPriv, 0x05),
+            (false, SecurityLevel::AuthPriv, 0x03),
+            (true, SecurityLevel::AuthPriv, 0x07),
+        ];
+        for &(reportable, level, encoded) in cases.iter() {
+            let flags = MessageFlags::new(reportable, level);
+            assert_eq!(to_asn1(&flags).unwrap(), vec![0x04, 0x01, encoded]);
+            assert_eq!(ser_deser(&flags), flags);
+        }
+    }
+
+    #[test]
+    fn packet_v3_auth_parameters_range() {
+        let mut p = v3_fixture();
+        p.header_mut().set_flags(MessageFlags::new(true, SecurityLevel::AuthNoPriv));
+        p.security_parameters_mut().set_engine_id(EngineID::new(vec![0x80, 0x00, 0x1f, 0x88, 0x04]));
+        p.security_parameters_mut().set_user_name(UserName::new("admin"));
+        p.security_parameters_mut().set_auth_parameters(AuthenticationParameter::new(vec![0xaa; 12]));
+
+        let buf = to_asn1(&p).unwrap();
+        let range = PacketV3::auth_parameters_range(&buf).unwrap();
+
+        assert_eq!(range.len(), 12);
+        assert_eq!(&buf[range.start - 2..range.start], &[0x04, 0x0c]);
+        assert!(buf[range].iter().all(|b| *b == 0xaa));
+        assert_eq!(PacketV3::auth_parameters_range(V3_DISCOVERY), Some(40..40));
+    }
+
+    const V1_TRAP: &[u8] = &[
+        0x30, 0x39, 0x02, 0x01, 0x00, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c',
+        0xa4, 0x2c,
+        0x06, 0x07, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x3e,
+        0x40, 0x04, 0xc0, 0xa8, 0x01, 0x0a,
+        0x02, 0x01, 0x06,
+        0x02, 0x01, 0x05,
+        0x43, 0x03, 0x01, 0xe2, 0x40,
+        0x30, 0x10, 0x30, 0x0e,
+        0x06, 0x09, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x3e, 0x02, 0x01,
+        0x02, 0x01, 0x01,
+    ];
+
+    #[test]
+    fn trap_v1_captured() {
+        let p: PacketV2 = from_asn1(V1_TRAP).unwrap();
+        assert_eq!(p.version(), Version::Version1);
+        assert_eq!(p.pdu().request_id(), None);
+
+        let trap = match *p.pdu() {
+            Pdu::TrapV1(ref trap) => trap,
+            ref other => panic!("unexpected PDU: {:?}", other),
+        };
+        assert_eq!(trap.enterprise(), &oids::oid(&[1, 3, 6, 1, 4, 1, 318]));
+        assert_eq!(trap.agent_addr(), ::std::net::Ipv4Addr::new(192, 168, 1, 10));
+        assert_eq!(trap.generic_trap(), GenericTrap::EnterpriseSpecific);
+        assert_eq!(trap.specific_trap(), 5);
+        assert_eq!(trap.time_stamp(), 123456);
+        assert_eq!(
+            trap.binds(),
+            &vec![VarBind::new(oids::oid(&[1, 3, 6, 1, 4, 1, 318, 2, 1]), Variable::Integer(1))]
+        );
+
+        assert_eq!(&to_asn1(&p).unwrap()[..], V1_TRAP);
+    }
+
+    #[test]
+    fn error_status() {
+        for code in 0..20 {
+            let status = ErrorStatus::from(code);
+            assert_eq!(u32::from(status), code);
+            assert_eq!(if let ErrorStatus::Unknown(_) = status { true } else { false }, code > 18);
+            assert_eq!(status.is_v1(), code <= 5);
+            assert!(status.to_v1().is_v1());
+        }
+        match ErrorStatus::from(19) {
+            ErrorStatus::Unknown(status) => assert_eq!(status.value(), 19),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(ErrorStatus::InconsistentName.to_string(), "inconsistentName");
+        assert_eq!(ErrorStatus::from(19).to_string(), "unknown(19)");
+
+        assert_eq!(ErrorStatus::ReadOnly.to_v1(), ErrorStatus::ReadOnly);
+        assert_eq!(ErrorStatus::WrongLength.to_v1(), ErrorStatus::BadValue);
+        assert_eq!(ErrorStatus::AuthorizationError.to_v1(), ErrorStatus::NoSuchName);
+        assert_eq!(ErrorStatus::ResourceUnavailable.to_v1(), ErrorStatus::GenErr);
+        assert_eq!(ErrorStatus::from(42).to_v1(), ErrorStatus::GenErr);
+
+        let mut response = InnerPdu::new(7, ErrorStatus::NoCreation, 1, Vec::new());
+        assert_eq!(ser_deser(&response).error_status(), ErrorStatus::NoCreation);
+        response.set_error_status(ErrorStatus::from(99));
+        assert_eq!(ser_deser(&response).error_status(), ErrorStatus::from(99));
+    }
+
     #[bench]
     fn packet_serialize_bench(b: &mut test::Bencher) {
         let p = PacketV2::new(Version::Version1, Community::new("test"), Pdu::new_empty_request(RequestType::Get));
@@ -63,4 +332,36 @@ mod tests {
             PacketV2::asn1_deserialize(deserializer).unwrap()
         })
     }
+
+    fn trap_v2c() -> Vec<u8> {
+        let binds = vec![
+            VarBind::new(oids::oid(oids::SYS_UP_TIME), Variable::TimeTicks(123456)),
+            VarBind::new(oids::oid(oids::SNMP_TRAP_OID), Variable::Oid(oids::oid(&[1, 3, 6, 1, 6, 3, 1, 1, 5, 3]))),
+            VarBind::new(oids::oid(&[1, 3, 6, 1, 2, 1, 2, 2, 1, 1, 3]), Variable::Integer(3)),
+            VarBind::new(oids::oid(&[1, 3, 6, 1, 2, 1, 2, 2, 1, 2, 3]), Variable::OctetString(OctetString::from(b"eth0".to_vec()))),
+            VarBind::new(oids::oid(&[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 6, 3]), Variable::Counter64(1 << 40)),
+        ];
+        let pdu = Pdu::Trap(InnerPdu::new(1, ErrorStatus::NoError, 0, binds));
+        to_asn1(&PacketV2::new(Version::Version2, Community::new("public"), pdu)).unwrap()
+    }
+
+    #[bench]
+    fn trap_deserialize_bench(b: &mut test::Bencher) {
+        let buf = trap_v2c();
+
+        b.iter(|| {
+            let deserializer = der::Deserializer::new(&buf[..]);
+            PacketV2::asn1_deserialize(deserializer).unwrap()
+        })
+    }
+
+    #[bench]
+    fn trap_ref_bench(b: &mut test::Bencher) {
+        let buf = trap_v2c();
+
+        b.iter(|| {
+            let packet = PacketRef::parse(&buf).unwrap();
+            packet.binds().map(|bind| bind.unwrap().value().unwrap()).count()
+        })
+    }
 }
\ No newline at end of file
@@ -0,0 +1,158 @@
+//! Messages of any SNMP version, for code that only learns the version on receipt.
+
+use std::convert::TryFrom;
+
+use asn1_exp::{from_asn1, to_asn1};
+
+use ber;
+use borrowed;
+use error::Error;
+use types::*;
+
+/// Message of any version, the packet layout being picked by the version field.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Message {
+    Version1(PacketV2),
+    Version2(PacketV2),
+    Version3(PacketV3),
+}
+
+impl Message {
+    /// Decodes a message of any supported version.
+    pub fn decode(buf: &[u8]) -> Result<Message, Error> {
+        let message = match Message::peek_version(buf)? {
+            Version::Version1 => from_asn1(buf).map(Message::Version1),
+            Version::Version2 => from_asn1(buf).map(Message::Version2),
+            Version::Version3 => from_asn1(buf).map(Message::Version3),
+        };
+        message.map_err(|_| borrowed::decode_error(buf))
+    }
+
+    /// Version of an encoded message, read without decoding the rest of it.
+    pub fn peek_version(buf: &[u8]) -> Result<Version, Error> {
+        let version = ber::Reader::new(buf)
+            .expect(ber::TAG_SEQUENCE)
+            .and_then(|message| message.reader().expect(ber::TAG_INTEGER))
+            .and_then(|version| ber::decode_integer(version.value))
+            .ok_or(Error::Decode("msgVersion"))?;
+        Ok(Version::try_from(version)?)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let message = match *self {
+            Message::Version1(ref packet) | Message::Version2(ref packet) => to_asn1(packet),
+            Message::Version3(ref packet) => to_asn1(packet),
+        };
+        message.map_err(|_| Error::Encode)
+    }
+
+    pub fn version(&self) -> Version {
+        match *self {
+            Message::Version1(_) => Version::Version1,
+            Message::Version2(_) => Version::Version2,
+            Message::Version3(_) => Version::Version3,
+        }
+    }
+
+    /// PDU of the message, `None` for encrypted v3 messages.
+    pub fn pdu(&self) -> Option<&Pdu> {
+        match *self {
+            Message::Version1(ref packet) | Message::Version2(ref packet) => Some(packet.pdu()),
+            Message::Version3(ref packet) => match *packet.pdu() {
+                PduV3::Scoped(ref scoped) => Some(scoped.pdu()),
+                PduV3::Crypted(_) => None,
+            },
+        }
+    }
+
+    /// Request ID of the PDU, `None` for encrypted v3 messages and v1 traps.
+    pub fn request_id(&self) -> Option<u32> {
+        self.pdu().and_then(Pdu::request_id)
+    }
+}
+
+/// Fails for a community based packet claiming version 3, which has no such layout.
+impl TryFrom<PacketV2> for Message {
+    type Error = VersionError;
+
+    fn try_from(packet: PacketV2) -> Result<Message, VersionError> {
+        match packet.version() {
+            Version::Version1 => Ok(Message::Version1(packet)),
+            Version::Version2 => Ok(Message::Version2(packet)),
+            Version::Version3 => Err(VersionError::NotCommunityBased),
+        }
+    }
+}
+
+impl From<PacketV3> for Message {
+    fn from(packet: PacketV3) -> Self {
+        Message::Version3(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oids;
+    use security::EngineState;
+
+    #[test]
+    fn round_trip() {
+        let pdu = Pdu::new_request(RequestType::Get, 42, 0, 0, Vec::new());
+        let messages = vec![
+            Message::try_from(PacketV2::new(Version::Version1, Community::new("public"), pdu.clone())).unwrap(),
+            Message::try_from(PacketV2::new(Version::Version2, Community::new("public"), pdu)).unwrap(),
+            Message::from(EngineState::discovery_packet(7, 42, 1500)),
+        ];
+
+        for (message, &version) in messages.iter().zip([Version::Version1, Version::Version2, Version::Version3].iter()) {
+            assert_eq!(message.version(), version);
+            assert_eq!(message.request_id(), Some(42));
+
+            let encoded = message.encode().unwrap();
+            assert_eq!(Message::peek_version(&encoded).unwrap(), version);
+            assert_eq!(&Message::decode(&encoded).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn invalid_version() {
+        let pdu = Pdu::new_empty_request(RequestType::Get);
+        let mut encoded = Message::try_from(PacketV2::new(Version::Version2, Community::new("public"), pdu.clone()))
+            .unwrap()
+            .encode()
+            .unwrap();
+        encoded[4] = 2;
+        match Message::decode(&encoded) {
+            Err(Error::Version(VersionError::Unsupported(2))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match Message::decode(&[0x30, 0x03, 0x04, 0x01, 0x00]) {
+            Err(Error::Decode("msgVersion")) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let packet = PacketV2::new(Version::Version3, Community::new("public"), pdu);
+        assert_eq!(Message::try_from(packet), Err(VersionError::NotCommunityBased));
+    }
+
+    #[test]
+    fn failing_component() {
+        let bind = VarBind::new(oids::oid(oids::SYS_UP_TIME), Variable::Integer(5));
+        let pdu = Pdu::GetResponse(InnerPdu::new(1, ErrorStatus::NoError, 0, vec![bind]));
+        let mut encoded = Message::try_from(PacketV2::new(Version::Version2, Community::new("public"), pdu))
+            .unwrap()
+            .encode()
+            .unwrap();
+
+        // Integer(5) is encoded last as 02 01 05, retag it with an unassigned application tag.
+        let len = encoded.len();
+        assert_eq!(&encoded[len - 3..], &[0x02, 0x01, 0x05]);
+        encoded[len - 3] = 0x47;
+        match Message::decode(&encoded) {
+            Err(Error::Decode("value")) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Modules defining the SMI itself, which are made of ASN.1 macros rather than
+//! definitions the parser understands. Files defining them are ignored by `Mib`.
+
+use super::{BaseType, Mib, Node, NodeKind, Scope, Syntax};
+use oids;
+
+const SMI_NODES: &[(&str, &[u64])] = &[
+    ("ccitt", &[0]),
+    ("zeroDotZero", &[0, 0]),
+    ("iso", &[1]),
+    ("org", &[1, 3]),
+    ("dod", &[1, 3, 6]),
+    ("internet", &[1, 3, 6, 1]),
+    ("directory", &[1, 3, 6, 1, 1]),
+    ("mgmt", &[1, 3, 6, 1, 2]),
+    ("mib-2", &[1, 3, 6, 1, 2, 1]),
+    ("transmission", &[1, 3, 6, 1, 2, 1, 10]),
+    ("experimental", &[1, 3, 6, 1, 3]),
+    ("private", &[1, 3, 6, 1, 4]),
+    ("enterprises", &[1, 3, 6, 1, 4, 1]),
+    ("security", &[1, 3, 6, 1, 5]),
+    ("snmpV2", &[1, 3, 6, 1, 6]),
+    ("snmpDomains", &[1, 3, 6, 1, 6, 1]),
+    ("snmpProxys", &[1, 3, 6, 1, 6, 2]),
+    ("snmpModules", &[1, 3, 6, 1, 6, 3]),
+    ("joint-iso-ccitt", &[2]),
+];
+
+/// Nodes RFC 1155 defines, a subset of SNMPv2-SMI ones.
+const V1_NODES: &[&str] = &[
+    "iso",
+    "org",
+    "dod",
+    "internet",
+    "directory",
+    "mgmt",
+    "experimental",
+    "private",
+    "enterprises",
+];
+
+const SMI_TYPES: &[(&str, BaseType)] = &[
+    ("Integer32", BaseType::Integer),
+    ("Unsigned32", BaseType::Unsigned32),
+    ("Counter32", BaseType::Counter32),
+    ("Gauge32", BaseType::Gauge32),
+    ("Counter64", BaseType::Counter64),
+    ("TimeTicks", BaseType::TimeTicks),
+    ("IpAddress", BaseType::IpAddress),
+    ("Opaque", BaseType::Opaque),
+];
+
+const V1_TYPES: &[(&str, BaseType)] = &[
+    ("Counter", BaseType::Counter32),
+    ("Gauge", BaseType::Gauge32),
+    ("TimeTicks", BaseType::TimeTicks),
+    ("IpAddress", BaseType::IpAddress),
+    ("NetworkAddress", BaseType::NetworkAddress),
+    ("Opaque", BaseType::Opaque),
+];
+
+/// Textual conventions of RFC 2579, without their descriptions.
+const SNMPV2_TC: &str = r#"
+SNMPv2-TC DEFINITIONS ::= BEGIN
+IMPORTS TimeTicks FROM SNMPv2-SMI;
+
+DisplayString ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "255a" STATUS current SYNTAX OCTET STRING (SIZE (0..255))
+PhysAddress ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "1x:" STATUS current SYNTAX OCTET STRING
+MacAddress ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "1x:" STATUS current SYNTAX OCTET STRING (SIZE (6))
+TruthValue ::= TEXTUAL-CONVENTION
+    STATUS current SYNTAX INTEGER { true(1), false(2) }
+TestAndIncr ::= TEXTUAL-CONVENTION
+    STATUS current SYNTAX INTEGER (0..2147483647)
+AutonomousType ::= TEXTUAL-CONVENTION
+    STATUS current SYNTAX OBJECT IDENTIFIER
+InstancePointer ::= TEXTUAL-CONVENTION
+    STATUS obsolete SYNTAX OBJECT IDENTIFIER
+VariablePointer ::= TEXTUAL-CONVENTION
+    STATUS current SYNTAX OBJECT IDENTIFIER
+RowPointer ::= TEXTUAL-CONVENTION
+    STATUS current SYNTAX OBJECT IDENTIFIER
+RowStatus ::= TEXTUAL-CONVENTION
+    STATUS current
+    SYNTAX INTEGER {
+        active(1), notInService(2), notReady(3), createAndGo(4), createAndWait(5), destroy(6)
+    }
+TimeStamp ::= TEXTUAL-CONVENTION
+    STATUS current SYNTAX TimeTicks
+TimeInterval ::= TEXTUAL-CONVENTION
+    STATUS current SYNTAX INTEGER (0..2147483647)
+DateAndTime ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "2d-1d-1d,1d:1d:1d.1d,1a1d:1d"
+    STATUS current SYNTAX OCTET STRING (SIZE (8 | 11))
+StorageType ::= TEXTUAL-CONVENTION
+    STATUS current
+    SYNTAX INTEGER { other(1), volatile(2), nonVolatile(3), permanent(4), readOnly(5) }
+TDomain ::= TEXTUAL-CONVENTION
+    STATUS current SYNTAX OBJECT IDENTIFIER
+TAddress ::= TEXTUAL-CONVENTION
+    STATUS current SYNTAX OCTET STRING (SIZE (1..255))
+END
+"#;
+
+fn types(types: &[(&str, BaseType)]) -> Scope {
+    let mut scope = Scope::default();
+    for &(name, base) in types {
+        let mut syntax = Syntax::new(base);
+        syntax.type_name = Some(name.to_owned());
+        scope.types.insert(name.to_owned(), syntax);
+    }
+    scope
+}
+
+pub fn install(mib: &mut Mib) {
+    let mut smi = types(SMI_TYPES);
+    for &(name, oid) in SMI_NODES {
+        let index = mib.insert_node(Node::new(name, "SNMPv2-SMI", oids::oid(oid), NodeKind::ObjectIdentifier));
+        smi.nodes.insert(name.to_owned(), index);
+    }
+
+    let mut v1 = types(V1_TYPES);
+    for &name in V1_NODES {
+        v1.nodes.insert(name.to_owned(), smi.nodes[name]);
+    }
+
+    mib.modules.insert("SNMPv2-SMI".to_owned(), smi);
+    mib.modules.insert("RFC1155-SMI".to_owned(), v1.clone());
+    mib.modules.insert("RFC1065-SMI".to_owned(), v1);
+    for &name in &["SNMPv2-CONF", "RFC-1212", "RFC-1215"] {
+        mib.modules.insert(name.to_owned(), Scope::default());
+    }
+    mib.load_str(SNMPV2_TC).expect("built-in SNMPv2-TC");
+}
@@ -0,0 +1,1034 @@
+//! SMIv1/SMIv2 MIB modules resolved into a tree of named nodes.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use oids;
+use types::*;
+
+mod builtin;
+mod names;
+mod parser;
+mod render;
+
+pub use self::names::{IndexValue, Named};
+
+use self::parser::{Assignment, OidComponent, TypeRef, Value};
+
+/// Nesting limit of textual conventions defined in terms of each other.
+const MAX_TYPE_DEPTH: usize = 16;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// Module text is not valid SMI.
+    Syntax {
+        file: Option<PathBuf>,
+        line: usize,
+        message: String,
+    },
+    /// `module` imports from a module that is neither loaded nor being loaded.
+    MissingModule { module: String, import: String },
+    /// Modules importing from each other.
+    ImportCycle(String),
+    /// `name` used in `module` is neither defined nor imported there.
+    Unresolved { module: String, name: String },
+    /// No loaded module defines the node.
+    UnknownName(String),
+    /// Malformed OID or instance suffix.
+    InvalidName(String),
+    /// Text not matching the syntax of the object.
+    InvalidValue(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Syntax { file: Some(ref file), line, ref message } => {
+                write!(f, "{}:{}: {}", file.display(), line, message)
+            }
+            Error::Syntax { file: None, line, ref message } => write!(f, "line {}: {}", line, message),
+            Error::MissingModule { ref module, ref import } => {
+                write!(f, "module {} imports from unknown module {}", module, import)
+            }
+            Error::ImportCycle(ref module) => write!(f, "module {} imports itself", module),
+            Error::Unresolved { ref module, ref name } => write!(f, "unresolved name {} in module {}", name, module),
+            Error::UnknownName(ref name) => write!(f, "unknown name {}", name),
+            Error::InvalidName(ref name) => write!(f, "invalid OID {}", name),
+            Error::InvalidValue(ref value) => write!(f, "invalid value {}", value),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(ref e) => e.description(),
+            Error::Syntax { .. } => "MIB syntax error",
+            Error::MissingModule { .. } => "missing MIB module",
+            Error::ImportCycle(_) => "MIB import cycle",
+            Error::Unresolved { .. } => "unresolved MIB name",
+            Error::UnknownName(_) => "unknown MIB name",
+            Error::InvalidName(_) => "invalid OID",
+            Error::InvalidValue(_) => "invalid value",
+        }
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Macro, or plain OBJECT IDENTIFIER value, defining a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    ObjectIdentifier,
+    ModuleIdentity,
+    ObjectIdentity,
+    ObjectType,
+    NotificationType,
+    /// SMIv1 trap, placed at `enterprise.0.specific-trap` as RFC 3584 section 3.1 does.
+    TrapType,
+    ObjectGroup,
+    NotificationGroup,
+    ModuleCompliance,
+    AgentCapabilities,
+}
+
+/// MAX-ACCESS, or SMIv1 ACCESS, of an object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    NotAccessible,
+    AccessibleForNotify,
+    ReadOnly,
+    ReadWrite,
+    ReadCreate,
+    WriteOnly,
+}
+
+impl Access {
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword {
+            "not-accessible" => Some(Access::NotAccessible),
+            "accessible-for-notify" => Some(Access::AccessibleForNotify),
+            "read-only" => Some(Access::ReadOnly),
+            "read-write" => Some(Access::ReadWrite),
+            "read-create" => Some(Access::ReadCreate),
+            "write-only" => Some(Access::WriteOnly),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    Current,
+    Deprecated,
+    Obsolete,
+    /// SMIv1 only.
+    Mandatory,
+    /// SMIv1 only.
+    Optional,
+}
+
+impl Status {
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword {
+            "current" => Some(Status::Current),
+            "deprecated" => Some(Status::Deprecated),
+            "obsolete" => Some(Status::Obsolete),
+            "mandatory" => Some(Status::Mandatory),
+            "optional" => Some(Status::Optional),
+            _ => None,
+        }
+    }
+}
+
+/// Underlying SMI type of a syntax, after textual conventions are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BaseType {
+    Integer,
+    OctetString,
+    ObjectIdentifier,
+    Bits,
+    IpAddress,
+    /// SMIv1 only.
+    NetworkAddress,
+    Counter32,
+    Gauge32,
+    TimeTicks,
+    Opaque,
+    Counter64,
+    Unsigned32,
+    /// Conceptual row.
+    Sequence,
+    /// Conceptual table.
+    SequenceOf,
+}
+
+/// Resolved SYNTAX of an object or textual convention.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Syntax {
+    base: BaseType,
+    type_name: Option<String>,
+    display_hint: Option<String>,
+    named_numbers: Vec<(String, i64)>,
+    sizes: Vec<(i64, i64)>,
+    ranges: Vec<(i64, i64)>,
+}
+
+impl Syntax {
+    fn new(base: BaseType) -> Self {
+        Syntax {
+            base: base,
+            type_name: None,
+            display_hint: None,
+            named_numbers: Vec::new(),
+            sizes: Vec::new(),
+            ranges: Vec::new(),
+        }
+    }
+
+    pub fn base(&self) -> BaseType {
+        self.base
+    }
+
+    /// Name of the textual convention or defined type, if the syntax refers to one.
+    pub fn type_name(&self) -> Option<&str> {
+        self.type_name.as_ref().map(|s| s.as_str())
+    }
+
+    pub fn display_hint(&self) -> Option<&str> {
+        self.display_hint.as_ref().map(|s| s.as_str())
+    }
+
+    /// Enumeration labels of an INTEGER, or bit names of BITS.
+    pub fn named_numbers(&self) -> &[(String, i64)] {
+        &self.named_numbers
+    }
+
+    /// SIZE constraint of an OCTET STRING, as inclusive ranges.
+    pub fn sizes(&self) -> &[(i64, i64)] {
+        &self.sizes
+    }
+
+    /// Value constraint of a numeric type, as inclusive ranges.
+    pub fn ranges(&self) -> &[(i64, i64)] {
+        &self.ranges
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    name: String,
+    module: String,
+    oid: ObjectIdentifier,
+    kind: NodeKind,
+    syntax: Option<Syntax>,
+    access: Option<Access>,
+    status: Option<Status>,
+    units: Option<String>,
+    description: Option<String>,
+    index: Vec<ObjectIdentifier>,
+    implied: bool,
+    augments: Option<ObjectIdentifier>,
+    objects: Vec<ObjectIdentifier>,
+}
+
+impl Node {
+    fn new(name: &str, module: &str, oid: ObjectIdentifier, kind: NodeKind) -> Self {
+        Node {
+            name: name.to_owned(),
+            module: module.to_owned(),
+            oid: oid,
+            kind: kind,
+            syntax: None,
+            access: None,
+            status: None,
+            units: None,
+            description: None,
+            index: Vec::new(),
+            implied: false,
+            augments: None,
+            objects: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Name of the defining module.
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    pub fn oid(&self) -> &ObjectIdentifier {
+        &self.oid
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn syntax(&self) -> Option<&Syntax> {
+        self.syntax.as_ref()
+    }
+
+    pub fn access(&self) -> Option<Access> {
+        self.access
+    }
+
+    pub fn status(&self) -> Option<Status> {
+        self.status
+    }
+
+    pub fn units(&self) -> Option<&str> {
+        self.units.as_ref().map(|s| s.as_str())
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_ref().map(|s| s.as_str())
+    }
+
+    /// INDEX objects of a conceptual row.
+    pub fn index(&self) -> &[ObjectIdentifier] {
+        &self.index
+    }
+
+    /// Whether the last INDEX object is IMPLIED.
+    pub fn implied(&self) -> bool {
+        self.implied
+    }
+
+    /// Row this conceptual row AUGMENTS.
+    pub fn augments(&self) -> Option<&ObjectIdentifier> {
+        self.augments.as_ref()
+    }
+
+    /// OBJECTS of a notification or group, NOTIFICATIONS of a notification group,
+    /// or VARIABLES of an SMIv1 trap.
+    pub fn objects(&self) -> &[ObjectIdentifier] {
+        &self.objects
+    }
+}
+
+/// Symbols visible in a module: its own definitions and its imports.
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    nodes: HashMap<String, usize>,
+    types: HashMap<String, Syntax>,
+}
+
+/// Outcome of `Mib::load_dir`.
+#[derive(Debug)]
+pub struct Loaded {
+    /// Names of the modules loaded, in file name order.
+    pub modules: Vec<String>,
+    /// Files that could not be read or parsed and modules that could not be resolved.
+    pub errors: Vec<Error>,
+}
+
+/// Tree of nodes defined by loaded MIB modules.
+///
+/// The SMI modules (SNMPv2-SMI, SNMPv2-TC, SNMPv2-CONF, RFC1155-SMI, RFC-1212, RFC-1215)
+/// are built in. When two modules define the same OID, lookups by OID return the
+/// node loaded first.
+#[derive(Debug)]
+pub struct Mib {
+    nodes: Vec<Node>,
+    by_oid: BTreeMap<Vec<u64>, usize>,
+    by_name: HashMap<String, Vec<usize>>,
+    modules: HashMap<String, Scope>,
+}
+
+impl Default for Mib {
+    fn default() -> Self {
+        Mib::new()
+    }
+}
+
+impl Mib {
+    pub fn new() -> Self {
+        let mut mib = Mib {
+            nodes: Vec::new(),
+            by_oid: BTreeMap::new(),
+            by_name: HashMap::new(),
+            modules: HashMap::new(),
+        };
+        builtin::install(&mut mib);
+        mib
+    }
+
+    /// Loads the modules defined in `text`, returning their names.
+    ///
+    /// Modules imported from must already be loaded or be defined in `text`.
+    pub fn load_str(&mut self, text: &str) -> Result<Vec<String>, Error> {
+        let modules = parser::parse(text).map_err(|e| Error::Syntax {
+            file: None,
+            line: e.line,
+            message: e.message,
+        })?;
+        self.add(modules)
+    }
+
+    /// Loads the modules defined in the file at `path`, returning their names.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Vec<String>, Error> {
+        let modules = read_modules(path.as_ref())?;
+        self.add(modules)
+    }
+
+    /// Loads every module found in the files of directory `dir`, resolving imports between
+    /// them. Files without a module definition are ignored.
+    ///
+    /// Unreadable files and modules that fail to parse or resolve are reported in the result
+    /// without stopping the others from loading; only failing to list `dir` is an error.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<Loaded, Error> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut modules = Vec::new();
+        let mut errors = Vec::new();
+        for path in paths {
+            match read_modules(&path) {
+                Ok(found) => modules.extend(found),
+                Err(e) => errors.push(e),
+            }
+        }
+        let (names, failed) = self.add_all(modules);
+        errors.extend(failed);
+        Ok(Loaded {
+            modules: names,
+            errors: errors,
+        })
+    }
+
+    pub fn has_module(&self, name: &str) -> bool {
+        self.modules.contains_key(name)
+    }
+
+    /// Node named `name`, optionally qualified as `MODULE::name`.
+    ///
+    /// An unqualified name defined by several modules refers to the one loaded first.
+    pub fn node(&self, name: &str) -> Option<&Node> {
+        let index = match name.find("::") {
+            Some(pos) => self.modules.get(&name[..pos]).and_then(|scope| scope.nodes.get(&name[pos + 2..])),
+            None => self.by_name.get(name).and_then(|indices| indices.first()),
+        };
+        index.map(|&index| &self.nodes[index])
+    }
+
+    pub fn node_by_oid(&self, oid: &ObjectIdentifier) -> Option<&Node> {
+        self.by_oid.get(oids::components(oid)).map(|&index| &self.nodes[index])
+    }
+
+    /// Deepest node that is `oid` or one of its ancestors, along with the remaining components.
+    pub fn lookup<'a, 'b>(&'a self, oid: &'b ObjectIdentifier) -> Option<(&'a Node, &'b [u64])> {
+        let components = oids::components(oid);
+        (1..components.len() + 1).rev().filter_map(|len| {
+            self.by_oid.get(&components[..len]).map(|&index| (&self.nodes[index], &components[len..]))
+        }).next()
+    }
+
+    pub fn parent(&self, node: &Node) -> Option<&Node> {
+        let components = oids::components(&node.oid);
+        match components.split_last() {
+            Some((_, parent)) if !parent.is_empty() => self.lookup(&oids::oid(parent)).map(|(parent, _)| parent),
+            _ => None,
+        }
+    }
+
+    /// Direct children of `node`, in OID order.
+    pub fn children<'a>(&'a self, node: &Node) -> impl Iterator<Item = &'a Node> + 'a {
+        let prefix = oids::components(&node.oid).to_vec();
+        let depth = prefix.len() + 1;
+        self.by_oid
+            .range(prefix.clone()..)
+            .take_while(move |&(oid, _)| oid.starts_with(&prefix))
+            .filter(move |&(oid, _)| oid.len() == depth)
+            .map(move |(_, &index)| &self.nodes[index])
+    }
+
+    /// All nodes in OID order.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a Node> + 'a {
+        self.by_oid.values().map(move |&index| &self.nodes[index])
+    }
+
+    fn insert_node(&mut self, node: Node) -> usize {
+        let index = self.nodes.len();
+        self.by_oid.entry(oids::components(&node.oid).to_vec()).or_insert(index);
+        self.by_name.entry(node.name.clone()).or_insert_with(Vec::new).push(index);
+        self.nodes.push(node);
+        index
+    }
+
+    fn add(&mut self, modules: Vec<parser::Module>) -> Result<Vec<String>, Error> {
+        let (names, errors) = self.add_all(modules);
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(names),
+        }
+    }
+
+    /// Resolves each of `modules` regardless of failures of the others; returns the names of
+    /// those loaded and the errors of the rest.
+    fn add_all(&mut self, modules: Vec<parser::Module>) -> (Vec<String>, Vec<Error>) {
+        let mut names = Vec::new();
+        let mut pending = HashMap::new();
+        for module in modules {
+            if !self.modules.contains_key(&module.name) && !pending.contains_key(&module.name) {
+                names.push(module.name.clone());
+                pending.insert(module.name.clone(), module);
+            }
+        }
+        let mut errors = Vec::new();
+        for name in &names {
+            if let Err(e) = self.resolve_module(name, &mut pending, &mut Vec::new()) {
+                errors.push(e);
+            }
+        }
+        names.retain(|name| self.modules.contains_key(name));
+        (names, errors)
+    }
+
+    /// Resolves `name` from `pending` after the modules it imports from.
+    fn resolve_module(
+        &mut self,
+        name: &str,
+        pending: &mut HashMap<String, parser::Module>,
+        stack: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        let module = match pending.remove(name) {
+            Some(module) => module,
+            None => return Ok(()),
+        };
+
+        stack.push(module.name.clone());
+        for import in &module.imports {
+            if stack.contains(&import.module) {
+                return Err(Error::ImportCycle(import.module.clone()));
+            }
+            self.resolve_module(&import.module, pending, stack)?;
+            if !self.modules.contains_key(&import.module) {
+                return Err(Error::MissingModule {
+                    module: module.name.clone(),
+                    import: import.module.clone(),
+                });
+            }
+        }
+        stack.pop();
+
+        // Symbols that are not found, such as the SMI macros, only matter once used.
+        let mut scope = Scope::default();
+        for import in &module.imports {
+            let source = &self.modules[&import.module];
+            for symbol in &import.symbols {
+                if let Some(&index) = source.nodes.get(symbol) {
+                    scope.nodes.insert(symbol.clone(), index);
+                } else if let Some(syntax) = source.types.get(symbol) {
+                    scope.types.insert(symbol.clone(), syntax.clone());
+                }
+            }
+        }
+
+        let mut types = HashMap::new();
+        let mut defs = Vec::new();
+        for assignment in &module.assignments {
+            match *assignment {
+                Assignment::Type(ref def) => {
+                    types.insert(def.name.as_str(), def);
+                }
+                Assignment::Node(ref def) => defs.push(def),
+            }
+        }
+
+        let mut resolver = Resolver {
+            module: &module.name,
+            types: &types,
+            scope: scope,
+        };
+        for name in types.keys() {
+            let syntax = resolver.named_type(name, 0)?;
+            resolver.scope.types.insert((*name).to_owned(), syntax);
+        }
+
+        // A module that fails to resolve leaves no nodes behind.
+        let first = self.nodes.len();
+        if let Err(e) = self.place_nodes(&mut resolver, defs) {
+            self.truncate_nodes(first);
+            return Err(e);
+        }
+
+        self.modules.insert(module.name.clone(), resolver.scope);
+        Ok(())
+    }
+
+    /// Inserts the nodes of `defs` and resolves their clauses.
+    fn place_nodes(&mut self, resolver: &mut Resolver, mut defs: Vec<&parser::NodeDef>) -> Result<(), Error> {
+        // Nodes may refer to nodes defined further down the module.
+        let mut placed = Vec::new();
+        while !defs.is_empty() {
+            let count = defs.len();
+            for def in mem::replace(&mut defs, Vec::new()) {
+                match resolver.oid(def, &self.nodes) {
+                    Some(oid) => {
+                        let index = self.insert_node(Node::new(&def.name, resolver.module, oids::oid(&oid), def.kind));
+                        resolver.scope.nodes.insert(def.name.clone(), index);
+                        placed.push((index, def));
+                    }
+                    None => defs.push(def),
+                }
+            }
+            if defs.len() == count {
+                return Err(Error::Unresolved {
+                    module: resolver.module.to_owned(),
+                    name: parent_name(defs[0]),
+                });
+            }
+        }
+
+        for (index, def) in placed {
+            let clauses = &def.clauses;
+            let syntax = match clauses.syntax {
+                Some(ref syntax) => Some(resolver.syntax(syntax, 0)?),
+                None => None,
+            };
+            let (index_oids, augments, objects) = {
+                let nodes = &self.nodes;
+                let node_oid = |name: &String| resolver.node(name).map(|index| nodes[index].oid.clone());
+                let augments = match clauses.augments {
+                    Some(ref name) => Some(node_oid(name)?),
+                    None => None,
+                };
+                (
+                    clauses.index.iter().map(&node_oid).collect::<Result<Vec<_>, _>>()?,
+                    augments,
+                    clauses.objects.iter().map(&node_oid).collect::<Result<Vec<_>, _>>()?,
+                )
+            };
+
+            let node = &mut self.nodes[index];
+            node.syntax = syntax;
+            node.access = clauses.access;
+            node.status = clauses.status;
+            node.units = clauses.units.clone();
+            node.description = clauses.description.clone();
+            node.index = index_oids;
+            node.implied = clauses.implied;
+            node.augments = augments;
+            node.objects = objects;
+        }
+        Ok(())
+    }
+
+    /// Drops the nodes inserted from `len` on.
+    fn truncate_nodes(&mut self, len: usize) {
+        for node in self.nodes.drain(len..) {
+            let oid = oids::components(&node.oid);
+            if self.by_oid.get(oid).map_or(false, |&index| index >= len) {
+                self.by_oid.remove(oid);
+            }
+        }
+        self.by_name.retain(|_, indices| {
+            indices.retain(|&index| index < len);
+            !indices.is_empty()
+        });
+    }
+}
+
+fn read_modules(path: &Path) -> Result<Vec<parser::Module>, Error> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    let text = String::from_utf8_lossy(&bytes);
+    if !text.contains("DEFINITIONS") {
+        return Ok(Vec::new());
+    }
+    parser::parse(&text).map_err(|e| Error::Syntax {
+        file: Some(path.to_owned()),
+        line: e.line,
+        message: e.message,
+    })
+}
+
+/// Name an unplaceable definition is waiting for.
+fn parent_name(def: &parser::NodeDef) -> String {
+    match def.value {
+        Value::Trap(_) => def.clauses.enterprise.clone().unwrap_or_else(|| def.name.clone()),
+        Value::Oid(ref components) => {
+            for component in components {
+                if let OidComponent::Name(ref name) = *component {
+                    return name.clone();
+                }
+            }
+            def.name.clone()
+        }
+    }
+}
+
+/// Name resolution within a single module.
+struct Resolver<'a> {
+    module: &'a str,
+    types: &'a HashMap<&'a str, &'a parser::TypeDef>,
+    scope: Scope,
+}
+
+impl<'a> Resolver<'a> {
+    fn unresolved(&self, name: &str) -> Error {
+        Error::Unresolved {
+            module: self.module.to_owned(),
+            name: name.to_owned(),
+        }
+    }
+
+    fn node(&self, name: &str) -> Result<usize, Error> {
+        self.scope.nodes.get(name).cloned().ok_or_else(|| self.unresolved(name))
+    }
+
+    /// OID of `def`, or `None` while its parent is not placed yet.
+    fn oid(&self, def: &parser::NodeDef, nodes: &[Node]) -> Option<Vec<u64>> {
+        match def.value {
+            Value::Trap(specific) => {
+                let enterprise = def.clauses.enterprise.as_ref().and_then(|name| self.scope.nodes.get(name));
+                enterprise.map(|&enterprise| {
+                    let mut oid = oids::components(&nodes[enterprise].oid).to_vec();
+                    oid.push(0);
+                    oid.push(specific);
+                    oid
+                })
+            }
+            Value::Oid(ref components) => {
+                let mut oid = Vec::new();
+                for (i, component) in components.iter().enumerate() {
+                    match *component {
+                        OidComponent::Number(n) | OidComponent::Named(_, n) => oid.push(n),
+                        OidComponent::Name(ref name) if i == 0 => match self.scope.nodes.get(name) {
+                            Some(&parent) => oid.extend_from_slice(oids::components(&nodes[parent].oid)),
+                            None => return None,
+                        },
+                        OidComponent::Name(_) => return None,
+                    }
+                }
+                if oid.is_empty() {
+                    None
+                } else {
+                    Some(oid)
+                }
+            }
+        }
+    }
+
+    /// Type defined in the module, or imported, named `name`.
+    fn named_type(&self, name: &str, depth: usize) -> Result<Syntax, Error> {
+        match self.types.get(name) {
+            Some(def) if depth < MAX_TYPE_DEPTH => {
+                let mut syntax = self.syntax(&def.syntax, depth + 1)?;
+                syntax.type_name = Some(name.to_owned());
+                if def.clauses.display_hint.is_some() {
+                    syntax.display_hint = def.clauses.display_hint.clone();
+                }
+                Ok(syntax)
+            }
+            Some(_) => Err(self.unresolved(name)),
+            None => self.scope.types.get(name).cloned().ok_or_else(|| self.unresolved(name)),
+        }
+    }
+
+    fn syntax(&self, syntax: &parser::Type, depth: usize) -> Result<Syntax, Error> {
+        let mut resolved = match syntax.base {
+            TypeRef::Integer => Syntax::new(BaseType::Integer),
+            TypeRef::OctetString => Syntax::new(BaseType::OctetString),
+            TypeRef::ObjectIdentifier => Syntax::new(BaseType::ObjectIdentifier),
+            TypeRef::Bits => Syntax::new(BaseType::Bits),
+            TypeRef::Sequence => Syntax::new(BaseType::Sequence),
+            TypeRef::SequenceOf(_) => Syntax::new(BaseType::SequenceOf),
+            TypeRef::Choice => return Err(self.unresolved("CHOICE")),
+            TypeRef::Named(ref name) => self.named_type(name, depth)?,
+        };
+        // Refinements of a named type replace its own constraints.
+        if !syntax.named_numbers.is_empty() {
+            resolved.named_numbers = syntax.named_numbers.clone();
+        }
+        if !syntax.sizes.is_empty() {
+            resolved.sizes = syntax.sizes.clone();
+        }
+        if !syntax.ranges.is_empty() {
+            resolved.ranges = syntax.ranges.clone();
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    const IF_MIB: &str = r#"
+TEST-IF-MIB DEFINITIONS ::= BEGIN
+
+IMPORTS
+    MODULE-IDENTITY, OBJECT-TYPE, NOTIFICATION-TYPE, Integer32, Counter32, mib-2
+        FROM SNMPv2-SMI
+    TEXTUAL-CONVENTION, DisplayString, PhysAddress
+        FROM SNMPv2-TC
+    OBJECT-GROUP FROM SNMPv2-CONF;
+
+testIfMib MODULE-IDENTITY
+    LAST-UPDATED "201710170000Z"
+    ORGANIZATION "Test"
+    CONTACT-INFO "None"
+    DESCRIPTION  "Interfaces."
+    REVISION     "201710170000Z"
+    DESCRIPTION  "Initial revision."
+    ::= { mib-2 9999 }
+
+InterfaceIndex ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "d"
+    STATUS       current
+    DESCRIPTION  "Interface index."
+    SYNTAX       Integer32 (1..2147483647)
+
+IfEntry ::= SEQUENCE {
+    ifIndex       InterfaceIndex,
+    ifDescr       DisplayString,
+    ifAdminStatus INTEGER,
+    ifInOctets    Counter32
+}
+
+ifTable OBJECT-TYPE
+    SYNTAX      SEQUENCE OF IfEntry
+    MAX-ACCESS  not-accessible
+    STATUS      current
+    DESCRIPTION "Interfaces."
+    ::= { testIfObjects 2 }
+
+ifEntry OBJECT-TYPE
+    SYNTAX      IfEntry
+    MAX-ACCESS  not-accessible
+    STATUS      current
+    DESCRIPTION "Interface."
+    INDEX       { ifIndex }
+    ::= { ifTable 1 }
+
+ifIndex OBJECT-TYPE
+    SYNTAX      InterfaceIndex
+    MAX-ACCESS  read-only
+    STATUS      current
+    DESCRIPTION "Index."
+    ::= { ifEntry 1 }
+
+ifDescr OBJECT-TYPE
+    SYNTAX      DisplayString (SIZE (0..64))
+    MAX-ACCESS  read-only
+    STATUS      current
+    DESCRIPTION "Description."
+    ::= { ifEntry 2 }
+
+ifAdminStatus OBJECT-TYPE
+    SYNTAX      INTEGER { up(1), down(2), testing(3) }
+    MAX-ACCESS  read-write
+    STATUS      current
+    DESCRIPTION "Administrative status."
+    ::= { ifEntry 7 }
+
+ifInOctets OBJECT-TYPE
+    SYNTAX      Counter32
+    UNITS       "octets"
+    MAX-ACCESS  read-only
+    STATUS      current
+    DESCRIPTION "Received octets."
+    ::= { ifEntry 10 }
+
+testIfObjects OBJECT IDENTIFIER ::= { testIfMib 1 }
+
+linkDown NOTIFICATION-TYPE
+    OBJECTS     { ifIndex, ifAdminStatus }
+    STATUS      current
+    DESCRIPTION "Link down."
+    ::= { testIfMib 0 1 }
+
+END
+"#;
+
+    const EXT_MIB: &str = r#"
+TEST-EXT-MIB DEFINITIONS ::= BEGIN
+
+IMPORTS
+    OBJECT-TYPE FROM RFC-1212
+    TRAP-TYPE FROM RFC-1215
+    enterprises FROM RFC1155-SMI
+    ifIndex, InterfaceIndex FROM TEST-IF-MIB;
+
+test OBJECT IDENTIFIER ::= { enterprises 99999 }
+
+testIfIndex OBJECT-TYPE
+    SYNTAX  InterfaceIndex
+    ACCESS  read-only
+    STATUS  mandatory
+    ::= { test 1 }
+
+testTrap TRAP-TYPE
+    ENTERPRISE test
+    VARIABLES  { ifIndex }
+    DESCRIPTION "Trap."
+    ::= 3
+
+END
+"#;
+
+    fn oid(components: &[u64]) -> ObjectIdentifier {
+        oids::oid(components)
+    }
+
+    #[test]
+    fn builtin() {
+        let mib = Mib::new();
+        assert!(mib.has_module("SNMPv2-SMI"));
+        assert!(mib.has_module("RFC1155-SMI"));
+        assert_eq!(mib.node("enterprises").unwrap().oid(), &oid(&[1, 3, 6, 1, 4, 1]));
+        assert_eq!(mib.node("RFC1155-SMI::mgmt").unwrap().oid(), &oid(&[1, 3, 6, 1, 2]));
+    }
+
+    #[test]
+    fn resolve() {
+        let mut mib = Mib::new();
+        assert_eq!(mib.load_str(IF_MIB).unwrap(), vec!["TEST-IF-MIB"]);
+
+        let module = mib.node("TEST-IF-MIB::testIfMib").unwrap();
+        assert_eq!(module.kind(), NodeKind::ModuleIdentity);
+        assert_eq!(module.description(), Some("Interfaces."));
+
+        let table = mib.node("ifTable").unwrap();
+        assert_eq!(table.oid(), &oid(&[1, 3, 6, 1, 2, 1, 9999, 1, 2]));
+        assert_eq!(table.syntax().unwrap().base(), BaseType::SequenceOf);
+
+        let entry = mib.node("ifEntry").unwrap();
+        assert_eq!(entry.index(), &[oid(&[1, 3, 6, 1, 2, 1, 9999, 1, 2, 1, 1])][..]);
+        let columns: Vec<&str> = mib.children(entry).map(|n| n.name()).collect();
+        assert_eq!(columns, vec!["ifIndex", "ifDescr", "ifAdminStatus", "ifInOctets"]);
+        assert_eq!(mib.parent(mib.node("ifDescr").unwrap()).unwrap().name(), "ifEntry");
+
+        let index = mib.node("ifIndex").unwrap().syntax().unwrap();
+        assert_eq!(index.base(), BaseType::Integer);
+        assert_eq!(index.type_name(), Some("InterfaceIndex"));
+        assert_eq!(index.display_hint(), Some("d"));
+        assert_eq!(index.ranges(), &[(1, 2147483647)][..]);
+
+        let descr = mib.node("ifDescr").unwrap().syntax().unwrap();
+        assert_eq!(descr.base(), BaseType::OctetString);
+        assert_eq!(descr.display_hint(), Some("255a"));
+        assert_eq!(descr.sizes(), &[(0, 64)][..]);
+
+        let status = mib.node("ifAdminStatus").unwrap();
+        assert_eq!(status.access(), Some(Access::ReadWrite));
+        assert_eq!(status.syntax().unwrap().named_numbers()[2], ("testing".to_owned(), 3));
+
+        let octets = mib.node("ifInOctets").unwrap();
+        assert_eq!(octets.units(), Some("octets"));
+        assert_eq!(octets.syntax().unwrap().base(), BaseType::Counter32);
+
+        let link_down = mib.node("linkDown").unwrap();
+        assert_eq!(link_down.kind(), NodeKind::NotificationType);
+        assert_eq!(link_down.objects().len(), 2);
+
+        let instance = oid(&[1, 3, 6, 1, 2, 1, 9999, 1, 2, 1, 2, 5]);
+        let (node, rest) = mib.lookup(&instance).unwrap();
+        assert_eq!(node.name(), "ifDescr");
+        assert_eq!(rest, &[5][..]);
+    }
+
+    #[test]
+    fn imports() {
+        let mut mib = Mib::new();
+        match mib.load_str(EXT_MIB) {
+            Err(Error::MissingModule { ref module, ref import }) => {
+                assert_eq!(module, "TEST-EXT-MIB");
+                assert_eq!(import, "TEST-IF-MIB");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let dir = env::temp_dir().join(format!("snmp-mib-imports-{}-{}", since_epoch.as_secs(), since_epoch.subsec_nanos()));
+        fs::create_dir_all(&dir).unwrap();
+        for &(file, text) in &[
+            ("A-EXT.mib", EXT_MIB),
+            ("B-IF.txt", IF_MIB),
+            ("C-BROKEN.mib", "BROKEN-MIB DEFINITIONS ::= BEGIN x OBJECT"),
+            ("D-ORPHAN.mib", "ORPHAN-MIB DEFINITIONS ::= BEGIN IMPORTS x FROM NO-SUCH-MIB; END"),
+            ("README", "not a module"),
+        ] {
+            File::create(dir.join(file)).unwrap().write_all(text.as_bytes()).unwrap();
+        }
+        let loaded = mib.load_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Broken files do not keep the others from loading.
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.modules, vec!["TEST-EXT-MIB", "TEST-IF-MIB"]);
+        assert_eq!(loaded.errors.len(), 2);
+        match loaded.errors[0] {
+            Error::Syntax { file: Some(ref file), .. } => assert!(file.ends_with("C-BROKEN.mib")),
+            ref other => panic!("unexpected {:?}", other),
+        }
+        match loaded.errors[1] {
+            Error::MissingModule { ref module, ref import } => {
+                assert_eq!(module, "ORPHAN-MIB");
+                assert_eq!(import, "NO-SUCH-MIB");
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+
+        let column = mib.node("testIfIndex").unwrap();
+        assert_eq!(column.status(), Some(Status::Mandatory));
+        assert_eq!(column.syntax().unwrap().type_name(), Some("InterfaceIndex"));
+
+        let trap = mib.node("testTrap").unwrap();
+        assert_eq!(trap.kind(), NodeKind::TrapType);
+        assert_eq!(trap.oid(), &oid(&[1, 3, 6, 1, 4, 1, 99999, 0, 3]));
+        assert_eq!(trap.objects(), &[mib.node("TEST-IF-MIB::ifIndex").unwrap().oid().clone()][..]);
+    }
+
+    #[test]
+    fn unresolved() {
+        let mut mib = Mib::new();
+        let text = "A DEFINITIONS ::= BEGIN\nIMPORTS enterprises FROM SNMPv2-SMI;\n\
+                    a OBJECT IDENTIFIER ::= { enterprises 9999 }\nx OBJECT IDENTIFIER ::= { y 1 }\nEND";
+        match mib.load_str(text) {
+            Err(Error::Unresolved { ref module, ref name }) => {
+                assert_eq!(module, "A");
+                assert_eq!(name, "y");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(mib.node("x").is_none());
+        assert!(mib.node("a").is_none());
+        assert!(mib.node_by_oid(&oids::oid(&[1, 3, 6, 1, 4, 1, 9999])).is_none());
+        assert!(mib.node("enterprises").is_some());
+    }
+}
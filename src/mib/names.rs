@@ -0,0 +1,520 @@
+//! Symbolic names of OIDs, with table index decoding.
+
+use std::fmt::{self, Display, Write};
+use std::net::Ipv4Addr;
+
+use super::{BaseType, Error, Mib, Node};
+use oids;
+use types::*;
+
+/// Value of an INDEX object decoded from an instance OID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexValue {
+    Integer(u64),
+    IpAddress(Ipv4Addr),
+    OctetString(Vec<u8>),
+    Oid(ObjectIdentifier),
+}
+
+/// How an INDEX object is encoded in instance OIDs (RFC 2578 section 7.7).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IndexKind {
+    Integer,
+    IpAddress,
+    OctetString { fixed: Option<usize>, implied: bool },
+    Oid { implied: bool },
+}
+
+impl IndexKind {
+    /// Number of components of the value at the start of `suffix`, including any length prefix.
+    fn len(&self, suffix: &[u64]) -> Option<usize> {
+        match *self {
+            IndexKind::Integer => Some(1),
+            IndexKind::IpAddress => Some(4),
+            IndexKind::OctetString { fixed: Some(len), .. } => Some(len),
+            IndexKind::OctetString { implied: true, .. } | IndexKind::Oid { implied: true } => Some(suffix.len()),
+            // The length prefix is untrusted: it must fit in the rest of the suffix.
+            _ => suffix.first().and_then(|&len| if len < suffix.len() as u64 { Some(len as usize + 1) } else { None }),
+        }
+    }
+
+    fn prefixed(&self) -> bool {
+        match *self {
+            IndexKind::OctetString { fixed: None, implied: false } | IndexKind::Oid { implied: false } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Part of the instance suffix of a symbolic name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Number(u64),
+    Str(Vec<u8>),
+}
+
+fn octets(components: &[u64]) -> Option<Vec<u8>> {
+    components.iter().map(|&c| if c <= 0xff { Some(c as u8) } else { None }).collect()
+}
+
+fn write_quoted(out: &mut String, bytes: &[u8]) {
+    out.push('"');
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20...0x7e => out.push(b as char),
+            _ => {
+                let _ = write!(out, "\\x{:02x}", b);
+            }
+        }
+    }
+    out.push('"');
+}
+
+fn write_components(out: &mut String, components: &[u64]) {
+    for c in components {
+        let _ = write!(out, ".{}", c);
+    }
+}
+
+/// Parses `.1.2."string"...` suffixes.
+fn parse_parts(text: &str) -> Option<Vec<Part>> {
+    let bytes = text.as_bytes();
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'.' {
+            return None;
+        }
+        i += 1;
+
+        if bytes.get(i) == Some(&b'"') {
+            let mut value = Vec::new();
+            i += 1;
+            loop {
+                match *bytes.get(i)? {
+                    b'"' => break,
+                    b'\\' => match *bytes.get(i + 1)? {
+                        b'x' => {
+                            let hex = text.get(i + 2..i + 4)?;
+                            value.push(u8::from_str_radix(hex, 16).ok()?);
+                            i += 3;
+                        }
+                        b => {
+                            value.push(b);
+                            i += 1;
+                        }
+                    },
+                    b => value.push(b),
+                }
+                i += 1;
+            }
+            i += 1;
+            parts.push(Part::Str(value));
+        } else {
+            let end = text[i..].find('.').map_or(text.len(), |pos| i + pos);
+            parts.push(Part::Number(text[i..end].parse().ok()?));
+            i = end;
+        }
+    }
+    Some(parts)
+}
+
+/// Displays an OID, or a variable binding with its value rendered per its syntax,
+/// with symbolic names from a `Mib`.
+#[derive(Debug)]
+pub struct Named<'a, T: 'a + ?Sized> {
+    mib: &'a Mib,
+    value: &'a T,
+}
+
+impl<'a> Display for Named<'a, ObjectIdentifier> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.mib.name(self.value))
+    }
+}
+
+impl<'a> Display for Named<'a, VarBind> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let oid = self.value.oid();
+        write!(f, "{} = {}", self.mib.name(oid), self.mib.format_value(oid, self.value.value()))
+    }
+}
+
+impl Mib {
+    /// Short name of `oid`, such as `ifInOctets.3`.
+    pub fn name(&self, oid: &ObjectIdentifier) -> String {
+        self.format(oid, false)
+    }
+
+    /// Name of `oid` qualified with its module, such as `IF-MIB::ifInOctets.3`.
+    pub fn full_name(&self, oid: &ObjectIdentifier) -> String {
+        self.format(oid, true)
+    }
+
+    /// Wraps `value` for display with symbolic names.
+    pub fn display<'a, T: ?Sized>(&'a self, value: &'a T) -> Named<'a, T> {
+        Named {
+            mib: self,
+            value: value,
+        }
+    }
+
+    /// Parses a numeric OID (`1.3.6.1.2.1.1.3.0`, optionally with a leading dot) or a
+    /// symbolic one in either the short or the full form.
+    ///
+    /// String indices may be given quoted, with `\"`, `\\` and `\xNN` escapes.
+    pub fn parse_oid(&self, text: &str) -> Result<ObjectIdentifier, Error> {
+        let invalid = || Error::InvalidName(text.to_owned());
+        let (head, suffix) = match text.find('.') {
+            Some(pos) => (&text[..pos], &text[pos..]),
+            None => (text, ""),
+        };
+
+        if head.is_empty() || head.bytes().all(|b| b.is_ascii_digit()) {
+            let numeric = if head.is_empty() {
+                suffix.to_owned()
+            } else {
+                format!(".{}", text)
+            };
+            let mut oid = Vec::new();
+            for part in parse_parts(&numeric).ok_or_else(&invalid)? {
+                match part {
+                    Part::Number(n) => oid.push(n),
+                    Part::Str(_) => return Err(invalid()),
+                }
+            }
+            if oid.is_empty() {
+                return Err(invalid());
+            }
+            return Ok(oids::oid(&oid));
+        }
+
+        let node = self.node(head).ok_or_else(|| Error::UnknownName(head.to_owned()))?;
+        let parts = parse_parts(suffix).ok_or_else(&invalid)?;
+        let kinds = self.row(node).and_then(|row| self.index_kinds(row)).unwrap_or_default();
+
+        let mut oid = oids::components(node.oid()).to_vec();
+        let mut i = 0;
+        for kind in kinds {
+            match parts.get(i) {
+                None => break,
+                Some(&Part::Str(ref bytes)) => match kind {
+                    IndexKind::OctetString { fixed, .. } => {
+                        if fixed.map_or(false, |len| len != bytes.len()) {
+                            return Err(invalid());
+                        }
+                        if kind.prefixed() {
+                            oid.push(bytes.len() as u64);
+                        }
+                        oid.extend(bytes.iter().map(|&b| u64::from(b)));
+                        i += 1;
+                    }
+                    _ => return Err(invalid()),
+                },
+                Some(_) => {
+                    let numbers: Vec<u64> = parts[i..]
+                        .iter()
+                        .take_while(|part| match **part {
+                            Part::Number(_) => true,
+                            Part::Str(_) => false,
+                        })
+                        .map(|part| match *part {
+                            Part::Number(n) => n,
+                            Part::Str(_) => unreachable!(),
+                        })
+                        .collect();
+                    let len = kind.len(&numbers).ok_or_else(&invalid)?;
+                    if len > numbers.len() {
+                        return Err(invalid());
+                    }
+                    let bytes = match kind {
+                        IndexKind::OctetString { .. } if kind.prefixed() => &numbers[1..len],
+                        IndexKind::IpAddress | IndexKind::OctetString { .. } => &numbers[..len],
+                        _ => &[][..],
+                    };
+                    if octets(bytes).is_none() {
+                        return Err(invalid());
+                    }
+                    oid.extend_from_slice(&numbers[..len]);
+                    i += len;
+                }
+            }
+        }
+        for part in &parts[i..] {
+            match *part {
+                Part::Number(n) => oid.push(n),
+                Part::Str(ref bytes) => {
+                    oid.push(bytes.len() as u64);
+                    oid.extend(bytes.iter().map(|&b| u64::from(b)));
+                }
+            }
+        }
+        Ok(oids::oid(&oid))
+    }
+
+    /// Values of the INDEX objects encoded in `suffix`, the components of an instance OID
+    /// following those of `column`. `None` if `column` is not a column of a conceptual row
+    /// or `suffix` does not match the row INDEX.
+    pub fn decode_index(&self, column: &Node, suffix: &[u64]) -> Option<Vec<IndexValue>> {
+        let kinds = self.index_kinds(self.row(column)?)?;
+        decode(&kinds, suffix).map(|values| values.into_iter().map(|(_, value)| value).collect())
+    }
+
+    fn format(&self, oid: &ObjectIdentifier, full: bool) -> String {
+        let (node, suffix) = match self.lookup(oid) {
+            Some(found) => found,
+            None => {
+                let components: Vec<String> = oids::components(oid).iter().map(|c| c.to_string()).collect();
+                return components.join(".");
+            }
+        };
+
+        let mut out = String::new();
+        if full {
+            out.push_str(node.module());
+            out.push_str("::");
+        }
+        out.push_str(node.name());
+
+        let values = self.row(node).and_then(|row| self.index_kinds(row)).and_then(|kinds| decode(&kinds, suffix));
+        match values {
+            Some(values) => {
+                for (kind, value) in values {
+                    match value {
+                        IndexValue::Integer(n) => {
+                            let _ = write!(out, ".{}", n);
+                        }
+                        IndexValue::IpAddress(addr) => {
+                            let _ = write!(out, ".{}", addr);
+                        }
+                        IndexValue::OctetString(ref bytes) => {
+                            out.push('.');
+                            write_quoted(&mut out, bytes);
+                        }
+                        IndexValue::Oid(ref oid) => {
+                            let components = oids::components(oid);
+                            if kind.prefixed() {
+                                let _ = write!(out, ".{}", components.len());
+                            }
+                            write_components(&mut out, components);
+                        }
+                    }
+                }
+            }
+            None => write_components(&mut out, suffix),
+        }
+        out
+    }
+
+    /// Conceptual row `column` belongs to, which has an INDEX or AUGMENTS one that has.
+    fn row(&self, column: &Node) -> Option<&Node> {
+        let row = self.parent(column)?;
+        match row.augments() {
+            Some(augmented) => self.node_by_oid(augmented),
+            None if !row.index().is_empty() => Some(row),
+            None => None,
+        }
+    }
+
+    fn index_kinds(&self, row: &Node) -> Option<Vec<IndexKind>> {
+        let last = row.index().len().saturating_sub(1);
+        row.index()
+            .iter()
+            .enumerate()
+            .map(|(i, oid)| {
+                let syntax = self.node_by_oid(oid)?.syntax()?;
+                let implied = row.implied() && i == last;
+                let kind = match syntax.base() {
+                    BaseType::Integer
+                    | BaseType::Unsigned32
+                    | BaseType::Gauge32
+                    | BaseType::Counter32
+                    | BaseType::Counter64
+                    | BaseType::TimeTicks => IndexKind::Integer,
+                    BaseType::IpAddress | BaseType::NetworkAddress => IndexKind::IpAddress,
+                    BaseType::OctetString | BaseType::Bits | BaseType::Opaque => {
+                        let sizes = syntax.sizes();
+                        let fixed = if sizes.len() == 1 && sizes[0].0 == sizes[0].1 && sizes[0].0 >= 0 {
+                            Some(sizes[0].0 as usize)
+                        } else {
+                            None
+                        };
+                        IndexKind::OctetString {
+                            fixed: fixed,
+                            implied: implied,
+                        }
+                    }
+                    BaseType::ObjectIdentifier => IndexKind::Oid { implied: implied },
+                    BaseType::Sequence | BaseType::SequenceOf => return None,
+                };
+                Some(kind)
+            })
+            .collect()
+    }
+}
+
+fn decode(kinds: &[IndexKind], mut suffix: &[u64]) -> Option<Vec<(IndexKind, IndexValue)>> {
+    let mut values = Vec::with_capacity(kinds.len());
+    for &kind in kinds {
+        let len = kind.len(suffix)?;
+        if len > suffix.len() {
+            return None;
+        }
+        let (encoded, rest) = suffix.split_at(len);
+        suffix = rest;
+
+        let encoded = if kind.prefixed() { encoded.get(1..)? } else { encoded };
+        let value = match kind {
+            IndexKind::Integer => IndexValue::Integer(encoded[0]),
+            IndexKind::IpAddress => {
+                let a = octets(encoded)?;
+                IndexValue::IpAddress(Ipv4Addr::new(a[0], a[1], a[2], a[3]))
+            }
+            IndexKind::OctetString { .. } => IndexValue::OctetString(octets(encoded)?),
+            IndexKind::Oid { .. } => IndexValue::Oid(oids::oid(encoded)),
+        };
+        values.push((kind, value));
+    }
+    if suffix.is_empty() {
+        Some(values)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: &str = r#"
+TEST-NAMES-MIB DEFINITIONS ::= BEGIN
+IMPORTS
+    OBJECT-TYPE, Integer32, IpAddress, mib-2 FROM SNMPv2-SMI
+    DisplayString, MacAddress FROM SNMPv2-TC;
+
+testTable OBJECT-TYPE
+    SYNTAX SEQUENCE OF TestEntry MAX-ACCESS not-accessible STATUS current
+    ::= { mib-2 9998 1 }
+
+testEntry OBJECT-TYPE
+    SYNTAX TestEntry MAX-ACCESS not-accessible STATUS current
+    INDEX { testIndex, testAddr, testMac, IMPLIED testName }
+    ::= { testTable 1 }
+
+TestEntry ::= SEQUENCE {
+    testIndex Integer32, testAddr IpAddress, testMac MacAddress, testName DisplayString,
+    testValue Integer32
+}
+
+testIndex OBJECT-TYPE SYNTAX Integer32 MAX-ACCESS not-accessible STATUS current ::= { testEntry 1 }
+testAddr OBJECT-TYPE SYNTAX IpAddress MAX-ACCESS not-accessible STATUS current ::= { testEntry 2 }
+testMac OBJECT-TYPE SYNTAX MacAddress MAX-ACCESS not-accessible STATUS current ::= { testEntry 3 }
+testName OBJECT-TYPE SYNTAX DisplayString MAX-ACCESS not-accessible STATUS current ::= { testEntry 4 }
+testValue OBJECT-TYPE SYNTAX Integer32 MAX-ACCESS read-only STATUS current ::= { testEntry 5 }
+
+testScalar OBJECT-TYPE SYNTAX Integer32 MAX-ACCESS read-only STATUS current ::= { mib-2 9998 2 }
+END
+"#;
+
+    const INSTANCE: &[u64] = &[
+        1, 3, 6, 1, 2, 1, 9998, 1, 1, 5,
+        3,
+        10, 0, 0, 1,
+        0, 17, 34, 65, 66, 255,
+        101, 116, 104, 48,
+    ];
+
+    fn mib() -> Mib {
+        let mut mib = Mib::new();
+        mib.load_str(MIB).unwrap();
+        mib
+    }
+
+    #[test]
+    fn names() {
+        let mib = mib();
+        let instance = oids::oid(INSTANCE);
+        assert_eq!(mib.name(&instance), r#"testValue.3.10.0.0.1."\x00\x11\"AB\xff"."eth0""#);
+        assert_eq!(
+            mib.full_name(&instance),
+            r#"TEST-NAMES-MIB::testValue.3.10.0.0.1."\x00\x11\"AB\xff"."eth0""#
+        );
+
+        let (column, suffix) = mib.lookup(&instance).unwrap();
+        assert_eq!(
+            mib.decode_index(column, suffix),
+            Some(vec![
+                IndexValue::Integer(3),
+                IndexValue::IpAddress(Ipv4Addr::new(10, 0, 0, 1)),
+                IndexValue::OctetString(vec![0, 17, 34, 65, 66, 255]),
+                IndexValue::OctetString(b"eth0".to_vec()),
+            ])
+        );
+
+        // Suffixes not matching the INDEX are left numeric.
+        let truncated = oids::oid(&INSTANCE[..12]);
+        assert_eq!(mib.name(&truncated), "testValue.3.10");
+        assert_eq!(mib.name(&oids::oid(&[1, 3, 6, 1, 2, 1, 9998, 2, 0])), "testScalar.0");
+        assert_eq!(mib.name(&oids::oid(&[1, 3, 6, 1, 4, 1, 9])), "enterprises.9");
+    }
+
+    #[test]
+    fn parse() {
+        let mib = mib();
+        let instance = oids::oid(INSTANCE);
+        for text in &[
+            r#"testValue.3.10.0.0.1."\x00\x11\"AB\xff"."eth0""#,
+            r#"TEST-NAMES-MIB::testValue.3.10.0.0.1."\x00\x11\"AB\xff"."eth0""#,
+            r#"testValue.3.10.0.0.1.0.17.34.65.66.255."eth0""#,
+            "testValue.3.10.0.0.1.0.17.34.65.66.255.101.116.104.48",
+            "1.3.6.1.2.1.9998.1.1.5.3.10.0.0.1.0.17.34.65.66.255.101.116.104.48",
+            ".1.3.6.1.2.1.9998.1.1.5.3.10.0.0.1.0.17.34.65.66.255.101.116.104.48",
+        ] {
+            assert_eq!(mib.parse_oid(text).unwrap(), instance, "{}", text);
+        }
+
+        assert_eq!(mib.parse_oid("testScalar.0").unwrap(), oids::oid(&[1, 3, 6, 1, 2, 1, 9998, 2, 0]));
+        assert_eq!(mib.parse_oid("testScalar").unwrap(), oids::oid(&[1, 3, 6, 1, 2, 1, 9998, 2]));
+        match mib.parse_oid("noSuchName.0") {
+            Err(Error::UnknownName(ref name)) => assert_eq!(name, "noSuchName"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(mib.parse_oid(r#"testValue."x""#).is_err());
+        assert!(mib.parse_oid(r#"testValue.3.10.0.0.1."ABC"."eth0""#).is_err());
+        assert!(mib.parse_oid(r#"testValue.3.10.0.0.256.0.17.34.65.66.255."eth0""#).is_err());
+        assert!(mib.parse_oid(r#"testValue.3.10.0.0.1.0.17.34.65.66.256."eth0""#).is_err());
+        assert!(mib.parse_oid("testValue.3.10.0.0.1.0.17.34.65.66.255.101.116.104.304").is_err());
+        assert!(mib.parse_oid("testScalar..0").is_err());
+        assert!(mib.parse_oid("1.3.x").is_err());
+    }
+
+    #[test]
+    fn hostile_suffix() {
+        let string = IndexKind::OctetString { fixed: None, implied: false };
+        let oid = IndexKind::Oid { implied: false };
+        for kind in &[string, oid] {
+            assert_eq!(decode(&[*kind], &[u64::MAX]), None);
+            assert_eq!(decode(&[*kind], &[u64::MAX, 1, 2]), None);
+            assert_eq!(decode(&[*kind], &[1 << 32, 1]), None);
+            assert_eq!(decode(&[*kind], &[3, 1, 2]), None);
+            assert_eq!(decode(&[*kind], &[]), None);
+        }
+        assert_eq!(decode(&[string], &[2, 65, 66]), Some(vec![(string, IndexValue::OctetString(b"AB".to_vec()))]));
+        assert_eq!(decode(&[string, IndexKind::Integer], &[0, 7]), Some(vec![
+            (string, IndexValue::OctetString(Vec::new())),
+            (IndexKind::Integer, IndexValue::Integer(7)),
+        ]));
+    }
+
+    #[test]
+    fn display() {
+        let mib = mib();
+        let bind = VarBind::new(oids::oid(&[1, 3, 6, 1, 2, 1, 9998, 2, 0]), Variable::Integer(5));
+        assert_eq!(mib.display(&bind).to_string(), "testScalar.0 = 5");
+        assert_eq!(mib.display(bind.oid()).to_string(), "testScalar.0");
+    }
+}
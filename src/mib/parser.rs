@@ -0,0 +1,874 @@
+//! Lexer and recursive-descent parser of SMIv1/SMIv2 module definitions.
+//!
+//! Only the parts of ASN.1 used by MIB modules are understood; clauses that do not
+//! contribute to the resolved tree (REFERENCE, DEFVAL, REVISION...) are skipped.
+
+use std::i64;
+use std::mem;
+
+use super::{Access, NodeKind, Status};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Assign,
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Semi,
+    Pipe,
+    Range,
+}
+
+#[derive(Debug)]
+struct Token {
+    tok: Tok,
+    line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub message: String,
+}
+
+fn error<S: Into<String>>(line: usize, message: S) -> SyntaxError {
+    SyntaxError {
+        line: line,
+        message: message.into(),
+    }
+}
+
+fn saturate(digits: &str, radix: u32, negative: bool) -> i64 {
+    match i64::from_str_radix(digits, radix) {
+        Ok(value) if negative => -value,
+        Ok(value) => value,
+        Err(_) if digits.is_empty() => 0,
+        Err(_) if negative => i64::MIN,
+        Err(_) => i64::MAX,
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, SyntaxError> {
+    let chars: Vec<char> = text.chars().collect();
+    let at = |i: usize| chars.get(i).cloned();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    while let Some(c) = at(i) {
+        let start = line;
+        let tok = match c {
+            '\n' => {
+                line += 1;
+                i += 1;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            // Comments run to the end of the line or to the next "--".
+            '-' if at(i + 1) == Some('-') => {
+                i += 2;
+                while let Some(c) = at(i) {
+                    if c == '\n' {
+                        break;
+                    }
+                    i += 1;
+                    if c == '-' && at(i) == Some('-') {
+                        i += 1;
+                        break;
+                    }
+                }
+                continue;
+            }
+            '"' => {
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match at(i) {
+                        None => return Err(error(start, "unterminated string")),
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            value.push(c);
+                        }
+                    }
+                    i += 1;
+                }
+                i += 1;
+                Tok::Str(value)
+            }
+            // Binary ('0101'B) and hexadecimal ('ff'H) strings.
+            '\'' => {
+                let mut digits = String::new();
+                i += 1;
+                loop {
+                    match at(i) {
+                        None => return Err(error(start, "unterminated quoted string")),
+                        Some('\'') => break,
+                        Some(c) if c.is_whitespace() => {}
+                        Some(c) => digits.push(c),
+                    }
+                    i += 1;
+                }
+                let radix = match at(i + 1) {
+                    Some('H') | Some('h') => 16,
+                    Some('B') | Some('b') => 2,
+                    _ => return Err(error(start, "quoted string without B or H suffix")),
+                };
+                i += 2;
+                Tok::Number(saturate(&digits, radix, false))
+            }
+            ':' if at(i + 1) == Some(':') && at(i + 2) == Some('=') => {
+                i += 3;
+                Tok::Assign
+            }
+            '.' if at(i + 1) == Some('.') => {
+                i += 2;
+                Tok::Range
+            }
+            c if c.is_digit(10) || c == '-' && at(i + 1).map_or(false, |c| c.is_digit(10)) => {
+                let negative = c == '-';
+                if negative {
+                    i += 1;
+                }
+                let mut digits = String::new();
+                while let Some(c) = at(i) {
+                    if !c.is_digit(10) {
+                        break;
+                    }
+                    digits.push(c);
+                    i += 1;
+                }
+                Tok::Number(saturate(&digits, 10, negative))
+            }
+            c if c.is_alphabetic() => {
+                let mut ident = String::new();
+                while let Some(c) = at(i) {
+                    let hyphen = c == '-' && at(i + 1) != Some('-');
+                    if !(c.is_alphanumeric() || c == '_' || hyphen) {
+                        break;
+                    }
+                    ident.push(c);
+                    i += 1;
+                }
+                Tok::Ident(ident)
+            }
+            c => {
+                i += 1;
+                match c {
+                    '{' => Tok::LBrace,
+                    '}' => Tok::RBrace,
+                    '(' => Tok::LParen,
+                    ')' => Tok::RParen,
+                    '[' => Tok::LBracket,
+                    ']' => Tok::RBracket,
+                    ',' => Tok::Comma,
+                    ';' => Tok::Semi,
+                    '|' => Tok::Pipe,
+                    c => return Err(error(start, format!("unexpected character {:?}", c))),
+                }
+            }
+        };
+        tokens.push(Token {
+            tok: tok,
+            line: start,
+        });
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+    pub name: String,
+    pub imports: Vec<Import>,
+    pub assignments: Vec<Assignment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub module: String,
+    pub symbols: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Assignment {
+    Node(NodeDef),
+    Type(TypeDef),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OidComponent {
+    Name(String),
+    Number(u64),
+    /// `name(number)`; only the number is significant.
+    Named(String, u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Oid(Vec<OidComponent>),
+    /// Specific trap number of a TRAP-TYPE.
+    Trap(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeRef {
+    Integer,
+    OctetString,
+    ObjectIdentifier,
+    Bits,
+    Sequence,
+    SequenceOf(String),
+    Choice,
+    Named(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Type {
+    pub base: TypeRef,
+    pub named_numbers: Vec<(String, i64)>,
+    pub sizes: Vec<(i64, i64)>,
+    pub ranges: Vec<(i64, i64)>,
+}
+
+impl Type {
+    pub fn new(base: TypeRef) -> Self {
+        Type {
+            base: base,
+            named_numbers: Vec::new(),
+            sizes: Vec::new(),
+            ranges: Vec::new(),
+        }
+    }
+}
+
+/// Clauses shared by the OID-valued macros and TEXTUAL-CONVENTION.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Clauses {
+    pub syntax: Option<Type>,
+    pub access: Option<Access>,
+    pub status: Option<Status>,
+    pub units: Option<String>,
+    pub description: Option<String>,
+    pub display_hint: Option<String>,
+    pub index: Vec<String>,
+    pub implied: bool,
+    pub augments: Option<String>,
+    pub objects: Vec<String>,
+    pub enterprise: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeDef {
+    pub name: String,
+    pub kind: NodeKind,
+    pub value: Value,
+    pub clauses: Clauses,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeDef {
+    pub name: String,
+    pub syntax: Type,
+    pub textual_convention: bool,
+    pub clauses: Clauses,
+}
+
+pub fn parse(text: &str) -> Result<Vec<Module>, SyntaxError> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        pos: 0,
+    };
+    let mut modules = Vec::new();
+    while parser.peek().is_some() {
+        modules.push(parser.module()?);
+    }
+    Ok(modules)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|t| &t.tok)
+    }
+
+    fn at(&self, tok: &Tok) -> bool {
+        self.peek() == Some(tok)
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(&Tok::Ident(ref ident)) => ident == keyword,
+            _ => false,
+        }
+    }
+
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos).or_else(|| self.tokens.last()) {
+            Some(token) => token.line,
+            None => 1,
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> SyntaxError {
+        let found = match self.peek() {
+            Some(tok) => format!("{:?}", tok),
+            None => "end of input".to_owned(),
+        };
+        error(self.line(), format!("expected {}, found {}", expected, found))
+    }
+
+    fn next(&mut self) -> Result<Tok, SyntaxError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.tok.clone())
+            }
+            None => Err(self.unexpected("more input")),
+        }
+    }
+
+    fn expect(&mut self, tok: Tok) -> Result<(), SyntaxError> {
+        if self.at(&tok) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("{:?}", tok)))
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), SyntaxError> {
+        if self.at_keyword(keyword) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, SyntaxError> {
+        match self.peek().cloned() {
+            Some(Tok::Ident(ident)) => {
+                self.pos += 1;
+                Ok(ident)
+            }
+            _ => Err(self.unexpected("identifier")),
+        }
+    }
+
+    fn number(&mut self) -> Result<i64, SyntaxError> {
+        match self.peek().cloned() {
+            Some(Tok::Number(n)) => {
+                self.pos += 1;
+                Ok(n)
+            }
+            _ => Err(self.unexpected("number")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SyntaxError> {
+        match self.peek().cloned() {
+            Some(Tok::Str(s)) => {
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => Err(self.unexpected("string")),
+        }
+    }
+
+    /// Skips one token, or a whole bracketed group if the token opens one.
+    fn skip(&mut self) -> Result<(), SyntaxError> {
+        let mut depth = 0;
+        loop {
+            match self.next()? {
+                Tok::LBrace | Tok::LParen | Tok::LBracket => depth += 1,
+                Tok::RBrace | Tok::RParen | Tok::RBracket => depth -= 1,
+                _ => {}
+            }
+            if depth <= 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_until(&mut self, tok: &Tok) -> Result<(), SyntaxError> {
+        while !self.at(tok) {
+            self.skip()?;
+        }
+        Ok(())
+    }
+
+    fn module(&mut self) -> Result<Module, SyntaxError> {
+        let name = self.ident()?;
+        if self.at(&Tok::LBrace) {
+            self.skip()?;
+        }
+        self.keyword("DEFINITIONS")?;
+        self.skip_until(&Tok::Assign)?;
+        self.expect(Tok::Assign)?;
+        self.keyword("BEGIN")?;
+
+        let mut module = Module {
+            name: name,
+            imports: Vec::new(),
+            assignments: Vec::new(),
+        };
+        loop {
+            if self.at_keyword("END") {
+                self.pos += 1;
+                return Ok(module);
+            } else if self.at_keyword("EXPORTS") {
+                self.skip_until(&Tok::Semi)?;
+                self.pos += 1;
+            } else if self.at_keyword("IMPORTS") {
+                self.pos += 1;
+                module.imports.extend(self.imports()?);
+            } else if let Some(assignment) = self.assignment()? {
+                module.assignments.push(assignment);
+            }
+        }
+    }
+
+    fn imports(&mut self) -> Result<Vec<Import>, SyntaxError> {
+        let mut imports = Vec::new();
+        let mut symbols = Vec::new();
+        loop {
+            match self.next()? {
+                Tok::Semi => return Ok(imports),
+                Tok::Comma => {}
+                Tok::Ident(ref from) if from == "FROM" => imports.push(Import {
+                    module: self.ident()?,
+                    symbols: mem::replace(&mut symbols, Vec::new()),
+                }),
+                Tok::Ident(symbol) => symbols.push(symbol),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected("imported symbol"));
+                }
+            }
+        }
+    }
+
+    fn assignment(&mut self) -> Result<Option<Assignment>, SyntaxError> {
+        let name = self.ident()?;
+        let keyword = match self.next()? {
+            Tok::Assign => return self.type_assignment(name).map(Some),
+            Tok::Ident(keyword) => keyword,
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected("assignment"));
+            }
+        };
+
+        let kind = match keyword.as_str() {
+            "MACRO" => {
+                while !self.at_keyword("END") {
+                    self.next()?;
+                }
+                self.pos += 1;
+                return Ok(None);
+            }
+            "OBJECT" => {
+                self.keyword("IDENTIFIER")?;
+                NodeKind::ObjectIdentifier
+            }
+            "MODULE-IDENTITY" => NodeKind::ModuleIdentity,
+            "OBJECT-IDENTITY" => NodeKind::ObjectIdentity,
+            "OBJECT-TYPE" => NodeKind::ObjectType,
+            "NOTIFICATION-TYPE" => NodeKind::NotificationType,
+            "TRAP-TYPE" => NodeKind::TrapType,
+            "OBJECT-GROUP" => NodeKind::ObjectGroup,
+            "NOTIFICATION-GROUP" => NodeKind::NotificationGroup,
+            "MODULE-COMPLIANCE" => NodeKind::ModuleCompliance,
+            "AGENT-CAPABILITIES" => NodeKind::AgentCapabilities,
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected("known macro"));
+            }
+        };
+
+        let mut clauses = Clauses::default();
+        self.clauses(&mut clauses, false)?;
+        self.expect(Tok::Assign)?;
+        let value = match kind {
+            NodeKind::TrapType => match self.number()? {
+                n if n >= 0 => Value::Trap(n as u64),
+                _ => return Err(error(self.line(), "negative trap number")),
+            },
+            _ => Value::Oid(self.oid_value()?),
+        };
+        Ok(Some(Assignment::Node(NodeDef {
+            name: name,
+            kind: kind,
+            value: value,
+            clauses: clauses,
+        })))
+    }
+
+    fn type_assignment(&mut self, name: String) -> Result<Assignment, SyntaxError> {
+        let mut clauses = Clauses::default();
+        let textual_convention = self.at_keyword("TEXTUAL-CONVENTION");
+        let syntax = if textual_convention {
+            self.pos += 1;
+            self.clauses(&mut clauses, true)?;
+            clauses.syntax.take().ok_or_else(|| self.unexpected("SYNTAX"))?
+        } else {
+            self.syntax()?
+        };
+        Ok(Assignment::Type(TypeDef {
+            name: name,
+            syntax: syntax,
+            textual_convention: textual_convention,
+            clauses: clauses,
+        }))
+    }
+
+    /// Reads macro clauses up to the `::=` of the value, or up to SYNTAX of a textual convention.
+    ///
+    /// The first DESCRIPTION wins, so that REVISION descriptions do not replace the module one.
+    fn clauses(&mut self, clauses: &mut Clauses, textual_convention: bool) -> Result<(), SyntaxError> {
+        loop {
+            let keyword = match self.peek().cloned() {
+                Some(Tok::Assign) => return Ok(()),
+                Some(Tok::Ident(keyword)) => keyword,
+                Some(_) => {
+                    self.skip()?;
+                    continue;
+                }
+                None => return Err(self.unexpected("::=")),
+            };
+            self.pos += 1;
+
+            match keyword.as_str() {
+                "SYNTAX" => {
+                    clauses.syntax = Some(self.syntax()?);
+                    if textual_convention {
+                        return Ok(());
+                    }
+                }
+                "UNITS" => clauses.units = Some(self.string()?),
+                "MAX-ACCESS" | "ACCESS" => {
+                    let access = self.ident()?;
+                    clauses.access = Some(Access::from_keyword(&access).ok_or_else(|| {
+                        error(self.line(), format!("unknown access {}", access))
+                    })?);
+                }
+                "STATUS" => {
+                    let status = self.ident()?;
+                    clauses.status = Some(Status::from_keyword(&status).ok_or_else(|| {
+                        error(self.line(), format!("unknown status {}", status))
+                    })?);
+                }
+                "DESCRIPTION" => {
+                    let description = self.string()?;
+                    if clauses.description.is_none() {
+                        clauses.description = Some(description);
+                    }
+                }
+                "DISPLAY-HINT" => clauses.display_hint = Some(self.string()?),
+                "INDEX" => {
+                    self.expect(Tok::LBrace)?;
+                    loop {
+                        if self.at_keyword("IMPLIED") {
+                            self.pos += 1;
+                            clauses.implied = true;
+                        }
+                        clauses.index.push(self.ident()?);
+                        match self.next()? {
+                            Tok::Comma => {}
+                            Tok::RBrace => break,
+                            _ => {
+                                self.pos -= 1;
+                                return Err(self.unexpected("} or ,"));
+                            }
+                        }
+                    }
+                }
+                "AUGMENTS" => {
+                    self.expect(Tok::LBrace)?;
+                    clauses.augments = Some(self.ident()?);
+                    self.expect(Tok::RBrace)?;
+                }
+                "OBJECTS" | "NOTIFICATIONS" | "VARIABLES" => clauses.objects = self.names()?,
+                "ENTERPRISE" => clauses.enterprise = Some(self.ident()?),
+                // Per-module sections of MODULE-COMPLIANCE and AGENT-CAPABILITIES have their own
+                // SYNTAX and DESCRIPTION clauses, none of which describe the node itself.
+                "MODULE" | "SUPPORTS" => return self.skip_until(&Tok::Assign),
+                _ => {}
+            }
+        }
+    }
+
+    fn names(&mut self) -> Result<Vec<String>, SyntaxError> {
+        let mut names = Vec::new();
+        self.expect(Tok::LBrace)?;
+        loop {
+            match self.next()? {
+                Tok::RBrace => return Ok(names),
+                Tok::Comma => {}
+                Tok::Ident(name) => names.push(name),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected("name"));
+                }
+            }
+        }
+    }
+
+    fn syntax(&mut self) -> Result<Type, SyntaxError> {
+        // Tags of application types ("[APPLICATION 4] IMPLICIT") do not matter here.
+        if self.at(&Tok::LBracket) {
+            self.skip()?;
+            if self.at_keyword("IMPLICIT") || self.at_keyword("EXPLICIT") {
+                self.pos += 1;
+            }
+        }
+
+        let base = match self.ident()?.as_str() {
+            "INTEGER" => TypeRef::Integer,
+            "OCTET" => {
+                self.keyword("STRING")?;
+                TypeRef::OctetString
+            }
+            "OBJECT" => {
+                self.keyword("IDENTIFIER")?;
+                TypeRef::ObjectIdentifier
+            }
+            "BITS" => TypeRef::Bits,
+            "SEQUENCE" if self.at_keyword("OF") => {
+                self.pos += 1;
+                TypeRef::SequenceOf(self.ident()?)
+            }
+            "SEQUENCE" => {
+                self.skip()?;
+                return Ok(Type::new(TypeRef::Sequence));
+            }
+            "CHOICE" => {
+                self.skip()?;
+                return Ok(Type::new(TypeRef::Choice));
+            }
+            name => TypeRef::Named(name.to_owned()),
+        };
+
+        let mut syntax = Type::new(base);
+        if self.at(&Tok::LBrace) {
+            syntax.named_numbers = self.named_numbers()?;
+        } else if self.at(&Tok::LParen) {
+            self.pos += 1;
+            if self.at_keyword("SIZE") {
+                self.pos += 1;
+                self.expect(Tok::LParen)?;
+                syntax.sizes = self.ranges()?;
+                self.expect(Tok::RParen)?;
+            } else {
+                syntax.ranges = self.ranges()?;
+            }
+            self.expect(Tok::RParen)?;
+        }
+        Ok(syntax)
+    }
+
+    fn named_numbers(&mut self) -> Result<Vec<(String, i64)>, SyntaxError> {
+        let mut named = Vec::new();
+        self.expect(Tok::LBrace)?;
+        loop {
+            let name = self.ident()?;
+            self.expect(Tok::LParen)?;
+            named.push((name, self.number()?));
+            self.expect(Tok::RParen)?;
+            match self.next()? {
+                Tok::Comma => {}
+                Tok::RBrace => return Ok(named),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected("} or ,"));
+                }
+            }
+        }
+    }
+
+    fn ranges(&mut self) -> Result<Vec<(i64, i64)>, SyntaxError> {
+        let mut ranges = Vec::new();
+        loop {
+            let low = self.bound()?;
+            let high = if self.at(&Tok::Range) {
+                self.pos += 1;
+                self.bound()?
+            } else {
+                low
+            };
+            ranges.push((low, high));
+            if !self.at(&Tok::Pipe) {
+                return Ok(ranges);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn bound(&mut self) -> Result<i64, SyntaxError> {
+        if self.at_keyword("MIN") {
+            self.pos += 1;
+            Ok(i64::MIN)
+        } else if self.at_keyword("MAX") {
+            self.pos += 1;
+            Ok(i64::MAX)
+        } else {
+            self.number()
+        }
+    }
+
+    fn oid_value(&mut self) -> Result<Vec<OidComponent>, SyntaxError> {
+        let mut components = Vec::new();
+        self.expect(Tok::LBrace)?;
+        loop {
+            let component = match self.next()? {
+                Tok::RBrace => return Ok(components),
+                Tok::Number(n) if n >= 0 => OidComponent::Number(n as u64),
+                Tok::Ident(name) => {
+                    if self.at(&Tok::LParen) {
+                        self.pos += 1;
+                        let n = self.number()?;
+                        self.expect(Tok::RParen)?;
+                        if n < 0 {
+                            return Err(error(self.line(), "negative OID component"));
+                        }
+                        OidComponent::Named(name, n as u64)
+                    } else {
+                        OidComponent::Name(name)
+                    }
+                }
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected("OID component"));
+                }
+            };
+            components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let tokens = tokenize("a-b -- comment -- c\n'ff'H -1..10 \"x\ny\" ::= d--e").unwrap();
+        let toks: Vec<Tok> = tokens.iter().map(|t| t.tok.clone()).collect();
+        assert_eq!(
+            toks,
+            vec![
+                Tok::Ident("a-b".to_owned()),
+                Tok::Ident("c".to_owned()),
+                Tok::Number(255),
+                Tok::Number(-1),
+                Tok::Range,
+                Tok::Number(10),
+                Tok::Str("x\ny".to_owned()),
+                Tok::Assign,
+                Tok::Ident("d".to_owned()),
+            ]
+        );
+        assert_eq!(tokens[2].line, 2);
+        assert_eq!(tokens[8].line, 3);
+    }
+
+    #[test]
+    fn definitions() {
+        let modules = parse(
+            r#"
+TEST-MIB DEFINITIONS ::= BEGIN
+IMPORTS
+    OBJECT-TYPE, Integer32, mib-2 FROM SNMPv2-SMI
+    TEXTUAL-CONVENTION FROM SNMPv2-TC;
+
+Status ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "Status."
+    SYNTAX INTEGER { up(1), down(2) }
+
+Entry ::= SEQUENCE { index Integer32, status Status }
+
+table OBJECT-TYPE
+    SYNTAX SEQUENCE OF Entry
+    MAX-ACCESS not-accessible
+    STATUS current
+    DESCRIPTION "Table."
+    ::= { mib-2 999 1 }
+
+entry OBJECT-TYPE
+    SYNTAX Entry
+    MAX-ACCESS not-accessible
+    STATUS current
+    DESCRIPTION "Row."
+    INDEX { index, IMPLIED name }
+    ::= { table 1 }
+
+name OBJECT-TYPE
+    SYNTAX OCTET STRING (SIZE (0..32))
+    UNITS "chars"
+    MAX-ACCESS read-only
+    STATUS current
+    DEFVAL { "" }
+    ::= { entry 2 }
+END
+"#,
+        ).unwrap();
+
+        assert_eq!(modules.len(), 1);
+        let module = &modules[0];
+        assert_eq!(module.name, "TEST-MIB");
+        assert_eq!(module.imports[1].module, "SNMPv2-TC");
+        assert_eq!(module.imports[0].symbols, vec!["OBJECT-TYPE", "Integer32", "mib-2"]);
+        assert_eq!(module.assignments.len(), 5);
+
+        match module.assignments[0] {
+            Assignment::Type(ref def) => {
+                assert!(def.textual_convention);
+                assert_eq!(def.syntax.named_numbers, vec![("up".to_owned(), 1), ("down".to_owned(), 2)]);
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+        match module.assignments[3] {
+            Assignment::Node(ref def) => {
+                assert_eq!(def.clauses.index, vec!["index", "name"]);
+                assert!(def.clauses.implied);
+                assert_eq!(
+                    def.value,
+                    Value::Oid(vec![OidComponent::Name("table".to_owned()), OidComponent::Number(1)])
+                );
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+        match module.assignments[4] {
+            Assignment::Node(ref def) => {
+                assert_eq!(def.clauses.syntax.as_ref().unwrap().sizes, vec![(0, 32)]);
+                assert_eq!(def.clauses.units, Some("chars".to_owned()));
+                assert_eq!(def.clauses.access, Some(Access::ReadOnly));
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let e = parse("A DEFINITIONS ::= BEGIN\nx OBJECT IDENTIFIER ::= { y 1 \nEND").unwrap_err();
+        assert_eq!(e.line, 3);
+        assert!(parse("A DEFINITIONS ::= BEGIN\n\"x").is_err());
+    }
+}
@@ -0,0 +1,561 @@
+//! Rendering of values according to the syntax of their object, and parsing back.
+
+use std::fmt::Write;
+use std::i32;
+use std::net::Ipv4Addr;
+use std::u32;
+
+use super::{BaseType, Error, Mib, Syntax};
+use types::*;
+
+/// Octet-string DISPLAY-HINT specification (RFC 2579 section 3.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OctetSpec {
+    repeat: bool,
+    len: usize,
+    format: char,
+    separator: Option<char>,
+    terminator: Option<char>,
+}
+
+fn octet_specs(hint: &str) -> Option<Vec<OctetSpec>> {
+    let chars: Vec<char> = hint.chars().collect();
+    let delimiter = |i: usize| match chars.get(i) {
+        Some(&c) if !c.is_digit(10) && c != '*' => Some(c),
+        _ => None,
+    };
+    let mut specs = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let repeat = chars[i] == '*';
+        if repeat {
+            i += 1;
+        }
+        let start = i;
+        while chars.get(i).map_or(false, |c| c.is_digit(10)) {
+            i += 1;
+        }
+        let len: usize = chars[start..i].iter().collect::<String>().parse().ok()?;
+        let format = *chars.get(i)?;
+        if len == 0 || !"dxoat".contains(format) {
+            return None;
+        }
+        i += 1;
+
+        let separator = delimiter(i);
+        if separator.is_some() {
+            i += 1;
+        }
+        let terminator = if repeat { delimiter(i) } else { None };
+        if terminator.is_some() {
+            i += 1;
+        }
+        specs.push(OctetSpec {
+            repeat: repeat,
+            len: len,
+            format: format,
+            separator: separator,
+            terminator: terminator,
+        });
+    }
+    if specs.is_empty() {
+        None
+    } else {
+        Some(specs)
+    }
+}
+
+fn radix(format: char) -> u32 {
+    match format {
+        'x' => 16,
+        'o' => 8,
+        'b' => 2,
+        _ => 10,
+    }
+}
+
+fn format_octets(specs: &[OctetSpec], bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+    let mut index = 0;
+    while pos < bytes.len() {
+        // The last specification applies to all remaining octets.
+        let spec = specs[index.min(specs.len() - 1)];
+        index += 1;
+        let count = if spec.repeat {
+            pos += 1;
+            bytes[pos - 1] as usize
+        } else {
+            1
+        };
+
+        for i in 0..count {
+            if pos >= bytes.len() {
+                break;
+            }
+            let end = (pos + spec.len).min(bytes.len());
+            let chunk = &bytes[pos..end];
+            pos = end;
+
+            let _ = match spec.format {
+                'a' | 't' => out.write_str(&String::from_utf8_lossy(chunk)),
+                format => {
+                    let value = chunk.iter().fold(0u64, |acc, &b| acc << 8 | u64::from(b));
+                    match format {
+                        'x' => write!(out, "{:01$x}", value, chunk.len() * 2),
+                        'o' => write!(out, "{:o}", value),
+                        _ => write!(out, "{}", value),
+                    }
+                }
+            };
+
+            if pos < bytes.len() {
+                let delimiter = match spec.terminator {
+                    Some(terminator) if i + 1 == count => Some(terminator),
+                    _ => spec.separator,
+                };
+                if let Some(c) = delimiter {
+                    out.push(c);
+                }
+            }
+        }
+    }
+    out
+}
+
+fn parse_octets(specs: &[OctetSpec], text: &str) -> Option<Vec<u8>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    let mut index = 0;
+    while i < chars.len() {
+        let spec = specs[index.min(specs.len() - 1)];
+        index += 1;
+        let consumed = i;
+        let count_at = out.len();
+        if spec.repeat {
+            out.push(0);
+        }
+
+        let mut count = 0;
+        loop {
+            match spec.format {
+                'a' | 't' => {
+                    let start = out.len();
+                    while let Some(&c) = chars.get(i) {
+                        if Some(c) == spec.separator || Some(c) == spec.terminator || out.len() - start + c.len_utf8() > spec.len {
+                            break;
+                        }
+                        if spec.format == 'a' && !c.is_ascii() {
+                            return None;
+                        }
+                        let mut buf = [0; 4];
+                        out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                        i += 1;
+                    }
+                }
+                format => {
+                    let radix = radix(format);
+                    let start = i;
+                    while chars.get(i).map_or(false, |c| c.is_digit(radix)) {
+                        i += 1;
+                    }
+                    let digits: String = chars[start..i].iter().collect();
+                    let value = u64::from_str_radix(&digits, radix).ok()?;
+                    if spec.len < 8 && value >> (spec.len * 8) != 0 {
+                        return None;
+                    }
+                    out.extend((0..spec.len).rev().map(|n| if n < 8 { (value >> (n * 8)) as u8 } else { 0 }));
+                }
+            }
+            count += 1;
+
+            let next = chars.get(i).cloned();
+            if spec.repeat && next.is_some() && next == spec.terminator {
+                i += 1;
+                break;
+            }
+            if next.is_some() && next == spec.separator {
+                i += 1;
+                if spec.repeat {
+                    continue;
+                }
+            }
+            break;
+        }
+        // Text the specification cannot take, such as a character longer than its octets.
+        if i == consumed {
+            return None;
+        }
+
+        if spec.repeat {
+            if count > 0xff {
+                return None;
+            }
+            out[count_at] = count as u8;
+        }
+    }
+    Some(out)
+}
+
+fn format_integer(hint: Option<&str>, value: i64) -> String {
+    let hint = match hint {
+        Some(hint) if !hint.is_empty() => hint,
+        _ => return value.to_string(),
+    };
+    let sign = if value < 0 { "-" } else { "" };
+    let magnitude = value.abs();
+    match hint.as_bytes()[0] {
+        b'x' => format!("{}{:x}", sign, magnitude),
+        b'o' => format!("{}{:o}", sign, magnitude),
+        b'b' => format!("{}{:b}", sign, magnitude),
+        b'd' if hint.starts_with("d-") => match hint[2..].parse::<usize>() {
+            Ok(places) if places > 0 => {
+                let digits = format!("{:01$}", magnitude, places + 1);
+                let (integer, fraction) = digits.split_at(digits.len() - places);
+                format!("{}{}.{}", sign, integer, fraction)
+            }
+            _ => value.to_string(),
+        },
+        _ => value.to_string(),
+    }
+}
+
+fn parse_integer(hint: Option<&str>, text: &str) -> Option<i64> {
+    let hint = hint.unwrap_or("d");
+    let (negative, digits) = if text.starts_with('-') {
+        (true, &text[1..])
+    } else {
+        (false, text)
+    };
+    let magnitude = match hint.chars().next() {
+        Some(format @ 'x') | Some(format @ 'o') | Some(format @ 'b') => i64::from_str_radix(digits, radix(format)).ok()?,
+        _ => {
+            let places = if hint.starts_with("d-") { hint[2..].parse().ok()? } else { 0 };
+            let mut split = digits.splitn(2, '.');
+            let integer = split.next().unwrap_or("");
+            let fraction = split.next().unwrap_or("");
+            let digits = format!("{}{}", integer, fraction);
+            if fraction.len() > places || digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let scale = (fraction.len()..places).fold(1i64, |scale, _| scale.saturating_mul(10));
+            digits.parse::<i64>().ok()?.checked_mul(scale)?
+        }
+    };
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+fn format_hex(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    hex.join(" ")
+}
+
+/// Octets in exactly the layout `format_hex` produces.
+fn parse_hex_octets(text: &str) -> Option<Vec<u8>> {
+    if text.is_empty() {
+        return None;
+    }
+    text.split(' ')
+        .map(|pair| if pair.len() == 2 { u8::from_str_radix(pair, 16).ok() } else { None })
+        .collect()
+}
+
+/// OCTET STRING without DISPLAY-HINT: as text when printable and not mistakable for hex, else hex.
+fn format_plain_octets(bytes: &[u8]) -> String {
+    if bytes.iter().all(|&b| (b >= 0x20 && b < 0x7f) || b"\t\r\n".contains(&b)) {
+        let text = String::from_utf8_lossy(bytes).into_owned();
+        if parse_hex_octets(&text).is_none() {
+            return text;
+        }
+    }
+    format_hex(bytes)
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace() && *c != ':').collect();
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(&pair.iter().collect::<String>(), 16).ok())
+        .collect()
+}
+
+/// Value of `name(n)`, `name` or `n` given the enumeration labels or bit names.
+fn parse_named(named: &[(String, i64)], text: &str) -> Option<i64> {
+    if text.ends_with(')') {
+        let open = text.find('(')?;
+        return text[open + 1..text.len() - 1].parse().ok();
+    }
+    match named.iter().find(|&&(ref name, _)| name == text) {
+        Some(&(_, value)) => Some(value),
+        None => text.parse().ok(),
+    }
+}
+
+fn format_bits(named: &[(String, i64)], bytes: &[u8]) -> String {
+    let mut bits = Vec::new();
+    for (i, &byte) in bytes.iter().enumerate() {
+        for bit in 0..8 {
+            if byte & (0x80 >> bit) != 0 {
+                let position = (i * 8 + bit) as i64;
+                bits.push(match named.iter().find(|&&(_, value)| value == position) {
+                    Some(&(ref name, _)) => format!("{}({})", name, position),
+                    None => position.to_string(),
+                });
+            }
+        }
+    }
+    bits.join(" ")
+}
+
+fn parse_bits(named: &[(String, i64)], text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for token in text.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty()) {
+        let position = parse_named(named, token)?;
+        if position < 0 || position > 0xffff {
+            return None;
+        }
+        let position = position as usize;
+        if bytes.len() <= position / 8 {
+            bytes.resize(position / 8 + 1, 0);
+        }
+        bytes[position / 8] |= 0x80 >> (position % 8);
+    }
+    Some(bytes)
+}
+
+fn in_range(value: i64, min: i64, max: i64) -> Option<i64> {
+    if value < min || value > max {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+impl Mib {
+    /// Renders `value` of instance `oid` according to the syntax of its object: DISPLAY-HINT,
+    /// enumeration labels as `up(1)`, BITS names, symbolic OIDs and UNITS.
+    ///
+    /// Values of unknown objects, or not matching the syntax, are displayed as they are.
+    pub fn format_value(&self, oid: &ObjectIdentifier, value: &Variable) -> String {
+        let (syntax, units) = match self.lookup(oid) {
+            Some((node, _)) => match node.syntax() {
+                Some(syntax) => (syntax, node.units()),
+                None => return value.to_string(),
+            },
+            None => return value.to_string(),
+        };
+
+        let hint = syntax.display_hint();
+        let text = match *value {
+            Variable::Integer(n) => {
+                let n = i64::from(n);
+                match syntax.named_numbers().iter().find(|&&(_, value)| value == n) {
+                    Some(&(ref label, _)) => format!("{}({})", label, n),
+                    None => format_integer(hint, n),
+                }
+            }
+            Variable::Counter(n) | Variable::Gauge(n) | Variable::TimeTicks(n) => format_integer(hint, i64::from(n)),
+            Variable::Counter64(n) => n.to_string(),
+            Variable::OctetString(ref s) => {
+                let bytes: &[u8] = s.as_ref();
+                match syntax.base() {
+                    BaseType::Bits => format_bits(syntax.named_numbers(), bytes),
+                    _ => match hint.and_then(octet_specs) {
+                        Some(specs) => format_octets(&specs, bytes),
+                        None => format_plain_octets(bytes),
+                    },
+                }
+            }
+            Variable::Opaque(ref bytes) => format_hex(bytes),
+            Variable::Oid(ref oid) => self.name(oid),
+            Variable::IpAddress(a, b, c, d) => Ipv4Addr::new(a, b, c, d).to_string(),
+            _ => return value.to_string(),
+        };
+        match units {
+            Some(units) => format!("{} {}", text, units),
+            None => text,
+        }
+    }
+
+    /// Parses `text`, in any form `format_value` produces, into a value of instance `oid`.
+    ///
+    /// Enumerations accept `up(1)`, `up` or `1`; BITS a list of bit names or positions;
+    /// OCTET STRINGs without DISPLAY-HINT the hex form `00 11 ff` or else their text as is.
+    pub fn parse_value(&self, oid: &ObjectIdentifier, text: &str) -> Result<Variable, Error> {
+        let node = match self.lookup(oid) {
+            Some((node, _)) if node.syntax().is_some() => node,
+            _ => return Err(Error::UnknownName(self.name(oid))),
+        };
+        let syntax: &Syntax = node.syntax().unwrap();
+        let invalid = || Error::InvalidValue(text.to_owned());
+
+        // OCTET STRING text is taken verbatim, only the " {units}" `format_value` appends is removed.
+        let verbatim = syntax.base() == BaseType::OctetString;
+        let mut value = if verbatim { text } else { text.trim() };
+        if let Some(units) = node.units() {
+            let suffix = format!(" {}", units);
+            if value.ends_with(&suffix) {
+                value = &value[..value.len() - suffix.len()];
+            }
+        }
+        if !verbatim {
+            value = value.trim();
+        }
+
+        let hint = syntax.display_hint();
+        let integer = |min: i64, max: i64| -> Result<i64, Error> {
+            let n = if syntax.named_numbers().is_empty() {
+                parse_integer(hint, value)
+            } else {
+                parse_named(syntax.named_numbers(), value)
+            };
+            n.and_then(|n| in_range(n, min, max)).ok_or_else(&invalid)
+        };
+        let unsigned = || integer(0, i64::from(u32::MAX)).map(|n| n as u32);
+
+        Ok(match syntax.base() {
+            BaseType::Integer => Variable::Integer(integer(i64::from(i32::MIN), i64::from(i32::MAX))? as i32),
+            BaseType::Counter32 => Variable::Counter(unsigned()?),
+            BaseType::Gauge32 | BaseType::Unsigned32 => Variable::Gauge(unsigned()?),
+            BaseType::TimeTicks => Variable::TimeTicks(unsigned()?),
+            BaseType::Counter64 => Variable::Counter64(value.parse().map_err(|_| invalid())?),
+            BaseType::OctetString => {
+                let bytes = match hint.and_then(octet_specs) {
+                    Some(specs) => parse_octets(&specs, value).ok_or_else(&invalid)?,
+                    None => parse_hex_octets(value).unwrap_or_else(|| value.as_bytes().to_vec()),
+                };
+                Variable::OctetString(OctetString::from(bytes))
+            }
+            BaseType::Bits => {
+                let bytes = parse_bits(syntax.named_numbers(), value).ok_or_else(&invalid)?;
+                Variable::OctetString(OctetString::from(bytes))
+            }
+            BaseType::Opaque => Variable::Opaque(parse_hex(value).ok_or_else(&invalid)?),
+            BaseType::ObjectIdentifier => Variable::Oid(self.parse_oid(value)?),
+            BaseType::IpAddress | BaseType::NetworkAddress => {
+                let addr: Ipv4Addr = value.parse().map_err(|_| invalid())?;
+                let o = addr.octets();
+                Variable::IpAddress(o[0], o[1], o[2], o[3])
+            }
+            BaseType::Sequence | BaseType::SequenceOf => return Err(invalid()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oids;
+
+    const MIB: &str = r#"
+TEST-VALUES-MIB DEFINITIONS ::= BEGIN
+IMPORTS
+    OBJECT-TYPE, Integer32, IpAddress, mib-2 FROM SNMPv2-SMI
+    TEXTUAL-CONVENTION, DisplayString, MacAddress, DateAndTime FROM SNMPv2-TC;
+
+Temperature ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "d-2" STATUS current DESCRIPTION "Hundredths of a degree." SYNTAX Integer32
+
+test OBJECT IDENTIFIER ::= { mib-2 9997 }
+
+testStatus OBJECT-TYPE SYNTAX INTEGER { up(1), down(2) } MAX-ACCESS read-write STATUS current ::= { test 1 }
+testMac OBJECT-TYPE SYNTAX MacAddress MAX-ACCESS read-write STATUS current ::= { test 2 }
+testDate OBJECT-TYPE SYNTAX DateAndTime MAX-ACCESS read-write STATUS current ::= { test 3 }
+testFlags OBJECT-TYPE SYNTAX BITS { a(0), b(1), c(9) } MAX-ACCESS read-write STATUS current ::= { test 4 }
+testTemp OBJECT-TYPE
+    SYNTAX Temperature UNITS "degrees" MAX-ACCESS read-write STATUS current ::= { test 5 }
+testName OBJECT-TYPE SYNTAX DisplayString MAX-ACCESS read-write STATUS current ::= { test 6 }
+testType OBJECT-TYPE SYNTAX OBJECT IDENTIFIER MAX-ACCESS read-write STATUS current ::= { test 7 }
+testAddr OBJECT-TYPE SYNTAX IpAddress MAX-ACCESS read-write STATUS current ::= { test 8 }
+testData OBJECT-TYPE SYNTAX OCTET STRING MAX-ACCESS read-write STATUS current ::= { test 9 }
+testAnswer OBJECT-TYPE
+    SYNTAX DisplayString UNITS "s" MAX-ACCESS read-write STATUS current ::= { test 10 }
+END
+"#;
+
+    fn mib() -> Mib {
+        let mut mib = Mib::new();
+        mib.load_str(MIB).unwrap();
+        mib
+    }
+
+    fn instance(column: u64) -> ObjectIdentifier {
+        oids::oid(&[1, 3, 6, 1, 2, 1, 9997, column, 0])
+    }
+
+    fn octets(bytes: &[u8]) -> Variable {
+        Variable::OctetString(OctetString::from(bytes.to_vec()))
+    }
+
+    #[test]
+    fn round_trip() {
+        let mib = mib();
+        let date = [0x07, 0xd1, 10, 17, 13, 30, 15, 0, b'+', 2, 0];
+        for &(column, ref value, text) in &[
+            (1, Variable::Integer(2), "down(2)"),
+            (1, Variable::Integer(5), "5"),
+            (2, octets(&[0, 0x11, 0x22, 0xaa, 0xbb, 0xcc]), "00:11:22:aa:bb:cc"),
+            (3, octets(&date), "2001-10-17,13:30:15.0,+2:0"),
+            (3, octets(&date[..8]), "2001-10-17,13:30:15.0"),
+            (4, octets(&[0xa0, 0x40]), "a(0) 2 c(9)"),
+            (5, Variable::Integer(2150), "21.50 degrees"),
+            (5, Variable::Integer(-5), "-0.05 degrees"),
+            (6, octets(b"eth0"), "eth0"),
+            (7, Variable::Oid(instance(1)), "testStatus.0"),
+            (8, Variable::IpAddress(10, 0, 0, 1), "10.0.0.1"),
+            (9, octets(&[0, 0x11, 0xff]), "00 11 ff"),
+            (9, octets(b"eth0"), "eth0"),
+            (9, octets(b"ab"), "61 62"),
+            (9, octets(b""), ""),
+            (9, octets(b" eth0"), " eth0"),
+            (10, octets(b"yes"), "yes s"),
+            (10, octets(b"ye s"), "ye s s"),
+        ] {
+            assert_eq!(mib.format_value(&instance(column), value), text);
+            assert_eq!(&mib.parse_value(&instance(column), text).unwrap(), value, "{}", text);
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let mib = mib();
+        assert_eq!(mib.parse_value(&instance(1), "down").unwrap(), Variable::Integer(2));
+        assert_eq!(mib.parse_value(&instance(1), "2").unwrap(), Variable::Integer(2));
+        assert_eq!(mib.parse_value(&instance(4), "c, a 2").unwrap(), octets(&[0xa0, 0x40]));
+        assert_eq!(mib.parse_value(&instance(5), "21.5").unwrap(), Variable::Integer(2150));
+        assert_eq!(mib.parse_value(&instance(5), " 21.50 degrees ").unwrap(), Variable::Integer(2150));
+        assert_eq!(mib.parse_value(&instance(10), "yes").unwrap(), octets(b"yes"));
+
+        for &(column, text) in &[(1, "sideways"), (5, "21.505"), (2, "00:11:2g"), (8, "10.0.0")] {
+            match mib.parse_value(&instance(column), text) {
+                Err(Error::InvalidValue(ref value)) => assert_eq!(value, text),
+                other => panic!("unexpected {:?} for {}", other, text),
+            }
+        }
+        assert!(mib.parse_value(&oids::oid(&[2, 999]), "1").is_err());
+    }
+
+    #[test]
+    fn parse_octets_without_progress() {
+        let ascii = octet_specs("1a").unwrap();
+        assert_eq!(parse_octets(&ascii, "eth0"), Some(b"eth0".to_vec()));
+        assert_eq!(parse_octets(&ascii, "\u{e9}"), None);
+        assert_eq!(parse_octets(&octet_specs("255a").unwrap(), "caf\u{e9}"), None);
+        assert_eq!(parse_octets(&octet_specs("1t").unwrap(), "\u{e9}"), None);
+        assert_eq!(parse_octets(&octet_specs("2t").unwrap(), "\u{e9}"), Some("\u{e9}".as_bytes().to_vec()));
+        assert_eq!(parse_octets(&octet_specs("1x:").unwrap(), "00:zz"), None);
+    }
+
+    #[test]
+    fn unknown_objects() {
+        let mib = mib();
+        let value = Variable::Opaque(vec![1, 2]);
+        assert_eq!(mib.format_value(&oids::oid(&[2, 999]), &value), value.to_string());
+        // Values not matching the syntax of the object.
+        assert_eq!(mib.format_value(&instance(2), &Variable::Null), Variable::Null.to_string());
+    }
+}
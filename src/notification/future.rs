@@ -0,0 +1,157 @@
+//! Asynchronous notification receiver on top of tokio UDP socket.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use futures::{Async, Poll, Stream};
+use tokio::net::UdpSocket;
+
+use client::MAX_DATAGRAM_SIZE;
+use notification::{Notification, Processor};
+use error::Error;
+use security::{LocalEngine, UserTable, UsmError};
+
+/// Stream of traps and informs of all versions, see `Processor` for the message handling.
+///
+/// Informs are acknowledged on a best effort basis before being yielded. The stream only
+/// fails on receive errors.
+#[derive(Debug)]
+pub struct NotificationReceiver {
+    socket: UdpSocket,
+    processor: Processor,
+    buf: Vec<u8>,
+    reply: Option<(Vec<u8>, SocketAddr)>,
+    ready: Option<Notification>,
+}
+
+impl NotificationReceiver {
+    /// Binds to `addr`, usually port 162. `engine` is the authoritative engine v3 informs
+    /// are sent to, `users` authenticate v3 traps and informs.
+    pub fn bind(addr: &SocketAddr, engine: LocalEngine, users: UserTable) -> Result<Self, Error> {
+        Ok(NotificationReceiver {
+            socket: UdpSocket::bind(addr)?,
+            processor: Processor::new(engine, users).map_err(UsmError::from)?,
+            buf: vec![0; MAX_DATAGRAM_SIZE],
+            reply: None,
+            ready: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn engine(&self) -> &LocalEngine {
+        self.processor.engine()
+    }
+
+    pub fn users(&self) -> &UserTable {
+        self.processor.users()
+    }
+
+    pub fn users_mut(&mut self) -> &mut UserTable {
+        self.processor.users_mut()
+    }
+}
+
+impl Stream for NotificationReceiver {
+    type Item = Notification;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Notification>, io::Error> {
+        loop {
+            if let Some((ref reply, ref to)) = self.reply {
+                match self.socket.poll_send_to(reply, to) {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    // A lost acknowledgement only makes the originator retransmit the inform,
+                    // the notification itself is still delivered.
+                    Ok(Async::Ready(_)) | Err(_) => {}
+                }
+            }
+            self.reply = None;
+
+            if let Some(notification) = self.ready.take() {
+                return Ok(Async::Ready(Some(notification)));
+            }
+
+            let (len, from) = try_ready!(self.socket.poll_recv_from(&mut self.buf));
+            let processed = self.processor.process(&self.buf[..len], from, Instant::now());
+            self.reply = processed.reply.map(|reply| (reply, from));
+            self.ready = processed.notification;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket as StdUdpSocket;
+    use std::time::Duration;
+
+    use asn1_exp::{from_asn1, to_asn1};
+    use tokio::runtime::current_thread::Runtime;
+
+    use super::*;
+    use oids;
+    use types::*;
+
+    fn inform_binds() -> VarBindList {
+        vec![
+            VarBind::new(oids::oid(oids::SYS_UP_TIME), Variable::TimeTicks(42)),
+            VarBind::new(oids::oid(oids::SNMP_TRAP_OID), Variable::Oid(oids::oid(&[1, 3, 6, 1, 6, 3, 1, 1, 5, 1]))),
+        ]
+    }
+
+    fn inform() -> PacketV2 {
+        PacketV2::new(
+            Version::Version2,
+            Community::new("public"),
+            Pdu::Inform(InnerPdu::new(9, ErrorStatus::NoError, 0, inform_binds())),
+        )
+    }
+
+    fn receiver() -> NotificationReceiver {
+        let engine = LocalEngine::new(EngineID::new(b"receiver".to_vec()), 1);
+        NotificationReceiver::bind(&"127.0.0.1:0".parse().unwrap(), engine, UserTable::new()).unwrap()
+    }
+
+    #[test]
+    fn inform_is_acknowledged() {
+        let receiver = receiver();
+        let addr = receiver.local_addr().unwrap();
+
+        let binds = inform_binds();
+        let inform = inform();
+        let sender = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        sender.send_to(&to_asn1(&inform).unwrap(), addr).unwrap();
+
+        let mut runtime = Runtime::new().unwrap();
+        let (notification, _) = runtime.block_on(receiver.into_future()).map_err(|(e, _)| e).unwrap();
+        let notification = notification.unwrap();
+        assert_eq!(notification.source(), sender.local_addr().unwrap());
+        assert_eq!(notification.trap_oid(), &oids::oid(&[1, 3, 6, 1, 6, 3, 1, 1, 5, 1]));
+
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        let (len, _) = sender.recv_from(&mut buf).unwrap();
+        let ack: PacketV2 = from_asn1(&buf[..len]).unwrap();
+        assert_eq!(*ack.pdu(), Pdu::GetResponse(InnerPdu::new(9, ErrorStatus::NoError, 0, binds)));
+    }
+
+    #[test]
+    fn inform_is_delivered_if_acknowledgement_fails() {
+        let mut receiver = receiver();
+
+        // Port 0 is not a valid destination, so sending the Response fails.
+        let from: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let processed = receiver.processor.process(&to_asn1(&inform()).unwrap(), from, Instant::now());
+        receiver.reply = processed.reply.map(|reply| (reply, from));
+        receiver.ready = processed.notification;
+        assert!(receiver.reply.is_some());
+
+        let mut runtime = Runtime::new().unwrap();
+        let (notification, receiver) = runtime.block_on(receiver.into_future()).map_err(|(e, _)| e).unwrap();
+        assert_eq!(notification.unwrap().trap_oid(), &oids::oid(&[1, 3, 6, 1, 6, 3, 1, 1, 5, 1]));
+        assert!(receiver.reply.is_none());
+    }
+}
@@ -0,0 +1,391 @@
+//! Notification (trap and inform) receivers and originator.
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use asn1_exp::to_asn1;
+
+use client::MAX_DATAGRAM_SIZE;
+use message::Message;
+use security::{LocalEngine, PrivError, UserTable};
+use security::usm::UsmProcessor;
+use trap;
+use types::*;
+
+#[cfg(feature = "async")]
+pub mod future;
+pub mod originator;
+pub mod sync;
+
+#[cfg(feature = "async")]
+pub use self::future::NotificationReceiver;
+pub use self::originator::Originator;
+pub use self::sync::SyncReceiver;
+
+/// How the sender of a notification identified itself.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Security {
+    /// v1 and v2c.
+    Community(Community),
+    /// v3 with USM.
+    Usm {
+        user: UserName,
+        security_level: SecurityLevel,
+        /// Authoritative engine of the message: the sender for traps, the receiver for informs.
+        engine_id: EngineID,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum NotificationKind {
+    Trap,
+    /// Already acknowledged by the receiver.
+    Inform,
+}
+
+/// Received notification normalized to the SNMPv2 form.
+///
+/// v1 traps are translated as described in RFC 3584 section 3.1, so their
+/// bindings end with `snmpTrapAddress.0`, `snmpTrapCommunity.0` and `snmpTrapEnterprise.0`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Notification {
+    source: SocketAddr,
+    version: Version,
+    security: Security,
+    kind: NotificationKind,
+    trap_oid: ObjectIdentifier,
+    uptime: u32,
+    binds: VarBindList,
+}
+
+impl Notification {
+    /// Builds the notification from SNMPv2-Trap-PDU or InformRequest-PDU,
+    /// `None` if it does not start with `sysUpTime.0` and `snmpTrapOID.0`.
+    fn new(
+        source: SocketAddr,
+        version: Version,
+        security: Security,
+        kind: NotificationKind,
+        pdu: InnerPdu,
+    ) -> Option<Notification> {
+        let (uptime, trap_oid) = match trap::notification_header(pdu.binds()) {
+            Ok((uptime, trap_oid)) => (uptime, trap_oid.clone()),
+            Err(_) => return None,
+        };
+        let binds = pdu.into_binds().into_iter().skip(2).collect();
+
+        Some(Notification {
+            source: source,
+            version: version,
+            security: security,
+            kind: kind,
+            trap_oid: trap_oid,
+            uptime: uptime,
+            binds: binds,
+        })
+    }
+
+    pub fn source(&self) -> SocketAddr {
+        self.source
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn security(&self) -> &Security {
+        &self.security
+    }
+
+    pub fn kind(&self) -> NotificationKind {
+        self.kind
+    }
+
+    /// `snmpTrapOID.0` value.
+    pub fn trap_oid(&self) -> &ObjectIdentifier {
+        &self.trap_oid
+    }
+
+    /// `sysUpTime.0` value of the sender, in hundredths of a second.
+    pub fn uptime(&self) -> u32 {
+        self.uptime
+    }
+
+    /// Bindings following `sysUpTime.0` and `snmpTrapOID.0`.
+    pub fn binds(&self) -> &VarBindList {
+        &self.binds
+    }
+
+    pub fn into_binds(self) -> VarBindList {
+        self.binds
+    }
+}
+
+/// Outcome of processing a single datagram.
+#[derive(Debug, Default)]
+pub(crate) struct Processed {
+    pub notification: Option<Notification>,
+    /// Inform acknowledgement or USM Report to be sent back to the source.
+    pub reply: Option<Vec<u8>>,
+}
+
+/// Transport independent message processing of a notification receiver.
+///
+/// Malformed, unauthenticated and non-notification messages are dropped.
+/// v3 traps are authenticated with keys localized to the sender's engine,
+/// v3 informs must be addressed to the local engine and be within its time window.
+#[derive(Debug)]
+pub(crate) struct Processor {
+    usm: UsmProcessor,
+}
+
+impl Processor {
+    pub fn new(engine: LocalEngine, users: UserTable) -> Result<Self, PrivError> {
+        Ok(Processor {
+            usm: UsmProcessor::new(engine, users, MAX_DATAGRAM_SIZE as u32)?,
+        })
+    }
+
+    pub fn engine(&self) -> &LocalEngine {
+        self.usm.engine()
+    }
+
+    pub fn users(&self) -> &UserTable {
+        self.usm.users()
+    }
+
+    pub fn users_mut(&mut self) -> &mut UserTable {
+        self.usm.users_mut()
+    }
+
+    pub fn process(&mut self, buf: &[u8], source: SocketAddr, now: Instant) -> Processed {
+        match Message::decode(buf) {
+            Ok(Message::Version1(packet)) | Ok(Message::Version2(packet)) => process_community(packet, source),
+            Ok(Message::Version3(packet)) => self.process_usm(buf, packet, source, now),
+            Err(_) => Processed::default(),
+        }
+    }
+
+    fn process_usm(&mut self, buf: &[u8], packet: PacketV3, source: SocketAddr, now: Instant) -> Processed {
+        let accepted = match self.usm.accept(buf, packet, true, now) {
+            Ok(accepted) => accepted,
+            Err(report) => {
+                return Processed {
+                    notification: None,
+                    reply: report,
+                }
+            }
+        };
+        let security = Security::Usm {
+            user: accepted.params.user_name().clone(),
+            security_level: accepted.flags.security_level,
+            engine_id: accepted.params.engine_id().clone(),
+        };
+
+        match *accepted.scoped.pdu() {
+            Pdu::Trap(ref pdu) => Processed {
+                notification: Notification::new(source, Version::Version3, security, NotificationKind::Trap, pdu.clone()),
+                reply: None,
+            },
+            Pdu::Inform(ref pdu) if accepted.authoritative => Processed {
+                reply: self.usm.respond(&accepted, acknowledgement(pdu), now),
+                notification: Notification::new(source, Version::Version3, security, NotificationKind::Inform, pdu.clone()),
+            },
+            _ => Processed::default(),
+        }
+    }
+}
+
+fn process_community(packet: PacketV2, source: SocketAddr) -> Processed {
+    let version = packet.version();
+    let community = packet.community().clone();
+    let security = Security::Community(community.clone());
+
+    match packet.into_pdu() {
+        Pdu::TrapV1(ref pdu) if version == Version::Version1 => {
+            let pdu = trap::v1_to_v2(pdu, Some(&community), 0);
+            Processed {
+                notification: Notification::new(source, version, security, NotificationKind::Trap, pdu),
+                reply: None,
+            }
+        }
+        Pdu::Trap(pdu) if version != Version::Version1 => Processed {
+            notification: Notification::new(source, version, security, NotificationKind::Trap, pdu),
+            reply: None,
+        },
+        Pdu::Inform(pdu) if version != Version::Version1 => {
+            let response = PacketV2::new(version, community, acknowledgement(&pdu));
+            Processed {
+                reply: to_asn1(&response).ok(),
+                notification: Notification::new(source, version, security, NotificationKind::Inform, pdu),
+            }
+        }
+        _ => Processed::default(),
+    }
+}
+
+/// Response-PDU acknowledging an InformRequest-PDU (RFC 3416 section 4.2.7).
+fn acknowledgement(inform: &InnerPdu) -> Pdu {
+    Pdu::GetResponse(InnerPdu::new(inform.request_id(), ErrorStatus::NoError, 0, inform.binds().clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use asn1_exp::from_asn1;
+
+    use super::*;
+    use oids;
+    use security::{testing, AuthProtocol, EngineState, PrivProtocol, UsmReport, UsmUser};
+
+    fn source() -> SocketAddr {
+        "192.0.2.1:162".parse().unwrap()
+    }
+
+    fn notification_binds() -> VarBindList {
+        vec![
+            VarBind::new(oids::oid(oids::SYS_UP_TIME), Variable::TimeTicks(42)),
+            VarBind::new(oids::oid(oids::SNMP_TRAP_OID), Variable::Oid(oids::oid(&[1, 3, 6, 1, 6, 3, 1, 1, 5, 4]))),
+            VarBind::new(oids::oid(&[1, 3, 6, 1, 2, 1, 2, 2, 1, 1, 3]), Variable::Integer(3)),
+        ]
+    }
+
+    fn collector() -> UsmUser {
+        UsmUser::new("collector")
+            .with_auth(AuthProtocol::Sha1, b"authpassword")
+            .with_privacy(PrivProtocol::Aes128, b"privpassword")
+    }
+
+    fn processor() -> Processor {
+        let mut users = UserTable::new();
+        users.insert(collector());
+        Processor::new(LocalEngine::new(EngineID::new(b"receiver".to_vec()), 1), users).unwrap()
+    }
+
+    /// v3 message protected for user `collector` on behalf of `engine_id`.
+    fn usm_message(pdu: Pdu, engine_id: &[u8], boots: u32, time: u32, reportable: bool) -> Vec<u8> {
+        let flags = MessageFlags::new(reportable, SecurityLevel::AuthPriv);
+        testing::protect(testing::packet(77, flags, engine_id, boots, time, "collector", pdu), &collector())
+    }
+
+    #[test]
+    fn v1_trap() {
+        let trap = TrapV1Pdu::new(
+            oids::oid(&[1, 3, 6, 1, 4, 1, 318]),
+            "192.168.1.10".parse().unwrap(),
+            GenericTrap::EnterpriseSpecific,
+            5,
+            123456,
+            Vec::new(),
+        );
+        let message = to_asn1(&PacketV2::new(Version::Version1, Community::new("public"), Pdu::TrapV1(trap))).unwrap();
+
+        let processed = processor().process(&message, source(), Instant::now());
+        let notification = processed.notification.unwrap();
+        assert!(processed.reply.is_none());
+        assert_eq!(notification.version(), Version::Version1);
+        assert_eq!(notification.security(), &Security::Community(Community::new("public")));
+        assert_eq!(notification.trap_oid(), &oids::oid(&[1, 3, 6, 1, 4, 1, 318, 0, 5]));
+        assert_eq!(notification.uptime(), 123456);
+        assert_eq!(notification.binds().len(), 3);
+    }
+
+    #[test]
+    fn v2c_inform_is_acknowledged() {
+        let pdu = Pdu::Inform(InnerPdu::new(1234, ErrorStatus::NoError, 0, notification_binds()));
+        let message = to_asn1(&PacketV2::new(Version::Version2, Community::new("public"), pdu)).unwrap();
+
+        let processed = processor().process(&message, source(), Instant::now());
+        let notification = processed.notification.unwrap();
+        assert_eq!(notification.kind(), NotificationKind::Inform);
+        assert_eq!(notification.source(), source());
+        assert_eq!(notification.uptime(), 42);
+        assert_eq!(notification.binds(), &notification_binds()[2..].to_vec());
+
+        let reply: PacketV2 = from_asn1(&processed.reply.unwrap()).unwrap();
+        assert_eq!(reply.community(), &Community::new("public"));
+        assert_eq!(*reply.pdu(), Pdu::GetResponse(InnerPdu::new(1234, ErrorStatus::NoError, 0, notification_binds())));
+    }
+
+    #[test]
+    fn v3_trap() {
+        let pdu = Pdu::Trap(InnerPdu::new(5, ErrorStatus::NoError, 0, notification_binds()));
+        let message = usm_message(pdu, b"sender", 7, 100000, false);
+
+        let processed = processor().process(&message, source(), Instant::now());
+        let notification = processed.notification.unwrap();
+        assert!(processed.reply.is_none());
+        assert_eq!(notification.version(), Version::Version3);
+        assert_eq!(
+            notification.security(),
+            &Security::Usm {
+                user: UserName::new("collector"),
+                security_level: SecurityLevel::AuthPriv,
+                engine_id: EngineID::new(b"sender".to_vec()),
+            }
+        );
+        assert_eq!(notification.trap_oid(), &oids::oid(&[1, 3, 6, 1, 6, 3, 1, 1, 5, 4]));
+
+        let mut tampered = message.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0xff;
+        assert!(processor().process(&tampered, source(), Instant::now()).notification.is_none());
+    }
+
+    #[test]
+    fn v3_trap_timeliness() {
+        let mut processor = processor();
+        let now = Instant::now();
+        let pdu = Pdu::Trap(InnerPdu::new(5, ErrorStatus::NoError, 0, notification_binds()));
+        let mut accepted = |boots, time| {
+            let message = usm_message(pdu.clone(), b"sender", boots, time, false);
+            processor.process(&message, source(), now).notification.is_some()
+        };
+
+        assert!(accepted(7, 100000));
+        assert!(!accepted(6, 100000));
+        assert!(!accepted(7, 99000));
+        assert!(accepted(7, 99900));
+        assert!(accepted(8, 0));
+        assert!(!accepted(7, 100000));
+    }
+
+    #[test]
+    fn v3_inform() {
+        let mut processor = processor();
+        let now = Instant::now() + Duration::from_secs(500);
+        let pdu = Pdu::Inform(InnerPdu::new(5, ErrorStatus::NoError, 0, notification_binds()));
+
+        // Originator has not discovered the receiver engine yet.
+        let discovery = to_asn1(&EngineState::discovery_packet(1, 1, MAX_DATAGRAM_SIZE as u32)).unwrap();
+        let report: PacketV3 = from_asn1(&processor.process(&discovery, source(), now).reply.unwrap()).unwrap();
+        assert_eq!(report.security_parameters().engine_id(), processor.engine().engine_id());
+
+        let stale = usm_message(pdu.clone(), b"receiver", 1, 0, true);
+        let processed = processor.process(&stale, source(), now);
+        assert!(processed.notification.is_none());
+        let report: PacketV3 = from_asn1(&processed.reply.unwrap()).unwrap();
+        assert!(report.flags().is_authenticated());
+        assert_eq!(report.security_parameters().engine_time(), 500);
+
+        // Timeliness is checked before decryption: a stale message is never decrypted.
+        let other = UsmUser::new("collector")
+            .with_auth(AuthProtocol::Sha1, b"authpassword")
+            .with_privacy(PrivProtocol::Aes128, b"otherpassword");
+        let flags = MessageFlags::new(true, SecurityLevel::AuthPriv);
+        let garbled = testing::protect(testing::packet(77, flags, b"receiver", 1, 0, "collector", pdu.clone()), &other);
+        let report: PacketV3 = from_asn1(&processor.process(&garbled, source(), now).reply.unwrap()).unwrap();
+        match *report.pdu() {
+            PduV3::Scoped(ref scoped) => assert_eq!(UsmReport::from_pdu(scoped.pdu()), Some(UsmReport::NotInTimeWindow)),
+            PduV3::Crypted(_) => panic!("report must not be encrypted"),
+        }
+
+        let message = usm_message(pdu, b"receiver", 1, 500, true);
+        let processed = processor.process(&message, source(), now);
+        assert_eq!(processed.notification.unwrap().kind(), NotificationKind::Inform);
+        let reply: PacketV3 = from_asn1(&processed.reply.unwrap()).unwrap();
+        assert_eq!(reply.msg_id(), 77);
+        assert!(reply.flags().is_encrypted());
+    }
+}
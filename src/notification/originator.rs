@@ -0,0 +1,211 @@
+//! Notification originator sending v2c/v3 traps and informs.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use client::{CommunityCodec, Error, RequestIds, SessionOptions, SyncSession, MAX_DATAGRAM_SIZE};
+use oids;
+use security::{self, LocalEngine, LocalizedKeys, KeyCache, SaltGenerator, UsmError, UsmUser};
+use types::*;
+
+/// Bindings of a v2 notification: `sysUpTime.0` and `snmpTrapOID.0` followed by `binds`.
+pub fn notification_binds(uptime: u32, trap_oid: &ObjectIdentifier, binds: VarBindList) -> VarBindList {
+    let mut all = Vec::with_capacity(binds.len() + 2);
+    all.push(VarBind::new(oids::oid(oids::SYS_UP_TIME), Variable::TimeTicks(uptime)));
+    all.push(VarBind::new(oids::oid(oids::SNMP_TRAP_OID), Variable::Oid(trap_oid.clone())));
+    all.extend(binds);
+    all
+}
+
+/// Trap encoding; the originator is the authoritative engine of v3 traps.
+#[derive(Debug)]
+enum TrapCodec {
+    Community(CommunityCodec),
+    Usm {
+        user: UsmUser,
+        engine: LocalEngine,
+        keys: LocalizedKeys,
+        salts: SaltGenerator,
+        msg_ids: RequestIds,
+    },
+}
+
+/// Blocking originator of notifications to a single receiver.
+///
+/// Traps are sent once without waiting; informs are retransmitted according to
+/// the session options until acknowledged. v3 informs discover the receiver engine first.
+#[derive(Debug)]
+pub struct Originator {
+    session: SyncSession,
+    trap_codec: TrapCodec,
+    ids: RequestIds,
+}
+
+impl Originator {
+    /// v2c originator.
+    pub fn new(peer: SocketAddr, community: Community) -> io::Result<Self> {
+        let codec = CommunityCodec {
+            version: Version::Version2,
+            community: community.clone(),
+        };
+        Ok(Originator {
+            session: SyncSession::new(peer, Version::Version2, community)?,
+            trap_codec: TrapCodec::Community(codec),
+            ids: RequestIds::new(),
+        })
+    }
+
+    /// v3 originator; traps are sent on behalf of `engine`.
+    pub fn new_v3(peer: SocketAddr, user: UsmUser, engine: LocalEngine) -> Result<Self, Error> {
+        let keys = user.localize(engine.engine_id(), &mut KeyCache::new())
+            .map_err(|e| Error::Usm(UsmError::Auth(e)))?;
+        Ok(Originator {
+            session: SyncSession::new_v3(peer, user.clone())?,
+            trap_codec: TrapCodec::Usm {
+                user: user,
+                engine: engine,
+                keys: keys,
+                salts: SaltGenerator::new().map_err(UsmError::from)?,
+                msg_ids: RequestIds::new(),
+            },
+            ids: RequestIds::new(),
+        })
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.session.peer()
+    }
+
+    pub fn options(&self) -> &SessionOptions {
+        self.session.options()
+    }
+
+    pub fn set_options(&mut self, value: SessionOptions) {
+        self.session.set_options(value)
+    }
+
+    /// Sends an SNMPv2-Trap-PDU without waiting for any acknowledgement.
+    pub fn trap(&mut self, uptime: u32, trap_oid: &ObjectIdentifier, binds: VarBindList) -> Result<(), Error> {
+        let pdu = Pdu::Trap(InnerPdu::new(self.ids.next(), ErrorStatus::NoError, 0, notification_binds(uptime, trap_oid, binds)));
+        let message = match self.trap_codec {
+            TrapCodec::Community(ref codec) => codec.encode(pdu)?,
+            TrapCodec::Usm {
+                ref user,
+                ref engine,
+                ref keys,
+                ref mut salts,
+                ref mut msg_ids,
+            } => {
+                let header = HeaderData::new(
+                    msg_ids.next(),
+                    MAX_DATAGRAM_SIZE as u32,
+                    MessageFlags::new(false, user.security_level()),
+                    SecurityModel::UserBasedSecurityModel,
+                );
+                let mut params = SecurityParameter::new_discovery();
+                params.set_user_name(user.name().clone());
+                engine.prepare(&mut params, Instant::now());
+
+                let scoped = ScopedPdu::new(
+                    ContextEngineID::new(engine.engine_id().as_bytes().to_vec()),
+                    ContextName::new(""),
+                    pdu,
+                );
+                let packet = PacketV3::new(header, params, PduV3::Scoped(scoped));
+                security::usm::protect(packet, keys, salts)?
+            }
+        };
+        Ok(self.session.send(&message)?)
+    }
+
+    /// Sends an InformRequest-PDU and waits for the receiver's Response-PDU, returning its bindings.
+    pub fn inform(&mut self, uptime: u32, trap_oid: &ObjectIdentifier, binds: VarBindList) -> Result<VarBindList, Error> {
+        let pdu = Pdu::Inform(InnerPdu::new(0, ErrorStatus::NoError, 0, notification_binds(uptime, trap_oid, binds)));
+        self.session.request(pdu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use notification::{NotificationKind, Security, SyncReceiver};
+    use security::{AuthProtocol, PrivProtocol, UserTable};
+
+    fn user() -> UsmUser {
+        UsmUser::new("originator")
+            .with_auth(AuthProtocol::Sha256, b"authpassword")
+            .with_privacy(PrivProtocol::Aes128, b"privpassword")
+    }
+
+    /// Receiver forwarding everything it gets to the returned channel.
+    fn receiver() -> (SocketAddr, mpsc::Receiver<::notification::Notification>) {
+        let mut users = UserTable::new();
+        users.insert(user());
+        let engine = LocalEngine::new(EngineID::new(b"receiver".to_vec()), 1);
+        let mut receiver = SyncReceiver::bind("127.0.0.1:0", engine, users).unwrap();
+        let addr = receiver.local_addr().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || loop {
+            let notification = receiver.recv().unwrap();
+            if tx.send(notification).is_err() {
+                break;
+            }
+        });
+        (addr, rx)
+    }
+
+    fn trap_oid() -> ObjectIdentifier {
+        oids::oid(&[1, 3, 6, 1, 6, 3, 1, 1, 5, 3])
+    }
+
+    fn binds() -> VarBindList {
+        vec![VarBind::new(oids::oid(&[1, 3, 6, 1, 2, 1, 2, 2, 1, 1, 3]), Variable::Integer(3))]
+    }
+
+    #[test]
+    fn v2c() {
+        let (addr, rx) = receiver();
+        let mut originator = Originator::new(addr, Community::new("public")).unwrap();
+
+        originator.trap(100, &trap_oid(), binds()).unwrap();
+        let trap = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(trap.kind(), NotificationKind::Trap);
+        assert_eq!(trap.uptime(), 100);
+        assert_eq!(trap.binds(), &binds());
+
+        let acknowledged = originator.inform(200, &trap_oid(), binds()).unwrap();
+        assert_eq!(acknowledged, notification_binds(200, &trap_oid(), binds()));
+        let inform = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(inform.kind(), NotificationKind::Inform);
+        assert_eq!(inform.security(), &Security::Community(Community::new("public")));
+    }
+
+    #[test]
+    fn v3() {
+        let (addr, rx) = receiver();
+        let engine = LocalEngine::new(EngineID::new(b"originator".to_vec()), 4);
+        let mut originator = Originator::new_v3(addr, user(), engine).unwrap();
+
+        originator.trap(100, &trap_oid(), binds()).unwrap();
+        let trap = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            trap.security(),
+            &Security::Usm {
+                user: UserName::new("originator"),
+                security_level: SecurityLevel::AuthPriv,
+                engine_id: EngineID::new(b"originator".to_vec()),
+            }
+        );
+
+        originator.inform(200, &trap_oid(), binds()).unwrap();
+        let inform = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(inform.kind(), NotificationKind::Inform);
+        assert_eq!(inform.uptime(), 200);
+    }
+}
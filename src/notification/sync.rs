@@ -0,0 +1,62 @@
+//! Blocking notification receiver on top of `std::net::UdpSocket`.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Instant;
+
+use client::MAX_DATAGRAM_SIZE;
+use notification::{Notification, Processor};
+use error::Error;
+use security::{LocalEngine, UserTable, UsmError};
+
+/// Receiver of traps and informs of all versions, see `Processor` for the message handling.
+#[derive(Debug)]
+pub struct SyncReceiver {
+    socket: UdpSocket,
+    processor: Processor,
+    buf: Vec<u8>,
+}
+
+impl SyncReceiver {
+    /// Binds to `addr`, usually port 162. `engine` is the authoritative engine v3 informs
+    /// are sent to, `users` authenticate v3 traps and informs.
+    pub fn bind<A: ToSocketAddrs>(addr: A, engine: LocalEngine, users: UserTable) -> Result<Self, Error> {
+        Ok(SyncReceiver {
+            socket: UdpSocket::bind(addr)?,
+            processor: Processor::new(engine, users).map_err(UsmError::from)?,
+            buf: vec![0; MAX_DATAGRAM_SIZE],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn engine(&self) -> &LocalEngine {
+        self.processor.engine()
+    }
+
+    pub fn users(&self) -> &UserTable {
+        self.processor.users()
+    }
+
+    pub fn users_mut(&mut self) -> &mut UserTable {
+        self.processor.users_mut()
+    }
+
+    /// Blocks until the next valid notification, acknowledging informs on a best effort basis.
+    pub fn recv(&mut self) -> io::Result<Notification> {
+        loop {
+            let (len, from) = self.socket.recv_from(&mut self.buf)?;
+            let processed = self.processor.process(&self.buf[..len], from, Instant::now());
+            if let Some(reply) = processed.reply {
+                // A lost acknowledgement only makes the originator retransmit the inform,
+                // the notification itself is still delivered.
+                let _ = self.socket.send_to(&reply, from);
+            }
+            if let Some(notification) = processed.notification {
+                return Ok(notification);
+            }
+        }
+    }
+}
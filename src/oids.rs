@@ -0,0 +1,28 @@
+//! Well-known object identifiers.
+
+use types::ObjectIdentifier;
+
+pub const SYS_UP_TIME: &[u64] = &[1, 3, 6, 1, 2, 1, 1, 3, 0];
+
+pub const SNMP_TRAP_OID: &[u64] = &[1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0];
+pub const SNMP_TRAP_ENTERPRISE: &[u64] = &[1, 3, 6, 1, 6, 3, 1, 1, 4, 3, 0];
+/// Parent of the generic traps coldStart (.1) to egpNeighborLoss (.6).
+pub const SNMP_TRAPS: &[u64] = &[1, 3, 6, 1, 6, 3, 1, 1, 5];
+
+pub const SNMP_TRAP_ADDRESS: &[u64] = &[1, 3, 6, 1, 6, 3, 18, 1, 3, 0];
+pub const SNMP_TRAP_COMMUNITY: &[u64] = &[1, 3, 6, 1, 6, 3, 18, 1, 4, 0];
+
+pub const USM_STATS_UNSUPPORTED_SEC_LEVELS: &[u64] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 1, 0];
+pub const USM_STATS_NOT_IN_TIME_WINDOWS: &[u64] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 2, 0];
+pub const USM_STATS_UNKNOWN_USER_NAMES: &[u64] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 3, 0];
+pub const USM_STATS_UNKNOWN_ENGINE_IDS: &[u64] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 4, 0];
+pub const USM_STATS_WRONG_DIGESTS: &[u64] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 5, 0];
+pub const USM_STATS_DECRYPTION_ERRORS: &[u64] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 6, 0];
+
+pub fn oid(components: &[u64]) -> ObjectIdentifier {
+    ObjectIdentifier::from(components.to_vec())
+}
+
+pub fn components(oid: &ObjectIdentifier) -> &[u64] {
+    oid.as_ref()
+}
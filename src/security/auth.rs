@@ -0,0 +1,236 @@
+//! Message authentication: HMAC-MD5-96 and HMAC-SHA-96 (RFC 3414),
+//! HMAC-SHA-224/256/384/512 (RFC 7860).
+
+use std::error::Error;
+use std::fmt::{self, Display};
+
+use asn1_exp::to_asn1;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+use types::{AuthenticationParameter, PacketV3};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum AuthProtocol {
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl AuthProtocol {
+    pub fn message_digest(&self) -> MessageDigest {
+        match *self {
+            AuthProtocol::Md5 => MessageDigest::md5(),
+            AuthProtocol::Sha1 => MessageDigest::sha1(),
+            AuthProtocol::Sha224 => MessageDigest::sha224(),
+            AuthProtocol::Sha256 => MessageDigest::sha256(),
+            AuthProtocol::Sha384 => MessageDigest::sha384(),
+            AuthProtocol::Sha512 => MessageDigest::sha512(),
+        }
+    }
+
+    /// Length of the localized key, equal to the digest length.
+    pub fn key_len(&self) -> usize {
+        match *self {
+            AuthProtocol::Md5 => 16,
+            AuthProtocol::Sha1 => 20,
+            AuthProtocol::Sha224 => 28,
+            AuthProtocol::Sha256 => 32,
+            AuthProtocol::Sha384 => 48,
+            AuthProtocol::Sha512 => 64,
+        }
+    }
+
+    /// Length of the truncated MAC carried in `msgAuthenticationParameters`.
+    pub fn mac_len(&self) -> usize {
+        match *self {
+            AuthProtocol::Md5 | AuthProtocol::Sha1 => 12,
+            AuthProtocol::Sha224 => 16,
+            AuthProtocol::Sha256 => 24,
+            AuthProtocol::Sha384 => 32,
+            AuthProtocol::Sha512 => 48,
+        }
+    }
+}
+
+impl Display for AuthProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            AuthProtocol::Md5 => "HMAC-MD5-96",
+            AuthProtocol::Sha1 => "HMAC-SHA-96",
+            AuthProtocol::Sha224 => "HMAC-SHA-224",
+            AuthProtocol::Sha256 => "HMAC-SHA-256",
+            AuthProtocol::Sha384 => "HMAC-SHA-384",
+            AuthProtocol::Sha512 => "HMAC-SHA-512",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// Message could not be serialized.
+    Encoding,
+    /// `msgAuthenticationParameters` could not be located or has a wrong length.
+    MalformedParameters,
+    /// Computed MAC does not match the received one.
+    WrongDigest,
+    /// Keys can not be derived from an empty passphrase.
+    EmptyPassword,
+    Crypto(ErrorStack),
+}
+
+impl From<ErrorStack> for AuthError {
+    fn from(e: ErrorStack) -> Self {
+        AuthError::Crypto(e)
+    }
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AuthError::Crypto(ref e) => write!(f, "{}: {}", self.description(), e),
+            _ => f.write_str(self.description()),
+        }
+    }
+}
+
+impl Error for AuthError {
+    fn description(&self) -> &str {
+        match *self {
+            AuthError::Encoding => "failed to encode message",
+            AuthError::MalformedParameters => "malformed authentication parameters",
+            AuthError::WrongDigest => "wrong message digest",
+            AuthError::EmptyPassword => "empty passphrase",
+            AuthError::Crypto(_) => "crypto error",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            AuthError::Crypto(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Computes HMAC of `data` truncated to the protocol MAC length.
+pub fn hmac(protocol: AuthProtocol, key: &[u8], data: &[u8]) -> Result<Vec<u8>, AuthError> {
+    let pkey = PKey::hmac(key)?;
+    let mut signer = Signer::new(protocol.message_digest(), &pkey)?;
+    signer.update(data)?;
+    let mut mac = signer.sign_to_vec()?;
+    mac.truncate(protocol.mac_len());
+    Ok(mac)
+}
+
+/// Authenticates a serialized message in place.
+///
+/// `msgAuthenticationParameters` must already be a placeholder of `mac_len` bytes;
+/// it is zeroed, the MAC is computed over the whole message and written over it.
+pub fn sign_message(protocol: AuthProtocol, key: &[u8], message: &mut [u8]) -> Result<(), AuthError> {
+    let range = PacketV3::auth_parameters_range(message).ok_or(AuthError::MalformedParameters)?;
+    if range.len() != protocol.mac_len() {
+        return Err(AuthError::MalformedParameters);
+    }
+
+    for b in &mut message[range.clone()] {
+        *b = 0;
+    }
+    let mac = hmac(protocol, key, message)?;
+    message[range].copy_from_slice(&mac);
+    Ok(())
+}
+
+/// Serializes and authenticates an outgoing packet.
+pub fn sign(protocol: AuthProtocol, key: &[u8], packet: &PacketV3) -> Result<Vec<u8>, AuthError> {
+    let mut packet = packet.clone();
+    packet
+        .security_parameters_mut()
+        .set_auth_parameters(AuthenticationParameter::new(vec![0; protocol.mac_len()]));
+
+    let mut message = to_asn1(&packet).map_err(|_| AuthError::Encoding)?;
+    sign_message(protocol, key, &mut message)?;
+    Ok(message)
+}
+
+/// Verifies MAC of a received serialized message.
+pub fn verify(protocol: AuthProtocol, key: &[u8], message: &[u8]) -> Result<(), AuthError> {
+    let range = PacketV3::auth_parameters_range(message).ok_or(AuthError::MalformedParameters)?;
+    if range.len() != protocol.mac_len() {
+        return Err(AuthError::MalformedParameters);
+    }
+
+    let received = message[range.clone()].to_vec();
+    let mut message = message.to_vec();
+    for b in &mut message[range] {
+        *b = 0;
+    }
+
+    let mac = hmac(protocol, key, &message)?;
+    if memcmp::eq(&mac, &received) {
+        Ok(())
+    } else {
+        Err(AuthError::WrongDigest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use security::testing::{self, unhex};
+    use types::*;
+
+    #[test]
+    fn hmac_vectors() {
+        // RFC 2202 and RFC 4231 test case 2, truncated to the USM MAC length.
+        let key = b"Jefe";
+        let data = b"what do ya want for nothing?";
+        let cases = [
+            (AuthProtocol::Md5, "750c783e6ab0b503eaa86e310a5db738"),
+            (AuthProtocol::Sha1, "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79"),
+            (AuthProtocol::Sha224, "a30e01098bc6dbbf45690f3a7e9e6d0f8bbea2a39e6148008fd05e44"),
+            (AuthProtocol::Sha256, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"),
+            (
+                AuthProtocol::Sha384,
+                "af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e\
+                 8e2240ca5e69e2c78b3239ecfab21649",
+            ),
+            (
+                AuthProtocol::Sha512,
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+                 9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+            ),
+        ];
+
+        for &(protocol, expected) in cases.iter() {
+            let expected = unhex(expected);
+            assert_eq!(hmac(protocol, key, data).unwrap(), &expected[..protocol.mac_len()]);
+        }
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let flags = MessageFlags::new(true, SecurityLevel::AuthNoPriv);
+        let engine_id = unhex("000000000000000000000002");
+        let packet = testing::packet(1, flags, &engine_id, 1, 100, "user", Pdu::new_empty_request(RequestType::Get));
+        let key = vec![0x42; 32];
+
+        let mut message = sign(AuthProtocol::Sha256, &key, &packet).unwrap();
+        verify(AuthProtocol::Sha256, &key, &message).unwrap();
+
+        let last = message.len() - 1;
+        message[last] ^= 0x01;
+        match verify(AuthProtocol::Sha256, &key, &message) {
+            Err(AuthError::WrongDigest) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,482 @@
+//! Discovery of the authoritative engine and timeliness checks (RFC 3414 sections 3.2.7 and 4).
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+use oids;
+use types::*;
+
+/// Maximum allowed difference between message time and the local notion of engine time.
+pub const TIME_WINDOW: u32 = 150;
+
+/// `snmpEngineBoots` value after which the engine is locked out until reconfigured.
+pub const MAX_ENGINE_BOOTS: u32 = 2147483647;
+
+/// USM error reported by the authoritative engine, identified by the first varbind of a Report PDU.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum UsmReport {
+    UnsupportedSecLevel,
+    NotInTimeWindow,
+    UnknownUserName,
+    UnknownEngineID,
+    WrongDigest,
+    DecryptionError,
+}
+
+impl UsmReport {
+    pub fn from_pdu(pdu: &Pdu) -> Option<UsmReport> {
+        let inner = match *pdu {
+            Pdu::Report(ref inner) => inner,
+            _ => return None,
+        };
+        let bind = inner.binds().first()?;
+
+        match oids::components(bind.oid()) {
+            oid if oid == oids::USM_STATS_UNSUPPORTED_SEC_LEVELS => Some(UsmReport::UnsupportedSecLevel),
+            oid if oid == oids::USM_STATS_NOT_IN_TIME_WINDOWS => Some(UsmReport::NotInTimeWindow),
+            oid if oid == oids::USM_STATS_UNKNOWN_USER_NAMES => Some(UsmReport::UnknownUserName),
+            oid if oid == oids::USM_STATS_UNKNOWN_ENGINE_IDS => Some(UsmReport::UnknownEngineID),
+            oid if oid == oids::USM_STATS_WRONG_DIGESTS => Some(UsmReport::WrongDigest),
+            oid if oid == oids::USM_STATS_DECRYPTION_ERRORS => Some(UsmReport::DecryptionError),
+            _ => None,
+        }
+    }
+
+    /// `usmStats` counter carried in the Report PDU.
+    pub fn oid(&self) -> &'static [u64] {
+        match *self {
+            UsmReport::UnsupportedSecLevel => oids::USM_STATS_UNSUPPORTED_SEC_LEVELS,
+            UsmReport::NotInTimeWindow => oids::USM_STATS_NOT_IN_TIME_WINDOWS,
+            UsmReport::UnknownUserName => oids::USM_STATS_UNKNOWN_USER_NAMES,
+            UsmReport::UnknownEngineID => oids::USM_STATS_UNKNOWN_ENGINE_IDS,
+            UsmReport::WrongDigest => oids::USM_STATS_WRONG_DIGESTS,
+            UsmReport::DecryptionError => oids::USM_STATS_DECRYPTION_ERRORS,
+        }
+    }
+}
+
+impl Display for UsmReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            UsmReport::UnsupportedSecLevel => "usmStatsUnsupportedSecLevels",
+            UsmReport::NotInTimeWindow => "usmStatsNotInTimeWindows",
+            UsmReport::UnknownUserName => "usmStatsUnknownUserNames",
+            UsmReport::UnknownEngineID => "usmStatsUnknownEngineIDs",
+            UsmReport::WrongDigest => "usmStatsWrongDigests",
+            UsmReport::DecryptionError => "usmStatsDecryptionErrors",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum EngineError {
+    /// Message is outside of the 150 seconds time window or engine boots mismatch.
+    NotInTimeWindow,
+    /// Message came from a different authoritative engine.
+    UnknownEngineID,
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl Error for EngineError {
+    fn description(&self) -> &str {
+        match *self {
+            EngineError::NotInTimeWindow => "message is not in time window",
+            EngineError::UnknownEngineID => "unknown engine id",
+        }
+    }
+}
+
+/// Result of feeding a received message into `EngineState`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum EngineEvent {
+    /// Engine ID (and possibly time) was learnt from a discovery report.
+    Discovered,
+    /// Boots and time were resynchronized after `usmStatsNotInTimeWindows` report.
+    Resynchronized,
+    /// Regular message, local time estimate updated if needed.
+    Updated,
+}
+
+#[derive(Debug, Clone)]
+struct Clock {
+    boots: u32,
+    time: u32,
+    latest_received_time: u32,
+    synced_at: Instant,
+}
+
+/// Non-authoritative side view of a remote SNMP engine.
+///
+/// Transport agnostic: feed received packets in with `handle_packet`
+/// and stamp outgoing ones with `prepare`.
+#[derive(Debug, Clone, Default)]
+pub struct EngineState {
+    engine_id: Option<EngineID>,
+    clock: Option<Clock>,
+}
+
+impl EngineState {
+    pub fn new() -> Self {
+        EngineState::default()
+    }
+
+    /// Engine with the ID known up front (e.g. configured), time is still to be learnt.
+    pub fn with_engine_id(engine_id: EngineID) -> Self {
+        EngineState {
+            engine_id: Some(engine_id),
+            clock: None,
+        }
+    }
+
+    pub fn engine_id(&self) -> Option<&EngineID> {
+        self.engine_id.as_ref()
+    }
+
+    pub fn is_discovered(&self) -> bool {
+        self.engine_id.is_some()
+    }
+
+    pub fn is_synchronized(&self) -> bool {
+        self.clock.is_some()
+    }
+
+    pub fn engine_boots(&self) -> u32 {
+        self.clock.as_ref().map(|c| c.boots).unwrap_or(0)
+    }
+
+    /// Local estimate of the remote `snmpEngineTime`.
+    pub fn engine_time(&self, now: Instant) -> u32 {
+        match self.clock {
+            Some(ref c) => c.time.saturating_add(elapsed_secs(c.synced_at, now)),
+            None => 0,
+        }
+    }
+
+    /// Builds the RFC 3414 section 4 discovery request: noAuthNoPriv, reportable,
+    /// empty security parameters and an empty GetRequest.
+    pub fn discovery_packet(msg_id: u32, request_id: u32, max_size: u32) -> PacketV3 {
+        let header = HeaderData::new(
+            msg_id,
+            max_size,
+            MessageFlags::new(true, SecurityLevel::NoAuthNoPriv),
+            SecurityModel::UserBasedSecurityModel,
+        );
+        let scoped = ScopedPdu::new(
+            ContextEngineID::new(Vec::new()),
+            ContextName::new(""),
+            Pdu::new_request(RequestType::Get, request_id, 0, 0, Vec::new()),
+        );
+        PacketV3::new(header, SecurityParameter::new_discovery(), PduV3::Scoped(scoped))
+    }
+
+    /// Fills engine ID, boots and time of outgoing security parameters.
+    pub fn prepare(&self, params: &mut SecurityParameter, now: Instant) {
+        if let Some(ref engine_id) = self.engine_id {
+            params.set_engine_id(engine_id.clone());
+        }
+        params.set_engine_boots(self.engine_boots());
+        params.set_engine_time(self.engine_time(now));
+    }
+
+    /// Processes a received packet, already authenticated and decrypted if its flags say so.
+    ///
+    /// An undiscovered engine ID is learnt from any `usmStatsUnknownEngineIDs` report, but a
+    /// discovered one is never replaced. Boots and time are only taken from authenticated
+    /// messages (RFC 3414 section 3.2 step 7b).
+    pub fn handle_packet(&mut self, packet: &PacketV3, now: Instant) -> Result<EngineEvent, EngineError> {
+        let params = packet.security_parameters();
+        let authenticated = packet.flags().is_authenticated();
+        let report = match *packet.pdu() {
+            PduV3::Scoped(ref scoped) => UsmReport::from_pdu(scoped.pdu()),
+            PduV3::Crypted(_) => None,
+        };
+
+        match report {
+            Some(UsmReport::UnknownEngineID) => {
+                if self.engine_id.is_some() {
+                    self.check_engine_id(params.engine_id())?;
+                } else {
+                    self.engine_id = Some(params.engine_id().clone());
+                }
+                if authenticated && (params.engine_boots() != 0 || params.engine_time() != 0) {
+                    self.synchronize(params.engine_boots(), params.engine_time(), now);
+                }
+                Ok(EngineEvent::Discovered)
+            }
+            Some(UsmReport::NotInTimeWindow) => {
+                self.check_engine_id(params.engine_id())?;
+                if !authenticated {
+                    return Err(EngineError::NotInTimeWindow);
+                }
+                self.synchronize(params.engine_boots(), params.engine_time(), now);
+                Ok(EngineEvent::Resynchronized)
+            }
+            _ => {
+                self.check_engine_id(params.engine_id())?;
+                if !authenticated {
+                    return Ok(EngineEvent::Updated);
+                }
+                self.check_time_window(params.engine_boots(), params.engine_time(), now)?;
+                self.update(params.engine_boots(), params.engine_time(), now);
+                Ok(EngineEvent::Updated)
+            }
+        }
+    }
+
+    /// RFC 3414 section 3.2.7 b) check performed by the non-authoritative engine.
+    pub fn check_time_window(&self, boots: u32, time: u32, now: Instant) -> Result<(), EngineError> {
+        let clock = match self.clock {
+            Some(ref c) => c,
+            None => return Ok(()),
+        };
+
+        let local_boots = clock.boots;
+        let local_time = self.engine_time(now);
+
+        if local_boots >= MAX_ENGINE_BOOTS
+            || boots < local_boots
+            || (boots == local_boots && time.saturating_add(TIME_WINDOW) < local_time)
+        {
+            return Err(EngineError::NotInTimeWindow);
+        }
+        Ok(())
+    }
+
+    fn check_engine_id(&self, engine_id: &EngineID) -> Result<(), EngineError> {
+        match self.engine_id {
+            Some(ref known) if known != engine_id => Err(EngineError::UnknownEngineID),
+            Some(_) => Ok(()),
+            None => Err(EngineError::UnknownEngineID),
+        }
+    }
+
+    fn synchronize(&mut self, boots: u32, time: u32, now: Instant) {
+        self.clock = Some(Clock {
+            boots: boots,
+            time: time,
+            latest_received_time: time,
+            synced_at: now,
+        });
+    }
+
+    /// RFC 3414 section 3.2.7 b) update of the local notion of engine time.
+    fn update(&mut self, boots: u32, time: u32, now: Instant) {
+        let newer = match self.clock {
+            Some(ref c) => boots > c.boots || (boots == c.boots && time > c.latest_received_time),
+            None => true,
+        };
+        if newer {
+            self.synchronize(boots, time, now);
+        }
+    }
+}
+
+/// Authoritative side SNMP engine: the local engine ID, boots and time.
+///
+/// Receivers of Inform PDUs (and agents) are authoritative for the messages sent to them.
+#[derive(Debug, Clone)]
+pub struct LocalEngine {
+    engine_id: EngineID,
+    boots: u32,
+    started: Instant,
+    reports: [u32; 6],
+}
+
+impl LocalEngine {
+    /// `boots` should be persisted and incremented by the application on every restart.
+    pub fn new(engine_id: EngineID, boots: u32) -> Self {
+        LocalEngine {
+            engine_id: engine_id,
+            boots: boots,
+            started: Instant::now(),
+            reports: [0; 6],
+        }
+    }
+
+    pub fn engine_id(&self) -> &EngineID {
+        &self.engine_id
+    }
+
+    pub fn engine_boots(&self) -> u32 {
+        self.boots
+    }
+
+    pub fn engine_time(&self, now: Instant) -> u32 {
+        elapsed_secs(self.started, now)
+    }
+
+    /// Fills engine ID, boots and time of outgoing security parameters.
+    pub fn prepare(&self, params: &mut SecurityParameter, now: Instant) {
+        params.set_engine_id(self.engine_id.clone());
+        params.set_engine_boots(self.boots);
+        params.set_engine_time(self.engine_time(now));
+    }
+
+    /// RFC 3414 section 3.2.7 a) check performed by the authoritative engine.
+    pub fn check_time_window(&self, boots: u32, time: u32, now: Instant) -> Result<(), EngineError> {
+        let local_time = self.engine_time(now);
+        let difference = if time > local_time {
+            time - local_time
+        } else {
+            local_time - time
+        };
+
+        if self.boots >= MAX_ENGINE_BOOTS || boots != self.boots || difference > TIME_WINDOW {
+            return Err(EngineError::NotInTimeWindow);
+        }
+        Ok(())
+    }
+
+    /// Builds a noAuthNoPriv Report answering message `msg_id`, incrementing the reported counter.
+    pub fn report_packet(
+        &mut self,
+        report: UsmReport,
+        msg_id: u32,
+        max_size: u32,
+        request_id: u32,
+        user_name: UserName,
+        now: Instant,
+    ) -> PacketV3 {
+        let counter = &mut self.reports[report as usize];
+        *counter = counter.wrapping_add(1);
+
+        let header = HeaderData::new(
+            msg_id,
+            max_size,
+            MessageFlags::new(false, SecurityLevel::NoAuthNoPriv),
+            SecurityModel::UserBasedSecurityModel,
+        );
+        let mut params = SecurityParameter::new_discovery();
+        params.set_user_name(user_name);
+        self.prepare(&mut params, now);
+
+        let bind = VarBind::new(oids::oid(report.oid()), Variable::Counter(*counter));
+        let scoped = ScopedPdu::new(
+            ContextEngineID::new(self.engine_id.as_bytes().to_vec()),
+            ContextName::new(""),
+            Pdu::Report(InnerPdu::new(request_id, ErrorStatus::NoError, 0, vec![bind])),
+        );
+        PacketV3::new(header, params, PduV3::Scoped(scoped))
+    }
+}
+
+fn elapsed_secs(since: Instant, now: Instant) -> u32 {
+    if now <= since {
+        return 0;
+    }
+    let elapsed: Duration = now - since;
+    if elapsed.as_secs() > u32::max_value() as u64 {
+        u32::max_value()
+    } else {
+        elapsed.as_secs() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use security::testing;
+
+    fn report(oid: &[u64], level: SecurityLevel, engine_id: &[u8], boots: u32, time: u32) -> PacketV3 {
+        let bind = VarBind::new(oids::oid(oid), Variable::Counter(1));
+        let pdu = Pdu::Report(InnerPdu::new(1, ErrorStatus::NoError, 0, vec![bind]));
+        testing::packet(1, MessageFlags::new(false, level), engine_id, boots, time, "", pdu)
+    }
+
+    /// Engine discovered as `engine` and synchronized to boots 5, time 1000 at `now`.
+    fn synchronized(now: Instant) -> EngineState {
+        let mut engine = EngineState::new();
+        let unknown = report(oids::USM_STATS_UNKNOWN_ENGINE_IDS, SecurityLevel::NoAuthNoPriv, b"engine", 0, 0);
+        engine.handle_packet(&unknown, now).unwrap();
+        let window = report(oids::USM_STATS_NOT_IN_TIME_WINDOWS, SecurityLevel::AuthNoPriv, b"engine", 5, 1000);
+        engine.handle_packet(&window, now).unwrap();
+        engine
+    }
+
+    #[test]
+    fn discovery_and_resync() {
+        let now = Instant::now();
+        let mut engine = EngineState::new();
+        assert!(!engine.is_discovered());
+
+        let unknown = report(oids::USM_STATS_UNKNOWN_ENGINE_IDS, SecurityLevel::NoAuthNoPriv, b"engine", 5, 1000);
+        assert_eq!(engine.handle_packet(&unknown, now), Ok(EngineEvent::Discovered));
+        assert_eq!(engine.engine_id(), Some(&EngineID::new(b"engine".to_vec())));
+        assert!(!engine.is_synchronized());
+
+        let window = report(oids::USM_STATS_NOT_IN_TIME_WINDOWS, SecurityLevel::AuthNoPriv, b"engine", 5, 1000);
+        assert_eq!(engine.handle_packet(&window, now), Ok(EngineEvent::Resynchronized));
+        assert_eq!(engine.engine_boots(), 5);
+        assert_eq!(engine.engine_time(now + Duration::from_secs(10)), 1010);
+
+        let window = report(oids::USM_STATS_NOT_IN_TIME_WINDOWS, SecurityLevel::AuthNoPriv, b"engine", 6, 3);
+        assert_eq!(engine.handle_packet(&window, now), Ok(EngineEvent::Resynchronized));
+        assert_eq!(engine.engine_boots(), 6);
+        assert_eq!(engine.engine_time(now), 3);
+
+        let window = report(oids::USM_STATS_NOT_IN_TIME_WINDOWS, SecurityLevel::AuthNoPriv, b"other", 6, 3);
+        assert_eq!(engine.handle_packet(&window, now), Err(EngineError::UnknownEngineID));
+    }
+
+    #[test]
+    fn unauthenticated_reports() {
+        let now = Instant::now();
+        let mut engine = synchronized(now);
+
+        let unknown = report(oids::USM_STATS_UNKNOWN_ENGINE_IDS, SecurityLevel::NoAuthNoPriv, b"other", 9, 9);
+        assert_eq!(engine.handle_packet(&unknown, now), Err(EngineError::UnknownEngineID));
+        let window = report(oids::USM_STATS_NOT_IN_TIME_WINDOWS, SecurityLevel::NoAuthNoPriv, b"engine", 9, 9);
+        assert_eq!(engine.handle_packet(&window, now), Err(EngineError::NotInTimeWindow));
+        let unknown = report(oids::USM_STATS_UNKNOWN_ENGINE_IDS, SecurityLevel::NoAuthNoPriv, b"engine", 9, 9);
+        assert_eq!(engine.handle_packet(&unknown, now), Ok(EngineEvent::Discovered));
+
+        assert_eq!(engine.engine_id(), Some(&EngineID::new(b"engine".to_vec())));
+        assert_eq!(engine.engine_boots(), 5);
+        assert_eq!(engine.engine_time(now), 1000);
+    }
+
+    #[test]
+    fn time_window() {
+        let now = Instant::now();
+        let engine = synchronized(now);
+
+        let later = now + Duration::from_secs(200);
+        assert_eq!(engine.check_time_window(5, 1100, later), Ok(()));
+        assert_eq!(engine.check_time_window(5, 1000, later), Err(EngineError::NotInTimeWindow));
+        assert_eq!(engine.check_time_window(4, 1200, later), Err(EngineError::NotInTimeWindow));
+        assert_eq!(engine.check_time_window(6, 0, later), Ok(()));
+    }
+
+    #[test]
+    fn local_engine() {
+        let mut engine = LocalEngine::new(EngineID::new(b"local".to_vec()), 3);
+        let now = Instant::now() + Duration::from_secs(1000);
+        let time = engine.engine_time(now);
+
+        assert_eq!(engine.check_time_window(3, time - 100, now), Ok(()));
+        assert_eq!(engine.check_time_window(3, time + 200, now), Err(EngineError::NotInTimeWindow));
+        assert_eq!(engine.check_time_window(2, time, now), Err(EngineError::NotInTimeWindow));
+
+        engine.report_packet(UsmReport::UnknownEngineID, 1, 65507, 1, UserName::new(""), now);
+        let report = engine.report_packet(UsmReport::UnknownEngineID, 2, 65507, 9, UserName::new(""), now);
+        assert_eq!(report.security_parameters().engine_id(), engine.engine_id());
+        assert_eq!(report.security_parameters().engine_boots(), 3);
+        match *report.pdu() {
+            PduV3::Scoped(ref scoped) => {
+                assert_eq!(UsmReport::from_pdu(scoped.pdu()), Some(UsmReport::UnknownEngineID));
+                assert_eq!(scoped.pdu().request_id(), Some(9));
+                if let Pdu::Report(ref inner) = *scoped.pdu() {
+                    assert_eq!(*inner.binds()[0].value(), Variable::Counter(2));
+                }
+            }
+            PduV3::Crypted(_) => panic!("report must not be encrypted"),
+        }
+    }
+}
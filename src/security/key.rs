@@ -0,0 +1,244 @@
+//! Password to key algorithm and key localization (RFC 3414 A.2).
+
+use std::collections::HashMap;
+
+use openssl::hash::{Hasher, MessageDigest};
+
+use security::auth::{AuthError, AuthProtocol};
+use types::EngineID;
+
+const EXPANSION_LEN: usize = 1024 * 1024;
+
+/// Derives the non-localized key `Ku` by hashing 1 MiB of the repeated passphrase.
+pub fn password_to_key(protocol: AuthProtocol, password: &[u8]) -> Result<Vec<u8>, AuthError> {
+    if password.is_empty() {
+        return Err(AuthError::EmptyPassword);
+    }
+
+    let mut hasher = Hasher::new(protocol.message_digest())?;
+    let mut chunk = [0u8; 64];
+    let mut index = 0;
+    let mut count = 0;
+    while count < EXPANSION_LEN {
+        for b in chunk.iter_mut() {
+            *b = password[index % password.len()];
+            index += 1;
+        }
+        hasher.update(&chunk)?;
+        count += chunk.len();
+    }
+
+    Ok(hasher.finish()?.to_vec())
+}
+
+/// Localizes `Ku` to the authoritative engine: `Kul = H(Ku || engineID || Ku)`.
+pub fn localize_key(protocol: AuthProtocol, key: &[u8], engine_id: &EngineID) -> Result<Vec<u8>, AuthError> {
+    let mut hasher = Hasher::new(protocol.message_digest())?;
+    hasher.update(key)?;
+    hasher.update(engine_id.as_bytes())?;
+    hasher.update(key)?;
+    Ok(hasher.finish()?.to_vec())
+}
+
+/// Derives the localized key straight from the passphrase.
+pub fn localized_key(protocol: AuthProtocol, password: &[u8], engine_id: &EngineID) -> Result<Vec<u8>, AuthError> {
+    let key = password_to_key(protocol, password)?;
+    localize_key(protocol, &key, engine_id)
+}
+
+/// Identifies a passphrase of `protocol` without keeping the passphrase itself.
+fn passphrase_id(protocol: AuthProtocol, password: &[u8]) -> Result<Vec<u8>, AuthError> {
+    let mut hasher = Hasher::new(MessageDigest::sha256())?;
+    hasher.update(&[protocol as u8])?;
+    hasher.update(password)?;
+    Ok(hasher.finish()?.to_vec())
+}
+
+/// Cache of derived keys, so repeated discovery against the same engine skips the 1 MiB
+/// expansion and new engines only cost the localization.
+///
+/// Passphrases are only kept as digests: `Ku` is cached by passphrase and protocol,
+/// localized keys additionally by engine ID.
+#[derive(Debug, Default, Clone)]
+pub struct KeyCache {
+    master_keys: HashMap<Vec<u8>, Vec<u8>>,
+    keys: HashMap<(Vec<u8>, Vec<u8>), Vec<u8>>,
+    limit: Option<usize>,
+}
+
+impl KeyCache {
+    pub fn new() -> Self {
+        KeyCache::default()
+    }
+
+    /// Cache holding at most `limit` localized keys, emptied when full. For engine IDs taken
+    /// from unauthenticated messages, which must not grow it without bound.
+    pub fn with_limit(limit: usize) -> Self {
+        KeyCache {
+            limit: Some(limit),
+            ..KeyCache::default()
+        }
+    }
+
+    pub fn localized_key(
+        &mut self,
+        protocol: AuthProtocol,
+        password: &[u8],
+        engine_id: &EngineID,
+    ) -> Result<&[u8], AuthError> {
+        let id = passphrase_id(protocol, password)?;
+        let cache_key = (id.clone(), engine_id.as_bytes().to_vec());
+        if !self.keys.contains_key(&cache_key) {
+            if !self.master_keys.contains_key(&id) {
+                let master_key = password_to_key(protocol, password)?;
+                self.master_keys.insert(id.clone(), master_key);
+            }
+            let key = localize_key(protocol, &self.master_keys[&id], engine_id)?;
+            if self.limit.map_or(false, |limit| self.keys.len() >= limit) {
+                self.keys.clear();
+            }
+            self.keys.insert(cache_key.clone(), key);
+        }
+        Ok(&self.keys[&cache_key])
+    }
+
+    /// Number of localized keys.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.master_keys.clear();
+        self.keys.clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use security::testing::unhex;
+
+    // RFC 3414 A.3.1 and A.3.2.
+    const ENGINE_ID: [u8; 12] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+    #[test]
+    fn md5_vectors() {
+        let ku = password_to_key(AuthProtocol::Md5, b"maplesyrup").unwrap();
+        assert_eq!(
+            ku,
+            vec![0x9f, 0xaf, 0x32, 0x83, 0x88, 0x4e, 0x92, 0x83, 0x4e, 0xbc, 0x98, 0x47, 0xd8, 0xed, 0xd9, 0x63]
+        );
+
+        let kul = localize_key(AuthProtocol::Md5, &ku, &EngineID::new(ENGINE_ID.to_vec())).unwrap();
+        assert_eq!(
+            kul,
+            vec![0x52, 0x6f, 0x5e, 0xed, 0x9f, 0xcc, 0xe2, 0x6f, 0x89, 0x64, 0xc2, 0x93, 0x07, 0x87, 0xd8, 0x2b]
+        );
+    }
+
+    #[test]
+    fn sha1_vectors() {
+        let ku = password_to_key(AuthProtocol::Sha1, b"maplesyrup").unwrap();
+        assert_eq!(
+            ku,
+            vec![
+                0x9f, 0xb5, 0xcc, 0x03, 0x81, 0x49, 0x7b, 0x37, 0x93, 0x52,
+                0x89, 0x39, 0xff, 0x78, 0x8d, 0x5d, 0x79, 0x14, 0x52, 0x11,
+            ]
+        );
+
+        let kul = localize_key(AuthProtocol::Sha1, &ku, &EngineID::new(ENGINE_ID.to_vec())).unwrap();
+        assert_eq!(
+            kul,
+            vec![
+                0x66, 0x95, 0xfe, 0xbc, 0x92, 0x88, 0xe3, 0x62, 0x82, 0x23,
+                0x5f, 0xc7, 0x15, 0x1f, 0x12, 0x84, 0x97, 0xb3, 0x8f, 0x3f,
+            ]
+        );
+    }
+
+    // RFC 7860 A.2, same passphrase and engine ID.
+    #[test]
+    fn sha2_vectors() {
+        let vectors = [
+            (
+                AuthProtocol::Sha224,
+                "282a5867ee9aac639ad59df9572c7d3ac0fbc13a905b6df07dbbf00b",
+                "0bd8827c6e29f8065e08e09237f177e410f69b90e1782be682075674",
+            ),
+            (
+                AuthProtocol::Sha256,
+                "ab51014d1e077f6017df2b12bee5f5aa72993177e9bb569c4dff5a4ca0b4afac",
+                "8982e0e549e866db361a6b625d84cccc11162d453ee8ce3a6445c2d6776f0f8b",
+            ),
+            (
+                AuthProtocol::Sha384,
+                "e06eccdf2c68a06ed034723c9c26e0db3b669e1e2efed49150b55377a2e98f38\
+                 3c86fb836857444654b287c93f51ff64",
+                "3b298f16164a11184279d5432bf169e2d2a48307de02b3d3f7e2b4f36eb6f045\
+                 5a53689a3937eea07319a633d2ccba78",
+            ),
+            (
+                AuthProtocol::Sha512,
+                "7e4396de5aadc77be853819b98c9406265b3a9c37cc3176569847a4e4f6fba63\
+                 dd3a73d04924d31a63f95a601f9385af6be4ed1b37f87d040f7c6ed6f8d38a91",
+                "22a5a36cedfcc085807a128d7bc6c2382167ad6c0dbc5fdff856740f3d84c099\
+                 ad1ea87a8db096714d9788bd544047c9021e4229ce27e4c0a69250adfcffbb0b",
+            ),
+        ];
+
+        for &(protocol, expected_ku, expected_kul) in vectors.iter() {
+            let ku = password_to_key(protocol, b"maplesyrup").unwrap();
+            assert_eq!(ku, unhex(expected_ku), "{:?}", protocol);
+
+            let kul = localize_key(protocol, &ku, &EngineID::new(ENGINE_ID.to_vec())).unwrap();
+            assert_eq!(kul, unhex(expected_kul), "{:?}", protocol);
+        }
+    }
+
+    #[test]
+    fn cache() {
+        let engine_id = EngineID::new(ENGINE_ID.to_vec());
+        let mut cache = KeyCache::new();
+
+        let first = cache.localized_key(AuthProtocol::Sha256, b"maplesyrup", &engine_id).unwrap().to_vec();
+        let second = cache.localized_key(AuthProtocol::Sha256, b"maplesyrup", &engine_id).unwrap().to_vec();
+
+        assert_eq!(first.len(), AuthProtocol::Sha256.key_len());
+        assert_eq!(first, second);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn cache_reuses_master_key() {
+        let mut cache = KeyCache::new();
+        for last in 0..3u8 {
+            let engine_id = EngineID::new(vec![0x80, 0, 0, 0, last]);
+            let key = cache.localized_key(AuthProtocol::Sha1, b"maplesyrup", &engine_id).unwrap().to_vec();
+            assert_eq!(key, localized_key(AuthProtocol::Sha1, b"maplesyrup", &engine_id).unwrap());
+        }
+        cache.localized_key(AuthProtocol::Md5, b"maplesyrup", &EngineID::new(ENGINE_ID.to_vec())).unwrap();
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.master_keys.len(), 2);
+
+        // Passphrases only appear as digests.
+        for id in cache.master_keys.keys() {
+            assert!(!id.windows(10).any(|w| w == b"maplesyrup"));
+        }
+    }
+
+    #[test]
+    fn cache_limit() {
+        let mut cache = KeyCache::with_limit(2);
+        for last in 0..5u8 {
+            let engine_id = EngineID::new(vec![0x80, 0, 0, 0, last]);
+            cache.localized_key(AuthProtocol::Md5, b"maplesyrup", &engine_id).unwrap();
+            assert!(cache.len() <= 2);
+        }
+        assert!(!cache.is_empty());
+    }
+}
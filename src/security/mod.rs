@@ -0,0 +1,15 @@
+//! User-based Security Model (RFC 3414) primitives.
+
+pub mod auth;
+pub mod engine;
+pub mod key;
+pub mod privacy;
+pub mod usm;
+#[cfg(test)]
+pub mod testing;
+
+pub use self::auth::{AuthProtocol, AuthError};
+pub use self::engine::{EngineState, EngineEvent, EngineError, LocalEngine, UsmReport};
+pub use self::key::KeyCache;
+pub use self::privacy::{PrivProtocol, PrivError, SaltGenerator};
+pub use self::usm::{UsmUser, UsmError, LocalizedKeys, UserTable};
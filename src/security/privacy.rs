@@ -0,0 +1,480 @@
+//! ScopedPdu encryption: CBC-DES (RFC 3414), AES-CFB-128 (RFC 3826) and
+//! AES-CFB-192/256 with either Blumenthal or Reeder key extension.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+
+use asn1_exp::{from_asn1, to_asn1, OctetString};
+use openssl::error::ErrorStack;
+use openssl::hash::Hasher;
+use openssl::rand::rand_bytes;
+use openssl::symm::{Cipher, Crypter, Mode};
+
+use ber;
+use security::auth::{AuthError, AuthProtocol};
+use security::key;
+use types::{EngineID, PacketV3, PduV3, PrivacyParameter, ScopedPdu};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum PrivProtocol {
+    Des,
+    Aes128,
+    /// AES-192 with key extension of draft-blumenthal-aes-usm-04.
+    Aes192,
+    /// AES-256 with key extension of draft-blumenthal-aes-usm-04.
+    Aes256,
+    /// AES-192 with key extension of draft-reeder-snmpv3-usm-3desede (Cisco).
+    Aes192Reeder,
+    /// AES-256 with key extension of draft-reeder-snmpv3-usm-3desede (Cisco).
+    Aes256Reeder,
+}
+
+impl PrivProtocol {
+    /// Length of the privacy key required by the protocol.
+    pub fn key_len(&self) -> usize {
+        match *self {
+            PrivProtocol::Des | PrivProtocol::Aes128 => 16,
+            PrivProtocol::Aes192 | PrivProtocol::Aes192Reeder => 24,
+            PrivProtocol::Aes256 | PrivProtocol::Aes256Reeder => 32,
+        }
+    }
+
+    fn cipher(&self) -> Cipher {
+        match *self {
+            PrivProtocol::Des => Cipher::des_cbc(),
+            PrivProtocol::Aes128 => Cipher::aes_128_cfb128(),
+            PrivProtocol::Aes192 | PrivProtocol::Aes192Reeder => Cipher::aes_192_cfb128(),
+            PrivProtocol::Aes256 | PrivProtocol::Aes256Reeder => Cipher::aes_256_cfb128(),
+        }
+    }
+}
+
+impl Display for PrivProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            PrivProtocol::Des => "CBC-DES",
+            PrivProtocol::Aes128 => "CFB-AES-128",
+            PrivProtocol::Aes192 => "CFB-AES-192",
+            PrivProtocol::Aes256 => "CFB-AES-256",
+            PrivProtocol::Aes192Reeder => "CFB-AES-192 (Reeder)",
+            PrivProtocol::Aes256Reeder => "CFB-AES-256 (Reeder)",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Debug)]
+pub enum PrivError {
+    /// Privacy key is shorter than the protocol requires.
+    KeyTooShort,
+    /// `msgPrivacyParameters` has a wrong length.
+    MalformedParameters,
+    /// Ciphertext length is invalid or plaintext is not a ScopedPdu.
+    Decryption,
+    /// Packet does not carry the expected plaintext or encrypted PDU.
+    UnexpectedPdu,
+    /// ScopedPdu could not be serialized.
+    Encoding,
+    Key(AuthError),
+    Crypto(ErrorStack),
+}
+
+impl From<ErrorStack> for PrivError {
+    fn from(e: ErrorStack) -> Self {
+        PrivError::Crypto(e)
+    }
+}
+
+impl From<AuthError> for PrivError {
+    fn from(e: AuthError) -> Self {
+        PrivError::Key(e)
+    }
+}
+
+impl Display for PrivError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PrivError::Key(ref e) => write!(f, "{}: {}", self.description(), e),
+            PrivError::Crypto(ref e) => write!(f, "{}: {}", self.description(), e),
+            _ => f.write_str(self.description()),
+        }
+    }
+}
+
+impl Error for PrivError {
+    fn description(&self) -> &str {
+        match *self {
+            PrivError::KeyTooShort => "privacy key is too short",
+            PrivError::MalformedParameters => "malformed privacy parameters",
+            PrivError::Decryption => "decryption error",
+            PrivError::UnexpectedPdu => "unexpected PDU",
+            PrivError::Encoding => "failed to encode scoped PDU",
+            PrivError::Key(_) => "key derivation error",
+            PrivError::Crypto(_) => "crypto error",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            PrivError::Key(ref e) => Some(e),
+            PrivError::Crypto(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Extends a localized key: `K = Kul || H(Kul) || H(Kul || H(Kul)) ...`.
+pub fn extend_key_blumenthal(auth: AuthProtocol, key: &[u8], len: usize) -> Result<Vec<u8>, AuthError> {
+    let mut extended = key.to_vec();
+    while extended.len() < len {
+        let mut hasher = Hasher::new(auth.message_digest())?;
+        hasher.update(&extended)?;
+        let digest = hasher.finish()?;
+        extended.extend_from_slice(&digest);
+    }
+    extended.truncate(len);
+    Ok(extended)
+}
+
+/// Extends a localized key by running password to key and localization
+/// on the previous block: `K = Kul || Kul(Kul) || Kul(Kul(Kul)) ...`.
+pub fn extend_key_reeder(
+    auth: AuthProtocol,
+    key: &[u8],
+    engine_id: &EngineID,
+    len: usize,
+) -> Result<Vec<u8>, AuthError> {
+    let mut extended = key.to_vec();
+    let mut last = key.to_vec();
+    while extended.len() < len {
+        last = key::localized_key(auth, &last, engine_id)?;
+        extended.extend_from_slice(&last);
+    }
+    extended.truncate(len);
+    Ok(extended)
+}
+
+/// Derives the privacy key of `protocol` from the passphrase,
+/// extending it with the protocol specific algorithm when required.
+pub fn localized_key(
+    protocol: PrivProtocol,
+    auth: AuthProtocol,
+    password: &[u8],
+    engine_id: &EngineID,
+) -> Result<Vec<u8>, AuthError> {
+    let key = key::localized_key(auth, password, engine_id)?;
+    localized_key_from(protocol, auth, &key, engine_id)
+}
+
+/// Same as `localized_key` but starting from an already localized key.
+pub fn localized_key_from(
+    protocol: PrivProtocol,
+    auth: AuthProtocol,
+    key: &[u8],
+    engine_id: &EngineID,
+) -> Result<Vec<u8>, AuthError> {
+    let len = protocol.key_len();
+    if key.len() >= len {
+        return Ok(key[..len].to_vec());
+    }
+
+    match protocol {
+        PrivProtocol::Aes192Reeder | PrivProtocol::Aes256Reeder => {
+            extend_key_reeder(auth, key, engine_id, len)
+        }
+        _ => extend_key_blumenthal(auth, key, len),
+    }
+}
+
+/// Source of `msgPrivacyParameters` salt values, started at a random point.
+#[derive(Debug, Clone)]
+pub struct SaltGenerator {
+    next: u64,
+}
+
+impl SaltGenerator {
+    /// Fails if the system random generator does: a fixed starting point would make every
+    /// process reuse the same IVs under the same key.
+    pub fn new() -> Result<Self, PrivError> {
+        let mut buf = [0u8; 8];
+        rand_bytes(&mut buf)?;
+        Ok(SaltGenerator {
+            next: buf.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64),
+        })
+    }
+
+    pub fn next_salt(&mut self) -> u64 {
+        let salt = self.next;
+        self.next = self.next.wrapping_add(1);
+        salt
+    }
+}
+
+fn be32(v: u32) -> [u8; 4] {
+    [(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]
+}
+
+/// Computes cipher key, IV and `msgPrivacyParameters` for the given salt.
+fn outgoing_iv(protocol: PrivProtocol, key: &[u8], boots: u32, time: u32, salt: u64) -> (Vec<u8>, Vec<u8>) {
+    match protocol {
+        PrivProtocol::Des => {
+            let mut params = be32(boots).to_vec();
+            params.extend_from_slice(&be32(salt as u32));
+            let iv = key[8..16].iter().zip(params.iter()).map(|(a, b)| a ^ b).collect();
+            (iv, params)
+        }
+        _ => {
+            let mut params = be32((salt >> 32) as u32).to_vec();
+            params.extend_from_slice(&be32(salt as u32));
+            (incoming_iv(protocol, key, boots, time, &params), params)
+        }
+    }
+}
+
+fn incoming_iv(protocol: PrivProtocol, key: &[u8], boots: u32, time: u32, params: &[u8]) -> Vec<u8> {
+    match protocol {
+        PrivProtocol::Des => key[8..16].iter().zip(params.iter()).map(|(a, b)| a ^ b).collect(),
+        _ => {
+            let mut iv = be32(boots).to_vec();
+            iv.extend_from_slice(&be32(time));
+            iv.extend_from_slice(params);
+            iv
+        }
+    }
+}
+
+fn cipher_key(protocol: PrivProtocol, key: &[u8]) -> &[u8] {
+    match protocol {
+        PrivProtocol::Des => &key[..8],
+        _ => &key[..protocol.key_len()],
+    }
+}
+
+fn run(protocol: PrivProtocol, mode: Mode, key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    let cipher = protocol.cipher();
+    let mut crypter = Crypter::new(cipher, mode, key, Some(iv))?;
+    crypter.pad(false);
+
+    let mut out = vec![0; data.len() + cipher.block_size()];
+    let mut count = crypter.update(data, &mut out)?;
+    count += crypter.finalize(&mut out[count..])?;
+    out.truncate(count);
+    Ok(out)
+}
+
+/// Encrypts serialized ScopedPdu, returning ciphertext and `msgPrivacyParameters`.
+pub fn encrypt(
+    protocol: PrivProtocol,
+    key: &[u8],
+    engine_boots: u32,
+    engine_time: u32,
+    salt: u64,
+    plaintext: &[u8],
+) -> Result<(Vec<u8>, PrivacyParameter), PrivError> {
+    if key.len() < protocol.key_len() {
+        return Err(PrivError::KeyTooShort);
+    }
+
+    let (iv, params) = outgoing_iv(protocol, key, engine_boots, engine_time, salt);
+    let mut data = plaintext.to_vec();
+    if protocol == PrivProtocol::Des {
+        let padded = (data.len() + 7) / 8 * 8;
+        data.resize(padded, 0);
+    }
+
+    let encrypted = run(protocol, Mode::Encrypt, cipher_key(protocol, key), &iv, &data)?;
+    Ok((encrypted, PrivacyParameter::new(params)))
+}
+
+/// Decrypts ciphertext into serialized ScopedPdu. DES padding is left in place.
+pub fn decrypt(
+    protocol: PrivProtocol,
+    key: &[u8],
+    engine_boots: u32,
+    engine_time: u32,
+    params: &PrivacyParameter,
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, PrivError> {
+    if key.len() < protocol.key_len() {
+        return Err(PrivError::KeyTooShort);
+    }
+    if params.as_bytes().len() != 8 {
+        return Err(PrivError::MalformedParameters);
+    }
+    if protocol == PrivProtocol::Des && ciphertext.len() % 8 != 0 {
+        return Err(PrivError::Decryption);
+    }
+
+    let iv = incoming_iv(protocol, key, engine_boots, engine_time, params.as_bytes());
+    Ok(run(protocol, Mode::Decrypt, cipher_key(protocol, key), &iv, ciphertext)?)
+}
+
+/// Replaces plaintext ScopedPdu of an outgoing packet with `PduV3::Crypted`
+/// and fills `msgPrivacyParameters`. Engine boots and time are taken from the packet.
+pub fn encrypt_packet(protocol: PrivProtocol, key: &[u8], salt: u64, packet: &mut PacketV3) -> Result<(), PrivError> {
+    let plaintext = match *packet.pdu() {
+        PduV3::Scoped(ref scoped) => to_asn1(scoped).map_err(|_| PrivError::Encoding)?,
+        PduV3::Crypted(_) => return Err(PrivError::UnexpectedPdu),
+    };
+
+    let (boots, time) = {
+        let params = packet.security_parameters();
+        (params.engine_boots(), params.engine_time())
+    };
+    let (encrypted, params) = encrypt(protocol, key, boots, time, salt, &plaintext)?;
+
+    packet.security_parameters_mut().set_privacy_parameters(params);
+    packet.set_pdu(PduV3::Crypted(OctetString::from(encrypted)));
+    Ok(())
+}
+
+/// Replaces `PduV3::Crypted` of a received packet with the decrypted ScopedPdu.
+pub fn decrypt_packet(protocol: PrivProtocol, key: &[u8], packet: &mut PacketV3) -> Result<(), PrivError> {
+    let plaintext = match *packet.pdu() {
+        PduV3::Crypted(ref encrypted) => {
+            let params = packet.security_parameters();
+            decrypt(
+                protocol,
+                key,
+                params.engine_boots(),
+                params.engine_time(),
+                params.privacy_parameters(),
+                encrypted.as_ref(),
+            )?
+        }
+        PduV3::Scoped(_) => return Err(PrivError::UnexpectedPdu),
+    };
+
+    // Drop DES padding: only the leading TLV is the ScopedPdu.
+    let len = {
+        let tlv = ber::Reader::new(&plaintext).expect(ber::TAG_SEQUENCE).ok_or(PrivError::Decryption)?;
+        tlv.offset + tlv.value.len()
+    };
+    let scoped: ScopedPdu = from_asn1(&plaintext[..len]).map_err(|_| PrivError::Decryption)?;
+
+    packet.set_pdu(PduV3::Scoped(scoped));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use security::testing::{self, unhex};
+    use types::*;
+
+    fn packet() -> PacketV3 {
+        let flags = MessageFlags::new(true, SecurityLevel::AuthPriv);
+        let engine_id = [0x80, 0x00, 0x1f, 0x88, 0x04, 0x01];
+        testing::packet(7, flags, &engine_id, 3, 1234, "user", Pdu::new_empty_request(RequestType::GetNext))
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        let protocols = [
+            PrivProtocol::Des,
+            PrivProtocol::Aes128,
+            PrivProtocol::Aes192,
+            PrivProtocol::Aes256,
+            PrivProtocol::Aes192Reeder,
+            PrivProtocol::Aes256Reeder,
+        ];
+
+        for protocol in protocols.iter() {
+            let engine_id = packet().security_parameters().engine_id().clone();
+            let key = localized_key(*protocol, AuthProtocol::Sha1, b"maplesyrup", &engine_id).unwrap();
+            assert_eq!(key.len(), protocol.key_len());
+
+            let mut p = packet();
+            encrypt_packet(*protocol, &key, 0x0102030405060708, &mut p).unwrap();
+            assert_eq!(p.security_parameters().privacy_parameters().as_bytes().len(), 8);
+            match *p.pdu() {
+                PduV3::Crypted(ref c) if *protocol == PrivProtocol::Des => assert_eq!(c.as_ref().len() % 8, 0),
+                PduV3::Crypted(_) => {}
+                _ => panic!("PDU is not encrypted"),
+            }
+
+            decrypt_packet(*protocol, &key, &mut p).unwrap();
+            assert_eq!(p.pdu(), packet().pdu());
+        }
+    }
+
+    #[test]
+    fn cipher_vectors() {
+        // NIST SP 800-38A F.3.13, F.3.15 and F.3.17: the IV splits into boots, time and salt.
+        let plaintext = unhex(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\
+             30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710",
+        );
+        let aes128 = (
+            "2b7e151628aed2a6abf7158809cf4f3c",
+            "3b3fd92eb72dad20333449f8e83cfb4ac8a64537a0b3a93fcde3cdad9f1ce58b\
+             26751f67a3cbb140b1808cf187a4f4dfc04b05357c5d1c0eeac4c66f9ff7f2e6",
+        );
+        let aes192 = (
+            "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b",
+            "cdc80d6fddf18cab34c25909c99a417467ce7f7f81173621961a2b70171d3d7a\
+             2e1e8a1dd59b88b1c8e60fed1efac4c9c05f9f9ca9834fa042ae8fba584b09ff",
+        );
+        let aes256 = (
+            "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4",
+            "dc7e84bfda79164b7ecd8486985d386039ffed143b28b1c832113c6331e5407b\
+             df10132415e54b92a13ed0a8267ae2f975a385741ab9cef82031623d55b1e471",
+        );
+        let vectors = [
+            (PrivProtocol::Aes128, aes128),
+            (PrivProtocol::Aes192, aes192),
+            (PrivProtocol::Aes256, aes256),
+            (PrivProtocol::Aes192Reeder, aes192),
+            (PrivProtocol::Aes256Reeder, aes256),
+        ];
+
+        for &(protocol, (key, ciphertext)) in vectors.iter() {
+            let key = unhex(key);
+            let (encrypted, privacy) = encrypt(protocol, &key, 0x00010203, 0x04050607, 0x08090a0b0c0d0e0f, &plaintext).unwrap();
+            assert_eq!(privacy.as_bytes(), &unhex("08090a0b0c0d0e0f")[..]);
+            assert_eq!(encrypted, unhex(ciphertext));
+            assert_eq!(decrypt(protocol, &key, 0x00010203, 0x04050607, &privacy, &encrypted).unwrap(), plaintext);
+        }
+
+        // FIPS 81 appendix B, CBC mode: the pre-IV is the second half of the key, the salt is zero.
+        let key = unhex("0123456789abcdef1234567890abcdef");
+        let plaintext = b"Now is the time for all ";
+        let (encrypted, privacy) = encrypt(PrivProtocol::Des, &key, 0, 0, 0, plaintext).unwrap();
+        assert_eq!(privacy.as_bytes(), &[0u8; 8][..]);
+        assert_eq!(encrypted, unhex("e5c7cdde872bf27c43e934008c389c0f683788499a7c05f6"));
+        assert_eq!(decrypt(PrivProtocol::Des, &key, 0, 0, &privacy, &encrypted).unwrap(), &plaintext[..]);
+    }
+
+    #[test]
+    fn key_vectors() {
+        // RFC 3414 A.3.1 and A.3.2, and the SHA extension of draft-reeder-snmpv3-usm-3desede appendix.
+        let engine_id = EngineID::new(unhex("000000000000000000000002"));
+        let reeder = "6695febc9288e36282235fc7151f128497b38f3f9b8b6d78936ba6e7d19dfd9cd2d5065547743fb5";
+        let vectors = [
+            (PrivProtocol::Des, AuthProtocol::Md5, "526f5eed9fcce26f8964c2930787d82b"),
+            (PrivProtocol::Aes128, AuthProtocol::Sha1, &reeder[..32]),
+            (PrivProtocol::Aes192Reeder, AuthProtocol::Sha1, &reeder[..48]),
+            (PrivProtocol::Aes256Reeder, AuthProtocol::Sha1, &reeder[..64]),
+        ];
+
+        for &(protocol, auth, key) in vectors.iter() {
+            let localized = localized_key(protocol, auth, b"maplesyrup", &engine_id).unwrap();
+            assert_eq!(localized, unhex(key));
+        }
+    }
+
+    #[test]
+    fn blumenthal_extension() {
+        // draft-blumenthal-aes-usm-04 publishes no vectors: check the construction itself.
+        let kul = vec![0x11; 20];
+        let extended = extend_key_blumenthal(AuthProtocol::Sha1, &kul, 32).unwrap();
+
+        let mut hasher = Hasher::new(AuthProtocol::Sha1.message_digest()).unwrap();
+        hasher.update(&kul).unwrap();
+        let digest = hasher.finish().unwrap();
+
+        assert_eq!(&extended[..20], &kul[..]);
+        assert_eq!(&extended[20..], &digest[..12]);
+    }
+}
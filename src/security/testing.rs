@@ -0,0 +1,33 @@
+//! Fixtures shared by the USM tests.
+
+use security::{self, KeyCache, SaltGenerator, UsmUser};
+use types::*;
+
+/// Unprotected v3 message of `user` carrying `pdu`, authoritative for and scoped to `engine_id`.
+pub fn packet(msg_id: u32, flags: MessageFlags, engine_id: &[u8], boots: u32, time: u32, user: &str, pdu: Pdu) -> PacketV3 {
+    let header = HeaderData::new(msg_id, 65507, flags, SecurityModel::UserBasedSecurityModel);
+    let params = SecurityParameter::new(
+        EngineID::new(engine_id.to_vec()),
+        boots,
+        time,
+        UserName::new(user),
+        AuthenticationParameter::new(Vec::new()),
+        PrivacyParameter::new(Vec::new()),
+    );
+    let scoped = ScopedPdu::new(ContextEngineID::new(engine_id.to_vec()), ContextName::new(""), pdu);
+    PacketV3::new(header, params, PduV3::Scoped(scoped))
+}
+
+/// `packet` protected with the keys of `user` localized to the engine of the packet.
+pub fn protect(packet: PacketV3, user: &UsmUser) -> Vec<u8> {
+    let keys = user.localize(packet.security_parameters().engine_id(), &mut KeyCache::new()).unwrap();
+    security::usm::protect(packet, &keys, &mut SaltGenerator::new().unwrap()).unwrap()
+}
+
+/// Decodes a hex string of a test vector.
+pub fn unhex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
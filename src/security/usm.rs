@@ -0,0 +1,431 @@
+//! USM users and outgoing/incoming message protection.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::time::Instant;
+
+use asn1_exp::to_asn1;
+
+use security::auth::{self, AuthError, AuthProtocol};
+use security::engine::{EngineState, LocalEngine, UsmReport};
+use security::key::KeyCache;
+use security::privacy::{self, PrivError, PrivProtocol, SaltGenerator};
+use types::*;
+
+/// USM user with its passphrases. Privacy requires authentication.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UsmUser {
+    name: UserName,
+    auth: Option<(AuthProtocol, Vec<u8>)>,
+    privacy: Option<(PrivProtocol, Vec<u8>)>,
+}
+
+impl UsmUser {
+    /// noAuthNoPriv user.
+    pub fn new(name: &str) -> Self {
+        UsmUser {
+            name: UserName::new(name),
+            auth: None,
+            privacy: None,
+        }
+    }
+
+    pub fn with_auth(mut self, protocol: AuthProtocol, password: &[u8]) -> Self {
+        self.auth = Some((protocol, password.to_vec()));
+        self
+    }
+
+    pub fn with_privacy(mut self, protocol: PrivProtocol, password: &[u8]) -> Self {
+        self.privacy = Some((protocol, password.to_vec()));
+        self
+    }
+
+    pub fn name(&self) -> &UserName {
+        &self.name
+    }
+
+    pub fn auth_protocol(&self) -> Option<AuthProtocol> {
+        self.auth.as_ref().map(|&(p, _)| p)
+    }
+
+    pub fn privacy_protocol(&self) -> Option<PrivProtocol> {
+        self.privacy.as_ref().map(|&(p, _)| p)
+    }
+
+    /// Highest security level the user is configured for.
+    pub fn security_level(&self) -> SecurityLevel {
+        match (&self.auth, &self.privacy) {
+            (&Some(_), &Some(_)) => SecurityLevel::AuthPriv,
+            (&Some(_), &None) => SecurityLevel::AuthNoPriv,
+            _ => SecurityLevel::NoAuthNoPriv,
+        }
+    }
+
+    /// Localizes the user keys to the authoritative engine.
+    pub fn localize(&self, engine_id: &EngineID, cache: &mut KeyCache) -> Result<LocalizedKeys, AuthError> {
+        let (auth_protocol, auth_password) = match self.auth {
+            Some((protocol, ref password)) => (protocol, password),
+            None => return Ok(LocalizedKeys::default()),
+        };
+
+        let auth_key = cache.localized_key(auth_protocol, auth_password, engine_id)?.to_vec();
+        let privacy = match self.privacy {
+            Some((protocol, ref password)) => {
+                let key = cache.localized_key(auth_protocol, password, engine_id)?.to_vec();
+                let key = privacy::localized_key_from(protocol, auth_protocol, &key, engine_id)?;
+                Some((protocol, key))
+            }
+            None => None,
+        };
+
+        Ok(LocalizedKeys {
+            auth: Some((auth_protocol, auth_key)),
+            privacy: privacy,
+        })
+    }
+}
+
+/// USM users known to an authoritative engine or a notification receiver, keyed by name.
+#[derive(Debug, Clone, Default)]
+pub struct UserTable {
+    users: HashMap<Vec<u8>, UsmUser>,
+}
+
+impl UserTable {
+    pub fn new() -> Self {
+        UserTable::default()
+    }
+
+    /// Adds a user, replacing and returning the one with the same name.
+    pub fn insert(&mut self, user: UsmUser) -> Option<UsmUser> {
+        self.users.insert(user.name().as_bytes().to_vec(), user)
+    }
+
+    pub fn get(&self, name: &UserName) -> Option<&UsmUser> {
+        self.users.get(name.as_bytes())
+    }
+
+    pub fn remove(&mut self, name: &UserName) -> Option<UsmUser> {
+        self.users.remove(name.as_bytes())
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+/// Keys of a user localized to a particular engine.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct LocalizedKeys {
+    pub auth: Option<(AuthProtocol, Vec<u8>)>,
+    pub privacy: Option<(PrivProtocol, Vec<u8>)>,
+}
+
+#[derive(Debug)]
+pub enum UsmError {
+    /// Message security level requires keys the user does not have.
+    UnsupportedSecurityLevel,
+    /// Message could not be serialized.
+    Encoding,
+    Auth(AuthError),
+    Privacy(PrivError),
+}
+
+impl From<AuthError> for UsmError {
+    fn from(e: AuthError) -> Self {
+        UsmError::Auth(e)
+    }
+}
+
+impl From<PrivError> for UsmError {
+    fn from(e: PrivError) -> Self {
+        UsmError::Privacy(e)
+    }
+}
+
+impl Display for UsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UsmError::Auth(ref e) => e.fmt(f),
+            UsmError::Privacy(ref e) => e.fmt(f),
+            _ => f.write_str(self.description()),
+        }
+    }
+}
+
+impl Error for UsmError {
+    fn description(&self) -> &str {
+        match *self {
+            UsmError::UnsupportedSecurityLevel => "unsupported security level",
+            UsmError::Encoding => "failed to encode message",
+            UsmError::Auth(ref e) => e.description(),
+            UsmError::Privacy(ref e) => e.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            UsmError::Auth(ref e) => Some(e),
+            UsmError::Privacy(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Encrypts and authenticates an outgoing packet according to its security level.
+pub fn protect(mut packet: PacketV3, keys: &LocalizedKeys, salts: &mut SaltGenerator) -> Result<Vec<u8>, UsmError> {
+    let flags = packet.flags();
+
+    if flags.is_encrypted() {
+        let (protocol, ref key) = *keys.privacy.as_ref().ok_or(UsmError::UnsupportedSecurityLevel)?;
+        privacy::encrypt_packet(protocol, key, salts.next_salt(), &mut packet)?;
+    }
+
+    if flags.is_authenticated() {
+        let (protocol, ref key) = *keys.auth.as_ref().ok_or(UsmError::UnsupportedSecurityLevel)?;
+        Ok(auth::sign(protocol, key, &packet)?)
+    } else {
+        to_asn1(&packet).map_err(|_| UsmError::Encoding)
+    }
+}
+
+/// Verifies and decrypts a received packet. `message` is the raw datagram `packet` was decoded from.
+pub fn unprotect(message: &[u8], packet: &mut PacketV3, keys: &LocalizedKeys) -> Result<(), UsmError> {
+    authenticate(message, packet, keys)?;
+    decrypt(packet, keys)
+}
+
+/// Verification half of `unprotect` (RFC 3414 section 3.2 step 6).
+fn authenticate(message: &[u8], packet: &PacketV3, keys: &LocalizedKeys) -> Result<(), UsmError> {
+    if packet.flags().is_authenticated() {
+        let (protocol, ref key) = *keys.auth.as_ref().ok_or(UsmError::UnsupportedSecurityLevel)?;
+        auth::verify(protocol, key, message)?;
+    }
+    Ok(())
+}
+
+/// Decryption half of `unprotect` (RFC 3414 section 3.2 step 8).
+fn decrypt(packet: &mut PacketV3, keys: &LocalizedKeys) -> Result<(), UsmError> {
+    if packet.flags().is_encrypted() {
+        let (protocol, ref key) = *keys.privacy.as_ref().ok_or(UsmError::UnsupportedSecurityLevel)?;
+        privacy::decrypt_packet(protocol, key, packet)?;
+    }
+    Ok(())
+}
+
+/// `usmStats` counter reporting a failed `unprotect`.
+fn failure_report(e: &UsmError) -> UsmReport {
+    match *e {
+        UsmError::UnsupportedSecurityLevel => UsmReport::UnsupportedSecLevel,
+        UsmError::Privacy(_) => UsmReport::DecryptionError,
+        _ => UsmReport::WrongDigest,
+    }
+}
+
+/// Received message that passed USM processing on the authoritative side.
+#[derive(Debug)]
+pub(crate) struct Accepted {
+    pub msg_id: u32,
+    /// msgMaxSize of the sender.
+    pub max_size: u32,
+    pub flags: MessageFlags,
+    pub params: SecurityParameter,
+    pub keys: LocalizedKeys,
+    pub scoped: ScopedPdu,
+    /// Whether the message was addressed to the local engine.
+    pub authoritative: bool,
+}
+
+/// Keys and clocks kept for engines other than the local one before they are forgotten.
+const MAX_FOREIGN_ENGINES: usize = 64;
+
+/// USM processing of incoming messages by a local (authoritative) engine:
+/// user lookup, authentication, decryption, timeliness checks and Report generation.
+#[derive(Debug)]
+pub(crate) struct UsmProcessor {
+    engine: LocalEngine,
+    users: UserTable,
+    /// Keys localized to the local engine.
+    key_cache: KeyCache,
+    /// Keys localized to the engines of v3 traps, whose IDs anyone can make up.
+    foreign_keys: KeyCache,
+    /// Boots and time of the engines of v3 traps, learnt from authenticated traps.
+    foreign_engines: HashMap<Vec<u8>, EngineState>,
+    salts: SaltGenerator,
+    max_size: u32,
+}
+
+impl UsmProcessor {
+    pub fn new(engine: LocalEngine, users: UserTable, max_size: u32) -> Result<Self, PrivError> {
+        Ok(UsmProcessor {
+            engine: engine,
+            users: users,
+            key_cache: KeyCache::new(),
+            foreign_keys: KeyCache::with_limit(MAX_FOREIGN_ENGINES),
+            foreign_engines: HashMap::new(),
+            salts: SaltGenerator::new()?,
+            max_size: max_size,
+        })
+    }
+
+    pub fn engine(&self) -> &LocalEngine {
+        &self.engine
+    }
+
+    pub fn users(&self) -> &UserTable {
+        &self.users
+    }
+
+    pub fn users_mut(&mut self) -> &mut UserTable {
+        &mut self.users
+    }
+
+    /// Authenticates and decrypts `packet` decoded from `message`. Messages authoritative for
+    /// another engine are only accepted with `foreign_engines` (v3 traps) and if not reportable;
+    /// their timeliness is checked against the boots and time last received from that engine.
+    ///
+    /// On failure returns the Report to be sent back, if any.
+    pub fn accept(
+        &mut self,
+        message: &[u8],
+        mut packet: PacketV3,
+        foreign_engines: bool,
+        now: Instant,
+    ) -> Result<Accepted, Option<Vec<u8>>> {
+        let msg_id = packet.msg_id();
+        let max_size = packet.header().max_size();
+        let flags = packet.flags();
+        let params = packet.security_parameters().clone();
+
+        let authoritative = params.engine_id() == self.engine.engine_id();
+        if !authoritative && (flags.reportable || !foreign_engines) {
+            return Err(self.report(UsmReport::UnknownEngineID, &packet, None, now));
+        }
+
+        let user = match self.users.get(params.user_name()) {
+            Some(user) => user.clone(),
+            None => return Err(self.report(UsmReport::UnknownUserName, &packet, None, now)),
+        };
+        // RFC 3414 only rejects levels the user has no keys for and leaves lower ones to access
+        // control. Without one, a user's configured level is also the least it is accepted at.
+        if flags.security_level < user.security_level() {
+            return Err(self.report(UsmReport::UnsupportedSecLevel, &packet, None, now));
+        }
+
+        let localized = {
+            let cache = if authoritative { &mut self.key_cache } else { &mut self.foreign_keys };
+            user.localize(params.engine_id(), cache)
+        };
+        let keys = match localized {
+            Ok(keys) => keys,
+            Err(_) => return Err(None),
+        };
+        if let Err(e) = authenticate(message, &packet, &keys) {
+            return Err(self.report(failure_report(&e), &packet, None, now));
+        }
+
+        // RFC 3414 section 3.2 step 7: timeliness is checked before anything is decrypted.
+        if flags.is_authenticated() {
+            let timely = if authoritative {
+                self.engine
+                    .check_time_window(params.engine_boots(), params.engine_time(), now)
+                    .is_ok()
+            } else {
+                self.foreign_engine(params.engine_id()).handle_packet(&packet, now).is_ok()
+            };
+            if !timely {
+                return Err(self.report(UsmReport::NotInTimeWindow, &packet, Some(&keys), now));
+            }
+        }
+
+        if let Err(e) = decrypt(&mut packet, &keys) {
+            return Err(self.report(failure_report(&e), &packet, None, now));
+        }
+
+        match packet.into_pdu() {
+            PduV3::Scoped(scoped) => Ok(Accepted {
+                msg_id: msg_id,
+                max_size: max_size,
+                flags: flags,
+                params: params,
+                keys: keys,
+                scoped: scoped,
+                authoritative: authoritative,
+            }),
+            PduV3::Crypted(_) => Err(None),
+        }
+    }
+
+    /// Non-authoritative view of a foreign engine, forgetting all of them once too many are known.
+    fn foreign_engine(&mut self, engine_id: &EngineID) -> &mut EngineState {
+        if self.foreign_engines.len() >= MAX_FOREIGN_ENGINES && !self.foreign_engines.contains_key(engine_id.as_bytes()) {
+            self.foreign_engines.clear();
+        }
+        self.foreign_engines
+            .entry(engine_id.as_bytes().to_vec())
+            .or_insert_with(|| EngineState::with_engine_id(engine_id.clone()))
+    }
+
+    /// Response to an accepted message, protected at the same security level.
+    pub fn respond(&mut self, request: &Accepted, pdu: Pdu, now: Instant) -> Option<Vec<u8>> {
+        let header = HeaderData::new(
+            request.msg_id,
+            self.max_size,
+            MessageFlags::new(false, request.flags.security_level),
+            SecurityModel::UserBasedSecurityModel,
+        );
+        let mut params = SecurityParameter::new_discovery();
+        params.set_user_name(request.params.user_name().clone());
+        self.engine.prepare(&mut params, now);
+
+        let scoped = ScopedPdu::new(
+            request.scoped.context_engine_id().clone(),
+            request.scoped.context_name().clone(),
+            pdu,
+        );
+        let packet = PacketV3::new(header, params, PduV3::Scoped(scoped));
+        protect(packet, &request.keys, &mut self.salts).ok()
+    }
+
+    /// Report answering a message that failed USM processing, if the message is reportable.
+    /// `keys` are used to authenticate the report, as required for `usmStatsNotInTimeWindows`.
+    fn report(
+        &mut self,
+        report: UsmReport,
+        packet: &PacketV3,
+        keys: Option<&LocalizedKeys>,
+        now: Instant,
+    ) -> Option<Vec<u8>> {
+        if !packet.flags().reportable {
+            return None;
+        }
+
+        let request_id = match *packet.pdu() {
+            PduV3::Scoped(ref scoped) => scoped.pdu().request_id().unwrap_or(0),
+            PduV3::Crypted(_) => 0,
+        };
+        let mut response = self.engine.report_packet(
+            report,
+            packet.msg_id(),
+            self.max_size,
+            request_id,
+            packet.security_parameters().user_name().clone(),
+            now,
+        );
+
+        match keys {
+            Some(keys) => {
+                response
+                    .header_mut()
+                    .set_flags(MessageFlags::new(false, SecurityLevel::AuthNoPriv));
+                protect(response, keys, &mut self.salts).ok()
+            }
+            None => to_asn1(&response).ok(),
+        }
+    }
+}
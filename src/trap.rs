@@ -0,0 +1,286 @@
+//! Translation between SNMPv1 Trap-PDU and SNMPv2-Trap-PDU as described in RFC 3584 section 3.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::net::Ipv4Addr;
+
+use oids;
+use types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranslationError {
+    /// First binding of the notification is not `sysUpTime.0` with a TimeTicks value.
+    MissingSysUpTime,
+    /// Second binding of the notification is not `snmpTrapOID.0` with an OID value.
+    MissingTrapOid,
+    /// `snmpTrapOID.0` value is too short to derive the enterprise from.
+    InvalidTrapOid,
+}
+
+impl Display for TranslationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl Error for TranslationError {
+    fn description(&self) -> &str {
+        match *self {
+            TranslationError::MissingSysUpTime => "sysUpTime.0 is not the first binding",
+            TranslationError::MissingTrapOid => "snmpTrapOID.0 is not the second binding",
+            TranslationError::InvalidTrapOid => "invalid snmpTrapOID.0 value",
+        }
+    }
+}
+
+/// `snmpTrapOID.0` value of a v1 trap (RFC 3584 section 3.1 items 1 and 2).
+pub fn trap_oid(trap: &TrapV1Pdu) -> ObjectIdentifier {
+    let oid = match trap.generic_trap() {
+        GenericTrap::EnterpriseSpecific => {
+            let mut oid = oids::components(trap.enterprise()).to_vec();
+            oid.push(0);
+            oid.push(trap.specific_trap() as u64);
+            oid
+        }
+        generic => {
+            let mut oid = oids::SNMP_TRAPS.to_vec();
+            oid.push(generic as u64 + 1);
+            oid
+        }
+    };
+    ObjectIdentifier::from(oid)
+}
+
+/// Converts a v1 trap to the bindings of an SNMPv2-Trap-PDU.
+///
+/// `sysUpTime.0` and `snmpTrapOID.0` are synthesized in front of the trap bindings,
+/// `snmpTrapAddress.0`, `snmpTrapCommunity.0` (when `community` is known) and
+/// `snmpTrapEnterprise.0` are appended unless already present.
+pub fn v1_to_v2(trap: &TrapV1Pdu, community: Option<&Community>, request_id: u32) -> InnerPdu {
+    let mut binds = Vec::with_capacity(trap.binds().len() + 5);
+    binds.push(VarBind::new(oids::oid(oids::SYS_UP_TIME), Variable::TimeTicks(trap.time_stamp())));
+    binds.push(VarBind::new(oids::oid(oids::SNMP_TRAP_OID), Variable::Oid(trap_oid(trap))));
+    binds.extend(trap.binds().iter().cloned());
+
+    let addr = trap.agent_addr().octets();
+    let mut proxied = vec![
+        VarBind::new(
+            oids::oid(oids::SNMP_TRAP_ADDRESS),
+            Variable::IpAddress(addr[0], addr[1], addr[2], addr[3]),
+        ),
+    ];
+    if let Some(community) = community {
+        proxied.push(VarBind::new(
+            oids::oid(oids::SNMP_TRAP_COMMUNITY),
+            Variable::OctetString(OctetString::from(community.as_bytes().to_vec())),
+        ));
+    }
+    proxied.push(VarBind::new(
+        oids::oid(oids::SNMP_TRAP_ENTERPRISE),
+        Variable::Oid(trap.enterprise().clone()),
+    ));
+
+    for bind in proxied {
+        if find(trap.binds(), oids::components(bind.oid())).is_none() {
+            binds.push(bind);
+        }
+    }
+
+    InnerPdu::new(request_id, ErrorStatus::NoError, 0, binds)
+}
+
+/// Converts the bindings of an SNMPv2-Trap-PDU or InformRequest-PDU to a v1 trap.
+///
+/// Counter64 bindings cannot be represented in SNMPv1 and are dropped.
+pub fn v2_to_v1(pdu: &InnerPdu) -> Result<TrapV1Pdu, TranslationError> {
+    let binds = pdu.binds();
+    let (time_stamp, trap) = notification_header(binds)?;
+    let trap = oids::components(trap);
+
+    let (enterprise, generic_trap, specific_trap) = match standard_trap(trap) {
+        Some(generic) => {
+            let enterprise = match find(binds, oids::SNMP_TRAP_ENTERPRISE).map(|b| b.value()) {
+                Some(&Variable::Oid(ref oid)) => oid.clone(),
+                _ => oids::oid(oids::SNMP_TRAPS),
+            };
+            (enterprise, generic, 0)
+        }
+        None => {
+            let (&specific, prefix) = trap.split_last().ok_or(TranslationError::InvalidTrapOid)?;
+            let enterprise = match prefix.split_last() {
+                Some((&0, enterprise)) => enterprise,
+                _ => prefix,
+            };
+            if enterprise.is_empty() || specific > u32::max_value() as u64 {
+                return Err(TranslationError::InvalidTrapOid);
+            }
+            (oids::oid(enterprise), GenericTrap::EnterpriseSpecific, specific as u32)
+        }
+    };
+
+    let agent_addr = match find(binds, oids::SNMP_TRAP_ADDRESS).map(|b| b.value()) {
+        Some(&Variable::IpAddress(a, b, c, d)) => Ipv4Addr::new(a, b, c, d),
+        _ => Ipv4Addr::new(0, 0, 0, 0),
+    };
+
+    let binds = binds[2..]
+        .iter()
+        .filter(|bind| match *bind.value() {
+            Variable::Counter64(_) => false,
+            _ => true,
+        })
+        .cloned()
+        .collect();
+
+    Ok(TrapV1Pdu::new(enterprise, agent_addr, generic_trap, specific_trap, time_stamp, binds))
+}
+
+/// `sysUpTime.0` and `snmpTrapOID.0` values, which must be the first two bindings of a v2 notification.
+pub fn notification_header(binds: &[VarBind]) -> Result<(u32, &ObjectIdentifier), TranslationError> {
+    let uptime = match binds.get(0) {
+        Some(bind) if oids::components(bind.oid()) == oids::SYS_UP_TIME => match *bind.value() {
+            Variable::TimeTicks(ticks) => ticks,
+            _ => return Err(TranslationError::MissingSysUpTime),
+        },
+        _ => return Err(TranslationError::MissingSysUpTime),
+    };
+    let trap_oid = match binds.get(1) {
+        Some(bind) if oids::components(bind.oid()) == oids::SNMP_TRAP_OID => match *bind.value() {
+            Variable::Oid(ref oid) => oid,
+            _ => return Err(TranslationError::MissingTrapOid),
+        },
+        _ => return Err(TranslationError::MissingTrapOid),
+    };
+    Ok((uptime, trap_oid))
+}
+
+/// Generic trap identified by one of the `snmpTraps` OIDs.
+fn standard_trap(trap_oid: &[u64]) -> Option<GenericTrap> {
+    if trap_oid.len() != oids::SNMP_TRAPS.len() + 1 || !trap_oid.starts_with(oids::SNMP_TRAPS) {
+        return None;
+    }
+    match trap_oid[oids::SNMP_TRAPS.len()] {
+        1 => Some(GenericTrap::ColdStart),
+        2 => Some(GenericTrap::WarmStart),
+        3 => Some(GenericTrap::LinkDown),
+        4 => Some(GenericTrap::LinkUp),
+        5 => Some(GenericTrap::AuthenticationFailure),
+        6 => Some(GenericTrap::EgpNeighborLoss),
+        _ => None,
+    }
+}
+
+fn find<'a>(binds: &'a [VarBind], oid: &[u64]) -> Option<&'a VarBind> {
+    binds.iter().find(|bind| oids::components(bind.oid()) == oid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTERPRISE: &[u64] = &[1, 3, 6, 1, 4, 1, 318];
+
+    fn bind(components: &[u64], value: Variable) -> VarBind {
+        VarBind::new(oids::oid(components), value)
+    }
+
+    fn trap(generic: GenericTrap, specific: u32) -> TrapV1Pdu {
+        TrapV1Pdu::new(
+            oids::oid(ENTERPRISE),
+            Ipv4Addr::new(192, 168, 1, 10),
+            generic,
+            specific,
+            123456,
+            vec![bind(&[1, 3, 6, 1, 4, 1, 318, 2, 1], Variable::Integer(1))],
+        )
+    }
+
+    #[test]
+    fn enterprise_specific() {
+        let v1 = trap(GenericTrap::EnterpriseSpecific, 5);
+        let v2 = v1_to_v2(&v1, Some(&Community::new("public")), 7);
+
+        assert_eq!(v2.request_id(), 7);
+        assert_eq!(
+            v2.binds(),
+            &vec![
+                bind(oids::SYS_UP_TIME, Variable::TimeTicks(123456)),
+                bind(oids::SNMP_TRAP_OID, Variable::Oid(oids::oid(&[1, 3, 6, 1, 4, 1, 318, 0, 5]))),
+                bind(&[1, 3, 6, 1, 4, 1, 318, 2, 1], Variable::Integer(1)),
+                bind(oids::SNMP_TRAP_ADDRESS, Variable::IpAddress(192, 168, 1, 10)),
+                bind(
+                    oids::SNMP_TRAP_COMMUNITY,
+                    Variable::OctetString(OctetString::from(b"public".to_vec())),
+                ),
+                bind(oids::SNMP_TRAP_ENTERPRISE, Variable::Oid(oids::oid(ENTERPRISE))),
+            ]
+        );
+
+        let back = v2_to_v1(&v2).unwrap();
+        assert_eq!(back.enterprise(), v1.enterprise());
+        assert_eq!(back.agent_addr(), v1.agent_addr());
+        assert_eq!(back.generic_trap(), GenericTrap::EnterpriseSpecific);
+        assert_eq!(back.specific_trap(), 5);
+        assert_eq!(back.time_stamp(), 123456);
+        assert_eq!(&back.binds()[..], &v2.binds()[2..]);
+    }
+
+    #[test]
+    fn generic() {
+        let v1 = trap(GenericTrap::LinkDown, 0);
+        let v2 = v1_to_v2(&v1, None, 1);
+
+        assert_eq!(v2.binds()[1], bind(oids::SNMP_TRAP_OID, Variable::Oid(oids::oid(&[1, 3, 6, 1, 6, 3, 1, 1, 5, 3]))));
+        assert!(find(v2.binds(), oids::SNMP_TRAP_COMMUNITY).is_none());
+
+        let back = v2_to_v1(&v2).unwrap();
+        assert_eq!(back.enterprise(), v1.enterprise());
+        assert_eq!(back.generic_trap(), GenericTrap::LinkDown);
+        assert_eq!(back.specific_trap(), 0);
+    }
+
+    #[test]
+    fn v2_notification() {
+        let v2 = InnerPdu::new(
+            1,
+            ErrorStatus::NoError,
+            0,
+            vec![
+                bind(oids::SYS_UP_TIME, Variable::TimeTicks(42)),
+                bind(oids::SNMP_TRAP_OID, Variable::Oid(oids::oid(&[1, 3, 6, 1, 4, 1, 8072, 2, 3, 0, 1]))),
+                bind(&[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 6, 1], Variable::Counter64(1 << 40)),
+                bind(&[1, 3, 6, 1, 2, 1, 1, 5, 0], Variable::OctetString(OctetString::from(b"host".to_vec()))),
+            ],
+        );
+
+        let v1 = v2_to_v1(&v2).unwrap();
+        assert_eq!(v1.enterprise(), &oids::oid(&[1, 3, 6, 1, 4, 1, 8072, 2, 3]));
+        assert_eq!(v1.agent_addr(), Ipv4Addr::new(0, 0, 0, 0));
+        assert_eq!(v1.generic_trap(), GenericTrap::EnterpriseSpecific);
+        assert_eq!(v1.specific_trap(), 1);
+        assert_eq!(v1.time_stamp(), 42);
+        assert_eq!(&v1.binds()[..], &v2.binds()[3..]);
+
+        let standard = InnerPdu::new(
+            1,
+            ErrorStatus::NoError,
+            0,
+            vec![
+                bind(oids::SYS_UP_TIME, Variable::TimeTicks(42)),
+                bind(oids::SNMP_TRAP_OID, Variable::Oid(oids::oid(&[1, 3, 6, 1, 6, 3, 1, 1, 5, 1]))),
+            ],
+        );
+        let v1 = v2_to_v1(&standard).unwrap();
+        assert_eq!(v1.enterprise(), &oids::oid(oids::SNMP_TRAPS));
+        assert_eq!(v1.generic_trap(), GenericTrap::ColdStart);
+    }
+
+    #[test]
+    fn missing_trap_oid() {
+        let v2 = InnerPdu::new(1, ErrorStatus::NoError, 0, vec![bind(oids::SYS_UP_TIME, Variable::TimeTicks(42))]);
+        assert_eq!(v2_to_v1(&v2), Err(TranslationError::MissingTrapOid));
+
+        let v2 = InnerPdu::new(1, ErrorStatus::NoError, 0, Vec::new());
+        assert_eq!(v2_to_v1(&v2), Err(TranslationError::MissingSysUpTime));
+    }
+}
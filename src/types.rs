@@ -1,4 +1,10 @@
+use std::convert::TryFrom;
+use std::error::Error as StdError;
 use std::fmt::{self, Display};
+use std::net::Ipv4Addr;
+use std::ops::Range;
+
+use ber;
 
 pub use asn1_exp::{BitString, ObjectIdentifier, OctetString, Asn1DisplayExt};
 
@@ -24,13 +30,71 @@ impl Display for Version {
     }
 }
 
-impl From<u8> for Version {
-    fn from(v: u8) -> Version {
+impl TryFrom<i64> for Version {
+    type Error = VersionError;
+
+    fn try_from(v: i64) -> Result<Version, VersionError> {
         match v {
-            0 => Version::Version1,
-            1 => Version::Version2,
-            3 => Version::Version3,
-            _ => panic!("incorrect version"),
+            0 => Ok(Version::Version1),
+            1 => Ok(Version::Version2),
+            2 => Err(VersionError::Unsupported(v)),
+            3 => Ok(Version::Version3),
+            _ => Err(VersionError::Invalid(v)),
+        }
+    }
+}
+
+impl TryFrom<u8> for Version {
+    type Error = VersionError;
+
+    fn try_from(v: u8) -> Result<Version, VersionError> {
+        Version::try_from(v as i64)
+    }
+}
+
+impl From<Version> for u8 {
+    fn from(v: Version) -> u8 {
+        match v {
+            Version::Version1 => 0,
+            Version::Version2 => 1,
+            Version::Version3 => 3,
+        }
+    }
+}
+
+impl From<Version> for i64 {
+    fn from(v: Version) -> i64 {
+        u8::from(v) as i64
+    }
+}
+
+/// Message version field not mapping to `Version`, or not fitting the message layout.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum VersionError {
+    /// SNMPv2u/SNMPv2* (version 2), which were never standardized.
+    Unsupported(i64),
+    /// Value no SNMP version ever used.
+    Invalid(i64),
+    /// Community based packet claiming version 3, which only has the user based layout.
+    NotCommunityBased,
+}
+
+impl Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VersionError::Unsupported(v) => write!(f, "unsupported SNMP version {}", v),
+            VersionError::Invalid(v) => write!(f, "invalid SNMP version {}", v),
+            VersionError::NotCommunityBased => write!(f, "SNMPv3 messages are not community based"),
+        }
+    }
+}
+
+impl StdError for VersionError {
+    fn description(&self) -> &str {
+        match *self {
+            VersionError::Unsupported(_) => "unsupported SNMP version",
+            VersionError::Invalid(_) => "invalid SNMP version",
+            VersionError::NotCommunityBased => "SNMPv3 messages are not community based",
         }
     }
 }
@@ -45,6 +109,14 @@ impl Community {
     pub fn new(community: &str) -> Community {
         Community(OctetString::from_str(community))
     }
+
+    pub fn from_bytes(community: Vec<u8>) -> Community {
+        Community(OctetString::from(community))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
@@ -65,47 +137,144 @@ impl From<RequestID> for u32 {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+/// Error status of a response PDU (RFC 3416 section 3).
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
-pub struct ErrorStatus(u32);
+pub enum ErrorStatus {
+    NoError,
+    TooBig,
+    NoSuchName,
+    BadValue,
+    ReadOnly,
+    GenErr,
+    NoAccess,
+    WrongType,
+    WrongLength,
+    WrongEncoding,
+    WrongValue,
+    NoCreation,
+    InconsistentValue,
+    ResourceUnavailable,
+    CommitFailed,
+    UndoFailed,
+    AuthorizationError,
+    NotWritable,
+    InconsistentName,
+    /// Value outside of RFC 3416, only obtained from `ErrorStatus::from`.
+    Unknown(UnknownStatus),
+}
 
-asn1_newtype!(ErrorStatus ::= u32);
+/// Error status value outside of RFC 3416, never one of the defined statuses.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct UnknownStatus(u32);
+
+impl UnknownStatus {
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+asn1_alias_info!(ErrorStatus ::= u32);
+
+impl ErrorStatus {
+    /// Whether the status is one of the SNMPv1 ones (RFC 1157), noError to genErr.
+    pub fn is_v1(&self) -> bool {
+        u32::from(*self) <= 5
+    }
+
+    /// Status to send in an SNMPv1 response instead of this one (RFC 3584 section 4.4).
+    pub fn to_v1(&self) -> ErrorStatus {
+        use self::ErrorStatus::*;
+        match *self {
+            WrongValue | WrongEncoding | WrongType | WrongLength | InconsistentValue => BadValue,
+            NoAccess | NotWritable | NoCreation | InconsistentName | AuthorizationError => NoSuchName,
+            ResourceUnavailable | CommitFailed | UndoFailed | Unknown(_) => GenErr,
+            status => status,
+        }
+    }
+}
 
 impl From<u32> for ErrorStatus {
     fn from(v: u32) -> Self {
-        ErrorStatus(v)
+        use self::ErrorStatus::*;
+        match v {
+            0 => NoError,
+            1 => TooBig,
+            2 => NoSuchName,
+            3 => BadValue,
+            4 => ReadOnly,
+            5 => GenErr,
+            6 => NoAccess,
+            7 => WrongType,
+            8 => WrongLength,
+            9 => WrongEncoding,
+            10 => WrongValue,
+            11 => NoCreation,
+            12 => InconsistentValue,
+            13 => ResourceUnavailable,
+            14 => CommitFailed,
+            15 => UndoFailed,
+            16 => AuthorizationError,
+            17 => NotWritable,
+            18 => InconsistentName,
+            v => Unknown(UnknownStatus(v)),
+        }
     }
 }
 
 impl From<ErrorStatus> for u32 {
     fn from(v: ErrorStatus) -> Self {
-        v.0
+        use self::ErrorStatus::*;
+        match v {
+            NoError => 0,
+            TooBig => 1,
+            NoSuchName => 2,
+            BadValue => 3,
+            ReadOnly => 4,
+            GenErr => 5,
+            NoAccess => 6,
+            WrongType => 7,
+            WrongLength => 8,
+            WrongEncoding => 9,
+            WrongValue => 10,
+            NoCreation => 11,
+            InconsistentValue => 12,
+            ResourceUnavailable => 13,
+            CommitFailed => 14,
+            UndoFailed => 15,
+            AuthorizationError => 16,
+            NotWritable => 17,
+            InconsistentName => 18,
+            Unknown(v) => v.value(),
+        }
     }
 }
 
 impl Display for ErrorStatus {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = match self.0 {
-            0 => "noError",
-            1 => "tooBig",
-            2 => "noSuchName",
-            3 => "badValue",
-            4 => "readOnly",
-            5 => "genErr",
-            6 => "noAccess",
-            7 => "wrongType",
-            8 => "wrongLength",
-            9 => "wrongEncoding",
-            10 => "wrongValue",
-            11 => "noCreation",
-            12 => "inconsistentValue",
-            13 => "resourceUnavailable",
-            14 => "commitFailed",
-            15 => "undoFailed",
-            16 => "authorizationError",
-            17 => "notWritable",
-            18 => "inconsistentName",
-            _  => "otherError",
+        use self::ErrorStatus::*;
+        let s = match *self {
+            NoError => "noError",
+            TooBig => "tooBig",
+            NoSuchName => "noSuchName",
+            BadValue => "badValue",
+            ReadOnly => "readOnly",
+            GenErr => "genErr",
+            NoAccess => "noAccess",
+            WrongType => "wrongType",
+            WrongLength => "wrongLength",
+            WrongEncoding => "wrongEncoding",
+            WrongValue => "wrongValue",
+            NoCreation => "noCreation",
+            InconsistentValue => "inconsistentValue",
+            ResourceUnavailable => "resourceUnavailable",
+            CommitFailed => "commitFailed",
+            UndoFailed => "undoFailed",
+            AuthorizationError => "authorizationError",
+            NotWritable => "notWritable",
+            InconsistentName => "inconsistentName",
+            Unknown(v) => return write!(f, "unknown({})", v.value()),
         };
         f.write_str(s)
     }
@@ -238,13 +407,13 @@ asn1_seq!(
 impl InnerPdu {
     pub fn new(
         request_id: u32,
-        error_status: u32,
+        error_status: ErrorStatus,
         error_index: u32,
         bindings: VarBindList,
     ) -> Self {
         InnerPdu {
             request_id: request_id.into(),
-            error_status: error_status.into(),
+            error_status: error_status,
             error_index: error_index.into(),
             variable_bindings: bindings,
         }
@@ -258,12 +427,12 @@ impl InnerPdu {
         self.request_id.0 = value
     }
 
-    pub fn error_status(&self) -> u32 {
-        self.error_status.0
+    pub fn error_status(&self) -> ErrorStatus {
+        self.error_status
     }
 
-    pub fn set_error_status(&mut self, value: u32) {
-        self.error_status.0 = value
+    pub fn set_error_status(&mut self, value: ErrorStatus) {
+        self.error_status = value
     }
 
     pub fn error_index(&self) -> u32 {
@@ -291,6 +460,241 @@ impl InnerPdu {
     }
 }
 
+/// GetBulkRequest-PDU: same wire layout as `InnerPdu`, with error status and index
+/// replaced by non-repeaters and max-repetitions.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct BulkPdu {
+    request_id: RequestID,
+    non_repeaters: u32,
+    max_repetitions: u32,
+    variable_bindings: VarBindList,
+}
+
+asn1_seq!(
+    BulkPdu: "BULK PDU",
+    request_id;
+    non_repeaters;
+    max_repetitions;
+    variable_bindings
+);
+
+impl BulkPdu {
+    pub fn new(
+        request_id: u32,
+        non_repeaters: u32,
+        max_repetitions: u32,
+        bindings: VarBindList,
+    ) -> Self {
+        BulkPdu {
+            request_id: request_id.into(),
+            non_repeaters: non_repeaters,
+            max_repetitions: max_repetitions,
+            variable_bindings: bindings,
+        }
+    }
+
+    pub fn request_id(&self) -> u32 {
+        self.request_id.0
+    }
+
+    pub fn set_request_id(&mut self, value: u32) {
+        self.request_id.0 = value
+    }
+
+    pub fn non_repeaters(&self) -> u32 {
+        self.non_repeaters
+    }
+
+    pub fn set_non_repeaters(&mut self, value: u32) {
+        self.non_repeaters = value
+    }
+
+    pub fn max_repetitions(&self) -> u32 {
+        self.max_repetitions
+    }
+
+    pub fn set_max_repetitions(&mut self, value: u32) {
+        self.max_repetitions = value
+    }
+
+    pub fn binds(&self) -> &VarBindList {
+        &self.variable_bindings
+    }
+
+    pub fn binds_mut(&mut self) -> &mut VarBindList {
+        &mut self.variable_bindings
+    }
+
+    pub fn set_binds(&mut self, value: VarBindList) {
+        self.variable_bindings = value
+    }
+
+    pub fn into_binds(self) -> VarBindList {
+        self.variable_bindings
+    }
+
+    /// Splits bindings of the response to this request into the non-repeater bindings
+    /// and the rows of repeated bindings. The last row may be truncated by the agent.
+    pub fn split_response<'a>(&self, response: &'a [VarBind]) -> (&'a [VarBind], Vec<&'a [VarBind]>) {
+        let requested = self.variable_bindings.len();
+        let non_repeaters = ::std::cmp::min(self.non_repeaters as usize, requested);
+        let non_repeaters = ::std::cmp::min(non_repeaters, response.len());
+        let repeaters = requested - ::std::cmp::min(self.non_repeaters as usize, requested);
+
+        let (head, tail) = response.split_at(non_repeaters);
+        let rows = if repeaters == 0 {
+            Vec::new()
+        } else {
+            tail.chunks(repeaters).collect()
+        };
+        (head, rows)
+    }
+}
+
+/// `generic-trap` field of RFC 1157 Trap-PDU.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum GenericTrap {
+    ColdStart = 0,
+    WarmStart = 1,
+    LinkDown = 2,
+    LinkUp = 3,
+    AuthenticationFailure = 4,
+    EgpNeighborLoss = 5,
+    EnterpriseSpecific = 6,
+}
+
+asn1_alias_info!(GenericTrap ::= u8);
+
+impl Display for GenericTrap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            &GenericTrap::ColdStart => "coldStart",
+            &GenericTrap::WarmStart => "warmStart",
+            &GenericTrap::LinkDown => "linkDown",
+            &GenericTrap::LinkUp => "linkUp",
+            &GenericTrap::AuthenticationFailure => "authenticationFailure",
+            &GenericTrap::EgpNeighborLoss => "egpNeighborLoss",
+            &GenericTrap::EnterpriseSpecific => "enterpriseSpecific",
+        };
+        f.write_str(s)
+    }
+}
+
+/// `NetworkAddress` (IpAddress) of the agent generating a v1 trap.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct NetworkAddress(pub Ipv4Addr);
+
+asn1_typed!(NetworkAddress, "NetworkAddress");
+
+/// TimeTicks value of `time-stamp` field of a v1 trap.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct TimeStamp(pub u32);
+
+asn1_typed!(TimeStamp, "TimeTicks");
+
+/// RFC 1157 Trap-PDU.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct TrapV1Pdu {
+    enterprise: ObjectIdentifier,
+    agent_addr: NetworkAddress,
+    generic_trap: GenericTrap,
+    specific_trap: u32,
+    time_stamp: TimeStamp,
+    variable_bindings: VarBindList,
+}
+
+asn1_seq!(
+    TrapV1Pdu: "TRAP PDU",
+    enterprise;
+    agent_addr;
+    generic_trap;
+    specific_trap;
+    time_stamp;
+    variable_bindings
+);
+
+impl TrapV1Pdu {
+    pub fn new(
+        enterprise: ObjectIdentifier,
+        agent_addr: Ipv4Addr,
+        generic_trap: GenericTrap,
+        specific_trap: u32,
+        time_stamp: u32,
+        bindings: VarBindList,
+    ) -> Self {
+        TrapV1Pdu {
+            enterprise: enterprise,
+            agent_addr: NetworkAddress(agent_addr),
+            generic_trap: generic_trap,
+            specific_trap: specific_trap,
+            time_stamp: TimeStamp(time_stamp),
+            variable_bindings: bindings,
+        }
+    }
+
+    pub fn enterprise(&self) -> &ObjectIdentifier {
+        &self.enterprise
+    }
+
+    pub fn set_enterprise(&mut self, value: ObjectIdentifier) {
+        self.enterprise = value
+    }
+
+    pub fn agent_addr(&self) -> Ipv4Addr {
+        self.agent_addr.0
+    }
+
+    pub fn set_agent_addr(&mut self, value: Ipv4Addr) {
+        self.agent_addr.0 = value
+    }
+
+    pub fn generic_trap(&self) -> GenericTrap {
+        self.generic_trap
+    }
+
+    pub fn set_generic_trap(&mut self, value: GenericTrap) {
+        self.generic_trap = value
+    }
+
+    pub fn specific_trap(&self) -> u32 {
+        self.specific_trap
+    }
+
+    pub fn set_specific_trap(&mut self, value: u32) {
+        self.specific_trap = value
+    }
+
+    pub fn time_stamp(&self) -> u32 {
+        self.time_stamp.0
+    }
+
+    pub fn set_time_stamp(&mut self, value: u32) {
+        self.time_stamp.0 = value
+    }
+
+    pub fn binds(&self) -> &VarBindList {
+        &self.variable_bindings
+    }
+
+    pub fn binds_mut(&mut self) -> &mut VarBindList {
+        &mut self.variable_bindings
+    }
+
+    pub fn set_binds(&mut self, value: VarBindList) {
+        self.variable_bindings = value
+    }
+
+    pub fn into_binds(self) -> VarBindList {
+        self.variable_bindings
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
@@ -316,25 +720,34 @@ pub enum Pdu {
     GetNextRequest(InnerPdu),
     GetResponse(InnerPdu),
     SetRequest(InnerPdu),
-    GetBulkRequest(InnerPdu),
+    GetBulkRequest(BulkPdu),
     Inform(InnerPdu),
+    /// SNMPv2-Trap-PDU.
     Trap(InnerPdu),
     Report(InnerPdu),
+    /// RFC 1157 Trap-PDU.
+    TrapV1(TrapV1Pdu),
 }
 
 asn1_typed!(Pdu, "PDU");
 
 impl Pdu {
+    /// For `RequestType::GetBulk` error status and index are taken as non-repeaters
+    /// and max-repetitions, prefer `new_bulk_request` for that.
     pub fn new_request(request_type: RequestType, request_id: u32, error_status: u32, error_index: u32, var_binds: VarBindList) -> Self {
-        let pdu = InnerPdu::new(request_id, error_status, error_index, var_binds);
+        let inner = |var_binds| InnerPdu::new(request_id, error_status.into(), error_index, var_binds);
         match request_type {
-            RequestType::Get => Pdu::GetRequest(pdu),
-            RequestType::GetNext => Pdu::GetNextRequest(pdu),
-            RequestType::Set => Pdu::SetRequest(pdu),
-            RequestType::GetBulk => Pdu::GetBulkRequest(pdu),
+            RequestType::Get => Pdu::GetRequest(inner(var_binds)),
+            RequestType::GetNext => Pdu::GetNextRequest(inner(var_binds)),
+            RequestType::Set => Pdu::SetRequest(inner(var_binds)),
+            RequestType::GetBulk => Self::new_bulk_request(request_id, error_status, error_index, var_binds),
         }
     }
 
+    pub fn new_bulk_request(request_id: u32, non_repeaters: u32, max_repetitions: u32, var_binds: VarBindList) -> Self {
+        Pdu::GetBulkRequest(BulkPdu::new(request_id, non_repeaters, max_repetitions, var_binds))
+    }
+
     pub fn new_empty_request(request_type: RequestType) -> Pdu {
         Self::new_request(request_type, 0, 0, 0, Vec::new())
     }
@@ -364,6 +777,36 @@ impl Pdu {
     pub fn is_response_type(&self) -> bool {
         self.response_type().is_some()
     }
+
+    /// Request ID, absent only in v1 Trap-PDU.
+    pub fn request_id(&self) -> Option<u32> {
+        match self {
+            &Pdu::GetRequest(ref pdu) |
+            &Pdu::GetNextRequest(ref pdu) |
+            &Pdu::GetResponse(ref pdu) |
+            &Pdu::SetRequest(ref pdu) |
+            &Pdu::Inform(ref pdu) |
+            &Pdu::Trap(ref pdu) |
+            &Pdu::Report(ref pdu) => Some(pdu.request_id()),
+            &Pdu::GetBulkRequest(ref pdu) => Some(pdu.request_id()),
+            &Pdu::TrapV1(_) => None,
+        }
+    }
+
+    /// Has no effect on v1 Trap-PDU.
+    pub fn set_request_id(&mut self, value: u32) {
+        match self {
+            &mut Pdu::GetRequest(ref mut pdu) |
+            &mut Pdu::GetNextRequest(ref mut pdu) |
+            &mut Pdu::GetResponse(ref mut pdu) |
+            &mut Pdu::SetRequest(ref mut pdu) |
+            &mut Pdu::Inform(ref mut pdu) |
+            &mut Pdu::Trap(ref mut pdu) |
+            &mut Pdu::Report(ref mut pdu) => pdu.set_request_id(value),
+            &mut Pdu::GetBulkRequest(ref mut pdu) => pdu.set_request_id(value),
+            &mut Pdu::TrapV1(_) => {}
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -430,11 +873,35 @@ impl PacketV2 {
 pub struct MessageID(u32);
 asn1_newtype!(MessageID ::= u32);
 
+impl From<u32> for MessageID {
+    fn from(v: u32) -> Self {
+        MessageID(v)
+    }
+}
+
+impl From<MessageID> for u32 {
+    fn from(v: MessageID) -> Self {
+        v.0
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct MaxSize(u32);
 asn1_newtype!(MaxSize ::= u32);
 
+impl From<u32> for MaxSize {
+    fn from(v: u32) -> Self {
+        MaxSize(v)
+    }
+}
+
+impl From<MaxSize> for u32 {
+    fn from(v: MaxSize) -> Self {
+        v.0
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
@@ -452,6 +919,23 @@ pub struct MessageFlags {
 }
 asn1_info!(MessageFlags => UNIVERSAL 0x04, "Message Flags");
 
+impl MessageFlags {
+    pub fn new(reportable: bool, security_level: SecurityLevel) -> Self {
+        MessageFlags {
+            reportable,
+            security_level,
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.security_level != SecurityLevel::NoAuthNoPriv
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.security_level == SecurityLevel::AuthPriv
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub enum SecurityModel {
@@ -464,31 +948,103 @@ asn1_info!(SecurityModel => UNIVERSAL 0x02, "Security Model");
 pub struct AuthenticationParameter(OctetString);
 asn1_newtype!(AuthenticationParameter ::= OctetString);
 
+impl AuthenticationParameter {
+    pub fn new(value: Vec<u8>) -> Self {
+        AuthenticationParameter(OctetString::from(value))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct PrivacyParameter(OctetString);
 asn1_newtype!(PrivacyParameter ::= OctetString);
 
+impl PrivacyParameter {
+    pub fn new(value: Vec<u8>) -> Self {
+        PrivacyParameter(OctetString::from(value))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct EngineID(OctetString);
 asn1_newtype!(EngineID ::= OctetString);
 
+impl EngineID {
+    pub fn new(value: Vec<u8>) -> Self {
+        EngineID(OctetString::from(value))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_bytes().is_empty()
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct EngineTime(u32);
 asn1_newtype!(EngineTime ::= u32);
 
+impl From<u32> for EngineTime {
+    fn from(v: u32) -> Self {
+        EngineTime(v)
+    }
+}
+
+impl From<EngineTime> for u32 {
+    fn from(v: EngineTime) -> Self {
+        v.0
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct EngineBootCount(u32);
 asn1_newtype!(EngineBootCount ::= u32);
 
+impl From<u32> for EngineBootCount {
+    fn from(v: u32) -> Self {
+        EngineBootCount(v)
+    }
+}
+
+impl From<EngineBootCount> for u32 {
+    fn from(v: EngineBootCount) -> Self {
+        v.0
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct UserName(OctetString);
 asn1_newtype!(UserName ::= OctetString);
 
+impl UserName {
+    pub fn new(name: &str) -> Self {
+        UserName(OctetString::from_str(name))
+    }
+
+    pub fn from_bytes(name: Vec<u8>) -> Self {
+        UserName(OctetString::from(name))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct SecurityParameter {
@@ -499,25 +1055,172 @@ pub struct SecurityParameter {
     auth_parameters: AuthenticationParameter,
     privacy_parameters: PrivacyParameter,
 }
-asn1_seq!(SecurityParameter: "Security Parameters",
-    engine_id;
-    engine_boots;
-    engine_time;
-    user_name;
-    auth_parameters;
-    privacy_parameters
-);
+// `msgSecurityParameters` is an OCTET STRING holding BER encoded `UsmSecurityParameters`.
+asn1_info!(SecurityParameter => UNIVERSAL 0x04, "Security Parameters");
+
+impl SecurityParameter {
+    pub fn new(
+        engine_id: EngineID,
+        engine_boots: u32,
+        engine_time: u32,
+        user_name: UserName,
+        auth_parameters: AuthenticationParameter,
+        privacy_parameters: PrivacyParameter,
+    ) -> Self {
+        SecurityParameter {
+            engine_id,
+            engine_boots: engine_boots.into(),
+            engine_time: engine_time.into(),
+            user_name,
+            auth_parameters,
+            privacy_parameters,
+        }
+    }
+
+    /// Parameters of the initial discovery request: everything is left empty.
+    pub fn new_discovery() -> Self {
+        Self::new(
+            EngineID::new(Vec::new()),
+            0,
+            0,
+            UserName::new(""),
+            AuthenticationParameter::new(Vec::new()),
+            PrivacyParameter::new(Vec::new()),
+        )
+    }
+
+    pub fn engine_id(&self) -> &EngineID {
+        &self.engine_id
+    }
+
+    pub fn set_engine_id(&mut self, value: EngineID) {
+        self.engine_id = value
+    }
+
+    pub fn engine_boots(&self) -> u32 {
+        self.engine_boots.0
+    }
+
+    pub fn set_engine_boots(&mut self, value: u32) {
+        self.engine_boots.0 = value
+    }
+
+    pub fn engine_time(&self) -> u32 {
+        self.engine_time.0
+    }
+
+    pub fn set_engine_time(&mut self, value: u32) {
+        self.engine_time.0 = value
+    }
+
+    pub fn user_name(&self) -> &UserName {
+        &self.user_name
+    }
+
+    pub fn set_user_name(&mut self, value: UserName) {
+        self.user_name = value
+    }
+
+    pub fn auth_parameters(&self) -> &AuthenticationParameter {
+        &self.auth_parameters
+    }
+
+    pub fn set_auth_parameters(&mut self, value: AuthenticationParameter) {
+        self.auth_parameters = value
+    }
+
+    pub fn privacy_parameters(&self) -> &PrivacyParameter {
+        &self.privacy_parameters
+    }
+
+    pub fn set_privacy_parameters(&mut self, value: PrivacyParameter) {
+        self.privacy_parameters = value
+    }
+
+    /// BER encoding of RFC 3414 `UsmSecurityParameters` SEQUENCE,
+    /// i.e. contents of the `msgSecurityParameters` OCTET STRING.
+    pub fn to_usm_bytes(&self) -> Vec<u8> {
+        let mut content = Vec::new();
+        ber::write_tlv(&mut content, ber::TAG_OCTET_STRING, self.engine_id().as_bytes());
+        ber::write_integer(&mut content, ber::TAG_INTEGER, self.engine_boots() as i64);
+        ber::write_integer(&mut content, ber::TAG_INTEGER, self.engine_time() as i64);
+        ber::write_tlv(&mut content, ber::TAG_OCTET_STRING, self.user_name().as_bytes());
+        ber::write_tlv(&mut content, ber::TAG_OCTET_STRING, self.auth_parameters().as_bytes());
+        ber::write_tlv(&mut content, ber::TAG_OCTET_STRING, self.privacy_parameters().as_bytes());
+
+        let mut out = Vec::with_capacity(content.len() + 4);
+        ber::write_tlv(&mut out, ber::TAG_SEQUENCE, &content);
+        out
+    }
+
+    /// Parses `UsmSecurityParameters` SEQUENCE from the contents of `msgSecurityParameters`.
+    pub fn from_usm_bytes(buf: &[u8]) -> Option<SecurityParameter> {
+        fn read_u32(r: &mut ber::Reader) -> Option<u32> {
+            let v = ber::decode_integer(r.expect(ber::TAG_INTEGER)?.value)?;
+            if v < 0 || v > i32::max_value() as i64 {
+                return None;
+            }
+            Some(v as u32)
+        }
+
+        let mut outer = ber::Reader::new(buf);
+        let seq = outer.expect(ber::TAG_SEQUENCE)?;
+        if !outer.is_empty() {
+            return None;
+        }
+
+        let mut r = seq.reader();
+        let engine_id = r.expect(ber::TAG_OCTET_STRING)?.value.to_vec();
+        let engine_boots = read_u32(&mut r)?;
+        let engine_time = read_u32(&mut r)?;
+        let user_name = r.expect(ber::TAG_OCTET_STRING)?.value.to_vec();
+        let auth_parameters = r.expect(ber::TAG_OCTET_STRING)?.value.to_vec();
+        let privacy_parameters = r.expect(ber::TAG_OCTET_STRING)?.value.to_vec();
+        if !r.is_empty() {
+            return None;
+        }
+
+        Some(SecurityParameter::new(
+            EngineID::new(engine_id),
+            engine_boots,
+            engine_time,
+            UserName::from_bytes(user_name),
+            AuthenticationParameter::new(auth_parameters),
+            PrivacyParameter::new(privacy_parameters),
+        ))
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct ContextEngineID(OctetString);
 asn1_newtype!(ContextEngineID ::= OctetString);
 
+impl ContextEngineID {
+    pub fn new(value: Vec<u8>) -> Self {
+        ContextEngineID(OctetString::from(value))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct ContextName(OctetString);
 asn1_newtype!(ContextName ::= OctetString);
 
+impl ContextName {
+    pub fn new(name: &str) -> Self {
+        ContextName(OctetString::from_str(name))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct ScopedPdu {
@@ -531,6 +1234,48 @@ asn1_seq!(ScopedPdu: "Scoped PDU",
     pdu
 );
 
+impl ScopedPdu {
+    pub fn new(context_engine_id: ContextEngineID, context_name: ContextName, pdu: Pdu) -> Self {
+        ScopedPdu {
+            context_engine_id,
+            context_name,
+            pdu,
+        }
+    }
+
+    pub fn context_engine_id(&self) -> &ContextEngineID {
+        &self.context_engine_id
+    }
+
+    pub fn set_context_engine_id(&mut self, value: ContextEngineID) {
+        self.context_engine_id = value
+    }
+
+    pub fn context_name(&self) -> &ContextName {
+        &self.context_name
+    }
+
+    pub fn set_context_name(&mut self, value: ContextName) {
+        self.context_name = value
+    }
+
+    pub fn pdu(&self) -> &Pdu {
+        &self.pdu
+    }
+
+    pub fn pdu_mut(&mut self) -> &mut Pdu {
+        &mut self.pdu
+    }
+
+    pub fn set_pdu(&mut self, value: Pdu) {
+        self.pdu = value
+    }
+
+    pub fn into_pdu(self) -> Pdu {
+        self.pdu
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub enum PduV3 {
@@ -539,16 +1284,167 @@ pub enum PduV3 {
 }
 asn1_typed!(PduV3, "PDU V3");
 
+/// `msgGlobalData` of RFC 3412 message.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
-pub(crate) struct PacketV3 {
-    pub version: Version,
-    pub msg_id: MessageID,
-    pub max_size: MaxSize,
-    pub flags: MessageFlags,
-    pub security_model: SecurityModel,
-    pub security_parameters: SecurityParameter,
-    pub pdu: PduV3,
+pub struct HeaderData {
+    msg_id: MessageID,
+    max_size: MaxSize,
+    flags: MessageFlags,
+    security_model: SecurityModel,
+}
+
+asn1_seq!(HeaderData: "SNMP V3 Header Data",
+    msg_id;
+    max_size;
+    flags;
+    security_model
+);
+
+impl HeaderData {
+    pub fn new(msg_id: u32, max_size: u32, flags: MessageFlags, security_model: SecurityModel) -> Self {
+        HeaderData {
+            msg_id: msg_id.into(),
+            max_size: max_size.into(),
+            flags,
+            security_model,
+        }
+    }
+
+    pub fn msg_id(&self) -> u32 {
+        self.msg_id.0
+    }
+
+    pub fn set_msg_id(&mut self, value: u32) {
+        self.msg_id.0 = value
+    }
+
+    pub fn max_size(&self) -> u32 {
+        self.max_size.0
+    }
+
+    pub fn set_max_size(&mut self, value: u32) {
+        self.max_size.0 = value
+    }
+
+    pub fn flags(&self) -> MessageFlags {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, value: MessageFlags) {
+        self.flags = value
+    }
+
+    pub fn security_model(&self) -> SecurityModel {
+        self.security_model
+    }
+
+    pub fn set_security_model(&mut self, value: SecurityModel) {
+        self.security_model = value
+    }
 }
 
-asn1_typed!(PacketV3, "SNMP V3 Packet");
\ No newline at end of file
+/// msgVersion of a v3 message: always 3, decoding fails on any other version.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub(crate) struct MsgVersion;
+
+asn1_alias_info!(MsgVersion ::= u8);
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct PacketV3 {
+    version: MsgVersion,
+    header: HeaderData,
+    security_parameters: SecurityParameter,
+    pdu: PduV3,
+}
+
+asn1_seq!(
+    PacketV3: "SNMP V3 Packet",
+    version;
+    header;
+    security_parameters;
+    pdu
+);
+
+impl PacketV3 {
+    pub fn new(header: HeaderData, security_parameters: SecurityParameter, pdu: PduV3) -> Self {
+        PacketV3 {
+            version: MsgVersion,
+            header,
+            security_parameters,
+            pdu,
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        Version::Version3
+    }
+
+    pub fn header(&self) -> &HeaderData {
+        &self.header
+    }
+
+    pub fn header_mut(&mut self) -> &mut HeaderData {
+        &mut self.header
+    }
+
+    pub fn set_header(&mut self, value: HeaderData) {
+        self.header = value
+    }
+
+    pub fn msg_id(&self) -> u32 {
+        self.header.msg_id()
+    }
+
+    pub fn flags(&self) -> MessageFlags {
+        self.header.flags()
+    }
+
+    pub fn security_parameters(&self) -> &SecurityParameter {
+        &self.security_parameters
+    }
+
+    pub fn security_parameters_mut(&mut self) -> &mut SecurityParameter {
+        &mut self.security_parameters
+    }
+
+    pub fn set_security_parameters(&mut self, value: SecurityParameter) {
+        self.security_parameters = value
+    }
+
+    pub fn pdu(&self) -> &PduV3 {
+        &self.pdu
+    }
+
+    pub fn pdu_mut(&mut self) -> &mut PduV3 {
+        &mut self.pdu
+    }
+
+    pub fn set_pdu(&mut self, value: PduV3) {
+        self.pdu = value
+    }
+
+    pub fn into_pdu(self) -> PduV3 {
+        self.pdu
+    }
+
+    /// Locates contents of `msgAuthenticationParameters` inside a serialized SNMP V3 message,
+    /// so the HMAC can be computed and written in place.
+    pub fn auth_parameters_range(message: &[u8]) -> Option<Range<usize>> {
+        let mut outer = ber::Reader::new(message);
+        let mut msg = outer.expect(ber::TAG_SEQUENCE)?.reader();
+
+        msg.expect(ber::TAG_INTEGER)?;
+        msg.expect(ber::TAG_SEQUENCE)?;
+        let wrapped = msg.expect(ber::TAG_OCTET_STRING)?;
+
+        let mut params = wrapped.reader().expect(ber::TAG_SEQUENCE)?.reader();
+        params.expect(ber::TAG_OCTET_STRING)?;
+        params.expect(ber::TAG_INTEGER)?;
+        params.expect(ber::TAG_INTEGER)?;
+        params.expect(ber::TAG_OCTET_STRING)?;
+        params.expect(ber::TAG_OCTET_STRING).map(|tlv| tlv.range())
+    }
+}